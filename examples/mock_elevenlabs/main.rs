@@ -0,0 +1,29 @@
+//! A tiny stand-in for the ElevenLabs API so the bot can be run and poked at without spending credits.
+//!
+//! Run it with `cargo run --example mock_elevenlabs`, then start the bot with
+//! `ELEVENLABS_API_BASE=http://127.0.0.1:8089/` (and any value for `ELEVENLABS_TOKEN`).
+//!
+//! Set `MOCK_ELEVENLABS_BUSY_REQUESTS=N` to have the first N text-to-speech requests rejected with a 429,
+//! which is handy for checking the client's retry behaviour.
+//!
+//! It also answers OpenAI style `/v1/audio/speech` requests, for trying out the OpenAI-compatible provider with
+//! `OPENAI_TTS_API_BASE=http://127.0.0.1:8089/`. Set `MOCK_OPENAI_API_KEY` to require that key as a bearer token.
+
+mod server;
+
+use ::log::info;
+
+const ADDR_ENV: &str = "MOCK_ELEVENLABS_ADDR";
+const DEFAULT_ADDR: &str = "127.0.0.1:8089";
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    env_logger::init();
+
+    let addr = std::env::var(ADDR_ENV).unwrap_or_else(|_| DEFAULT_ADDR.to_string());
+    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    info!(addr = addr.as_str(); "Mock ElevenLabs server listening");
+    axum::serve(listener, server::router()).await?;
+
+    Ok(())
+}
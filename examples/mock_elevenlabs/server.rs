@@ -0,0 +1,1697 @@
+//! The mock's endpoints, shared between the `mock_elevenlabs` example and the tests
+
+use std::collections::HashMap;
+use std::sync::{
+    LazyLock, Mutex,
+    atomic::{AtomicU32, Ordering},
+};
+
+use ::axum::{
+    Json, Router,
+    body::Bytes,
+    extract::{
+        DefaultBodyLimit, Path, Query,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
+};
+use ::base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use ::log::info;
+use ::serde::Deserialize;
+use ::serde_json::json;
+
+const API_KEY_ENV: &str = "MOCK_ELEVENLABS_API_KEY";
+const BUSY_REQUESTS_ENV: &str = "MOCK_ELEVENLABS_BUSY_REQUESTS";
+const OPENAI_API_KEY_ENV: &str = "MOCK_OPENAI_API_KEY";
+// Matches the API's limit for uploaded audio
+const MAX_UPLOAD_SIZE: usize = 50 * 1024 * 1024;
+
+// Mirrors the voices in `KnownVoice`, plus one the bot doesn't know about
+const BUILTIN_VOICES: &[(&str, &str, &str)] = &[
+    ("OzxGhSRE3FmszopZTbZE", "Scotty", "professional"),
+    ("79931Esd1pNmtJORtUBI", "June", "professional"),
+    ("YOq2y2Up4RgXP2HyXjE5", "UnrealTournament", "professional"),
+    ("mockVoice0000000000A", "Mock Narrator", "premade"),
+];
+
+static BUSY_REQUESTS_LEFT: AtomicU32 = AtomicU32::new(0);
+
+struct MockVoice {
+    voice_id: String,
+    name: String,
+    category: String,
+}
+
+// The built-in voices plus any added through the clone endpoint
+static VOICES: LazyLock<Mutex<Vec<MockVoice>>> = LazyLock::new(|| {
+    Mutex::new(
+        BUILTIN_VOICES
+            .iter()
+            .map(|(voice_id, name, category)| MockVoice {
+                voice_id: voice_id.to_string(),
+                name: name.to_string(),
+                category: category.to_string(),
+            })
+            .collect(),
+    )
+});
+static NEXT_VOICE_ID: AtomicU32 = AtomicU32::new(1);
+
+// Settings saved through the edit endpoint, by voice ID. Voices without an entry use the defaults.
+static VOICE_SETTINGS: LazyLock<Mutex<HashMap<String, serde_json::Value>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+struct HistoryEntry {
+    id: String,
+    request_id: String,
+    voice_id: String,
+    text: String,
+    date_unix: i64,
+    content_type: &'static str,
+    audio: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct PronunciationRule {
+    #[serde(rename = "type")]
+    rule_type: String,
+    string_to_replace: String,
+    alias: Option<String>,
+    phoneme: Option<String>,
+    alphabet: Option<String>,
+}
+
+struct PronunciationDictionary {
+    id: String,
+    name: String,
+    description: Option<String>,
+    created_unix: i64,
+    /// Every version's ID and rules, oldest first
+    versions: Vec<(String, Vec<PronunciationRule>)>,
+}
+
+impl PronunciationDictionary {
+    fn latest(&self) -> &(String, Vec<PronunciationRule>) {
+        self.versions
+            .last()
+            .expect("dictionaries have at least one version")
+    }
+
+    fn add_version(&mut self, rules: Vec<PronunciationRule>) -> Response {
+        let version_id = format!(
+            "mockVersion{:09}",
+            NEXT_DICTIONARY_ID.fetch_add(1, Ordering::SeqCst)
+        );
+        self.versions.push((version_id, rules));
+        self.version_response()
+    }
+
+    fn version_response(&self) -> Response {
+        let (version_id, rules) = self.latest();
+        Json(json!({
+            "id": self.id,
+            "name": self.name,
+            "version_id": version_id,
+            "version_rules_num": rules.len(),
+        }))
+        .into_response()
+    }
+}
+
+static PRONUNCIATION_DICTIONARIES: LazyLock<Mutex<Vec<PronunciationDictionary>>> =
+    LazyLock::new(|| Mutex::new(Vec::new()));
+static NEXT_DICTIONARY_ID: AtomicU32 = AtomicU32::new(1);
+
+// Every generation so far, oldest first
+static HISTORY: LazyLock<Mutex<Vec<HistoryEntry>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+static NEXT_HISTORY_ID: AtomicU32 = AtomicU32::new(1);
+
+/// Every endpoint the mock answers. The first `MOCK_ELEVENLABS_BUSY_REQUESTS` text-to-speech requests are
+/// rejected with a 429.
+pub fn router() -> Router {
+    if let Some(busy) = std::env::var(BUSY_REQUESTS_ENV)
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+    {
+        BUSY_REQUESTS_LEFT.store(busy, Ordering::SeqCst);
+    }
+
+    Router::new()
+        .route("/v1/user", get(get_user))
+        .route("/v1/models", get(get_models))
+        .route("/v1/usage/character-stats", get(get_character_stats))
+        .route("/v2/voices", get(get_voices))
+        .route("/v1/voices/add", post(add_voice))
+        .route("/v1/voices/{voice_id}", delete(delete_voice))
+        .route(
+            "/v1/voices/settings/default",
+            get(get_default_voice_settings),
+        )
+        .route("/v1/voices/{voice_id}/settings", get(get_voice_settings))
+        .route(
+            "/v1/voices/{voice_id}/settings/edit",
+            post(edit_voice_settings),
+        )
+        .route(
+            "/v1/pronunciation-dictionaries",
+            get(get_pronunciation_dictionaries),
+        )
+        .route(
+            "/v1/pronunciation-dictionaries/add-from-rules",
+            post(create_pronunciation_dictionary),
+        )
+        .route(
+            "/v1/pronunciation-dictionaries/{dictionary_id}/add-rules",
+            post(add_pronunciation_rules),
+        )
+        .route(
+            "/v1/pronunciation-dictionaries/{dictionary_id}/remove-rules",
+            post(remove_pronunciation_rules),
+        )
+        .route(
+            "/v1/pronunciation-dictionaries/{dictionary_id}/{version_id}/download",
+            get(download_pronunciation_dictionary),
+        )
+        .route("/v1/history", get(get_history))
+        .route("/v1/history/download", post(download_history))
+        .route(
+            "/v1/history/{history_item_id}",
+            get(get_history_item).delete(delete_history_item),
+        )
+        .route(
+            "/v1/history/{history_item_id}/audio",
+            get(get_history_item_audio),
+        )
+        .route("/v1/sound-generation", post(sound_generation))
+        .route("/v1/speech-to-speech/{voice_id}", post(speech_to_speech))
+        .route("/v1/speech-to-text", post(speech_to_text))
+        .route("/v1/text-to-dialogue", post(text_to_dialogue))
+        .route("/v1/text-to-speech/{voice_id}", post(text_to_speech))
+        .route("/v1/text-to-speech/{voice_id}/stream", post(text_to_speech))
+        .route(
+            "/v1/text-to-speech/{voice_id}/with-timestamps",
+            post(text_to_speech_with_timestamps),
+        )
+        .route(
+            "/v1/text-to-speech/{voice_id}/stream-input",
+            get(text_to_speech_input_stream),
+        )
+        .route("/v1/audio/speech", post(openai_speech))
+        // Uploads can be much bigger than axum's default limit of 2MB
+        .layer(DefaultBodyLimit::max(MAX_UPLOAD_SIZE))
+}
+
+fn error_response(status: StatusCode, detail_status: &str, message: &str) -> Response {
+    (
+        status,
+        Json(json!({ "detail": { "status": detail_status, "message": message } })),
+    )
+        .into_response()
+}
+
+/// Returns the rejection response if the request's API key is wrong.
+/// Only enforced if `MOCK_ELEVENLABS_API_KEY` is set, otherwise any (or no) key is accepted.
+fn reject_api_key(headers: &HeaderMap) -> Option<Response> {
+    let expected = std::env::var(API_KEY_ENV).ok()?;
+    match headers.get("xi-api-key").and_then(|v| v.to_str().ok()) {
+        Some(key) if key == expected => None,
+        _ => Some(error_response(
+            StatusCode::UNAUTHORIZED,
+            "invalid_api_key",
+            "Invalid API key",
+        )),
+    }
+}
+
+async fn get_user(headers: HeaderMap) -> Response {
+    if let Some(resp) = reject_api_key(&headers) {
+        return resp;
+    }
+    let reset = chrono::Utc::now() + chrono::Duration::days(14);
+    Json(json!({
+        "user_id": "mock-user",
+        "subscription": {
+            "character_count": 1234,
+            "character_limit": 100000,
+            "next_character_count_reset_unix": reset.timestamp(),
+        }
+    }))
+    .into_response()
+}
+
+fn mock_model(
+    model_id: &str,
+    name: &str,
+    can_do_text_to_speech: bool,
+    max_characters: u32,
+    cost: f32,
+    languages: &[(&str, &str)],
+) -> serde_json::Value {
+    json!({
+        "model_id": model_id,
+        "name": name,
+        "description": format!("Mock {}", name),
+        "can_do_text_to_speech": can_do_text_to_speech,
+        "can_do_voice_conversion": !can_do_text_to_speech,
+        "can_use_style": true,
+        "can_use_speaker_boost": true,
+        "token_cost_factor": cost,
+        "max_characters_request_free_user": max_characters / 2,
+        "max_characters_request_subscribed_user": max_characters,
+        "maximum_text_length_per_request": max_characters,
+        "languages": languages
+            .iter()
+            .map(|(id, name)| json!({ "language_id": id, "name": name }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+async fn get_models(headers: HeaderMap) -> Response {
+    if let Some(resp) = reject_api_key(&headers) {
+        return resp;
+    }
+
+    let languages = [("en", "English"), ("de", "German"), ("fr", "French")];
+    // Includes models the bot has no built-in knowledge of, and one that can't do text to speech
+    Json(json!([
+        mock_model("eleven_v3", "Eleven v3", true, 3000, 1.0, &languages),
+        mock_model(
+            "eleven_multilingual_v2",
+            "Eleven Multilingual v2",
+            true,
+            10000,
+            1.0,
+            &languages
+        ),
+        mock_model(
+            "eleven_turbo_v2",
+            "Eleven Turbo v2",
+            true,
+            30000,
+            0.5,
+            &languages[..1]
+        ),
+        mock_model(
+            "eleven_flash_v2_5",
+            "Eleven Flash v2.5",
+            true,
+            40000,
+            0.5,
+            &languages
+        ),
+        mock_model(
+            "eleven_english_sts_v2",
+            "Eleven English v2",
+            false,
+            10000,
+            1.0,
+            &languages[..1]
+        ),
+    ]))
+    .into_response()
+}
+
+#[derive(Debug, Deserialize)]
+struct CharacterStatsQuery {
+    start_unix: i64,
+    end_unix: i64,
+    breakdown_type: Option<String>,
+    aggregation_interval: Option<String>,
+}
+
+const MILLIS_PER_DAY: i64 = 24 * 60 * 60 * 1000;
+
+/// Daily character counts worked out from the generation history
+async fn get_character_stats(
+    headers: HeaderMap,
+    Query(query): Query<CharacterStatsQuery>,
+) -> Response {
+    if let Some(resp) = reject_api_key(&headers) {
+        return resp;
+    }
+    if query.aggregation_interval.as_deref().unwrap_or("day") != "day" {
+        return invalid_form("The mock only aggregates by day");
+    }
+    if query.end_unix < query.start_unix {
+        return invalid_form("end_unix must be after start_unix");
+    }
+
+    let first_day = query.start_unix.div_euclid(MILLIS_PER_DAY);
+    let last_day = query.end_unix.div_euclid(MILLIS_PER_DAY);
+    let time: Vec<i64> = (first_day..=last_day).map(|d| d * MILLIS_PER_DAY).collect();
+
+    let breakdown_type = query.breakdown_type.as_deref().unwrap_or("none");
+    let mut usage: HashMap<String, Vec<f64>> = HashMap::new();
+    if breakdown_type == "none" {
+        usage.insert("All".to_string(), vec![0.0; time.len()]);
+    }
+    for entry in HISTORY.lock().unwrap().iter() {
+        let millis = entry.date_unix * 1000;
+        if millis < query.start_unix || millis > query.end_unix {
+            continue;
+        }
+        let key = match breakdown_type {
+            "none" => "All".to_string(),
+            "voice" => entry.voice_id.clone(),
+            // History doesn't record the model, so it's all put down to the default
+            "model" => "eleven_multilingual_v2".to_string(),
+            other => {
+                return invalid_form(&format!("Unsupported breakdown_type {}", other));
+            }
+        };
+        let day = (millis.div_euclid(MILLIS_PER_DAY) - first_day) as usize;
+        usage.entry(key).or_insert_with(|| vec![0.0; time.len()])[day] +=
+            entry.text.chars().count() as f64;
+    }
+
+    Json(json!({ "time": time, "usage": usage })).into_response()
+}
+
+#[derive(Debug, Deserialize)]
+struct VoicesQuery {
+    page_size: Option<usize>,
+    next_page_token: Option<String>,
+    search: Option<String>,
+    category: Option<String>,
+}
+
+async fn get_voices(headers: HeaderMap, Query(query): Query<VoicesQuery>) -> Response {
+    if let Some(resp) = reject_api_key(&headers) {
+        return resp;
+    }
+
+    let search = query.search.map(|s| s.to_lowercase());
+    let voices = VOICES.lock().unwrap();
+    let matching: Vec<_> = voices
+        .iter()
+        .filter(|v| {
+            search
+                .as_ref()
+                .is_none_or(|s| v.name.to_lowercase().contains(s))
+                && query.category.as_ref().is_none_or(|c| *c == v.category)
+        })
+        .collect();
+
+    // Page tokens are just the index of the next voice
+    let start = query
+        .next_page_token
+        .and_then(|t| t.parse::<usize>().ok())
+        .unwrap_or(0);
+    let end = (start + query.page_size.unwrap_or(10)).min(matching.len());
+    let voices: Vec<_> = matching[start.min(end)..end]
+        .iter()
+        .map(|v| {
+            json!({
+                "voice_id": v.voice_id,
+                "name": v.name,
+                "category": v.category,
+                "description": format!("Mock voice {}", v.name),
+                "labels": { "accent": "mock", "use_case": "announcer" },
+                "preview_url": format!("https://example.com/previews/{}.mp3", v.voice_id),
+            })
+        })
+        .collect();
+    let has_more = end < matching.len();
+
+    Json(json!({
+        "voices": voices,
+        "has_more": has_more,
+        "total_count": matching.len(),
+        "next_page_token": has_more.then(|| end.to_string()),
+    }))
+    .into_response()
+}
+
+fn default_voice_settings() -> serde_json::Value {
+    json!({
+        "stability": 0.5,
+        "similarity_boost": 0.75,
+        "style": 0.0,
+        "use_speaker_boost": true,
+        "speed": 1.0,
+    })
+}
+
+fn is_known_voice(voice_id: &str) -> bool {
+    get_voice_name(voice_id).is_some()
+}
+
+fn get_voice_name(voice_id: &str) -> Option<String> {
+    VOICES
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|v| v.voice_id == voice_id)
+        .map(|v| v.name.clone())
+}
+
+fn voice_not_found(voice_id: &str) -> Response {
+    error_response(
+        StatusCode::NOT_FOUND,
+        "voice_not_found",
+        &format!("A voice with the voice_id {} was not found.", voice_id),
+    )
+}
+
+async fn add_voice(headers: HeaderMap, body: Bytes) -> Response {
+    if let Some(resp) = reject_api_key(&headers) {
+        return resp;
+    }
+    let Some(parts) = parse_multipart_parts(&headers, &body) else {
+        return invalid_form("Expected a multipart/form-data body");
+    };
+    let field_text = |name: &str| {
+        parts
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, f)| String::from_utf8_lossy(&f.data).to_string())
+    };
+    let Some(name) = field_text("name").filter(|n| !n.trim().is_empty()) else {
+        return invalid_form("The name field is required");
+    };
+    let files: Vec<_> = parts
+        .iter()
+        .filter(|(n, f)| n == "files" && !f.data.is_empty())
+        .map(|(_, f)| f)
+        .collect();
+    if files.is_empty() {
+        return invalid_form("At least one file is required");
+    }
+    if let Some(labels) = field_text("labels")
+        && serde_json::from_str::<HashMap<String, String>>(&labels).is_err()
+    {
+        return invalid_form("labels must be a JSON object of strings");
+    }
+
+    let voice_id = format!(
+        "mockClone{:011}",
+        NEXT_VOICE_ID.fetch_add(1, Ordering::SeqCst)
+    );
+    info!(
+        voice_id = voice_id.as_str(),
+        name = name.as_str(),
+        files = files.len(),
+        size = files.iter().map(|f| f.data.len()).sum::<usize>(),
+        labels = field_text("labels").as_deref().unwrap_or("{}");
+        "Mock voice clone request"
+    );
+    VOICES.lock().unwrap().push(MockVoice {
+        voice_id: voice_id.clone(),
+        name,
+        category: "cloned".to_string(),
+    });
+
+    Json(json!({
+        "voice_id": voice_id,
+        "requires_verification": false,
+    }))
+    .into_response()
+}
+
+async fn delete_voice(headers: HeaderMap, Path(voice_id): Path<String>) -> Response {
+    if let Some(resp) = reject_api_key(&headers) {
+        return resp;
+    }
+
+    let mut voices = VOICES.lock().unwrap();
+    match voices.iter().position(|v| v.voice_id == voice_id) {
+        Some(i) => {
+            voices.remove(i);
+            VOICE_SETTINGS.lock().unwrap().remove(&voice_id);
+            Json(json!({ "status": "ok" })).into_response()
+        }
+        None => voice_not_found(&voice_id),
+    }
+}
+
+async fn get_default_voice_settings(headers: HeaderMap) -> Response {
+    if let Some(resp) = reject_api_key(&headers) {
+        return resp;
+    }
+    Json(default_voice_settings()).into_response()
+}
+
+async fn get_voice_settings(headers: HeaderMap, Path(voice_id): Path<String>) -> Response {
+    if let Some(resp) = reject_api_key(&headers) {
+        return resp;
+    }
+    if !is_known_voice(&voice_id) {
+        return voice_not_found(&voice_id);
+    }
+
+    let settings = VOICE_SETTINGS
+        .lock()
+        .unwrap()
+        .get(&voice_id)
+        .cloned()
+        .unwrap_or_else(default_voice_settings);
+    Json(settings).into_response()
+}
+
+async fn edit_voice_settings(
+    headers: HeaderMap,
+    Path(voice_id): Path<String>,
+    Json(body): Json<serde_json::Map<String, serde_json::Value>>,
+) -> Response {
+    if let Some(resp) = reject_api_key(&headers) {
+        return resp;
+    }
+    if !is_known_voice(&voice_id) {
+        return voice_not_found(&voice_id);
+    }
+
+    let mut all_settings = VOICE_SETTINGS.lock().unwrap();
+    let settings = all_settings
+        .entry(voice_id.clone())
+        .or_insert_with(default_voice_settings);
+    // Only the fields that were sent change
+    for (key, value) in body {
+        if !value.is_null() {
+            settings[key] = value;
+        }
+    }
+    info!(voice_id = voice_id.as_str(); "Saved voice settings {}", settings);
+
+    Json(json!({ "status": "ok" })).into_response()
+}
+
+fn dictionary_not_found(dictionary_id: &str) -> Response {
+    error_response(
+        StatusCode::NOT_FOUND,
+        "pronunciation_dictionary_not_found",
+        &format!(
+            "A pronunciation dictionary with the id {} was not found.",
+            dictionary_id
+        ),
+    )
+}
+
+/// Returns the error for the first rule that's missing what its type needs
+fn reject_pronunciation_rules(rules: &[PronunciationRule]) -> Option<Response> {
+    let invalid = rules.iter().find(|r| match r.rule_type.as_str() {
+        "alias" => r.alias.is_none(),
+        "phoneme" => {
+            r.phoneme.is_none()
+                || !matches!(r.alphabet.as_deref(), Some("ipa") | Some("cmu-arpabet"))
+        }
+        _ => true,
+    })?;
+    Some(invalid_form(&format!(
+        "Invalid rule for \"{}\"",
+        invalid.string_to_replace
+    )))
+}
+
+#[derive(Debug, Deserialize)]
+struct DictionaryListQuery {
+    cursor: Option<String>,
+    page_size: Option<usize>,
+}
+
+async fn get_pronunciation_dictionaries(
+    headers: HeaderMap,
+    Query(query): Query<DictionaryListQuery>,
+) -> Response {
+    if let Some(resp) = reject_api_key(&headers) {
+        return resp;
+    }
+
+    let dictionaries = PRONUNCIATION_DICTIONARIES.lock().unwrap();
+    // Cursors are just the index of the next dictionary
+    let start = query
+        .cursor
+        .and_then(|c| c.parse::<usize>().ok())
+        .unwrap_or(0);
+    let end = (start + query.page_size.unwrap_or(30)).min(dictionaries.len());
+    let page: Vec<_> = dictionaries[start.min(end)..end]
+        .iter()
+        .map(|d| {
+            let (version_id, rules) = d.latest();
+            json!({
+                "id": d.id,
+                "name": d.name,
+                "description": d.description,
+                "latest_version_id": version_id,
+                "latest_version_rules_num": rules.len(),
+                "created_by": "mock",
+                "creation_time_unix": d.created_unix,
+            })
+        })
+        .collect();
+    let has_more = end < dictionaries.len();
+
+    Json(json!({
+        "pronunciation_dictionaries": page,
+        "has_more": has_more,
+        "next_cursor": has_more.then(|| end.to_string()),
+    }))
+    .into_response()
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateDictionaryBody {
+    name: String,
+    rules: Vec<PronunciationRule>,
+    description: Option<String>,
+}
+
+async fn create_pronunciation_dictionary(
+    headers: HeaderMap,
+    Json(body): Json<CreateDictionaryBody>,
+) -> Response {
+    if let Some(resp) = reject_api_key(&headers) {
+        return resp;
+    }
+    if let Some(resp) = reject_pronunciation_rules(&body.rules) {
+        return resp;
+    }
+
+    let mut dictionary = PronunciationDictionary {
+        id: format!(
+            "mockDictionary{:06}",
+            NEXT_DICTIONARY_ID.fetch_add(1, Ordering::SeqCst)
+        ),
+        name: body.name,
+        description: body.description,
+        created_unix: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0),
+        versions: Vec::new(),
+    };
+    info!(
+        id = dictionary.id.as_str(),
+        name = dictionary.name.as_str(),
+        rules = body.rules.len();
+        "Mock pronunciation dictionary created"
+    );
+    let resp = dictionary.add_version(body.rules);
+    PRONUNCIATION_DICTIONARIES.lock().unwrap().push(dictionary);
+    resp
+}
+
+#[derive(Debug, Deserialize)]
+struct AddRulesBody {
+    rules: Vec<PronunciationRule>,
+}
+
+async fn add_pronunciation_rules(
+    headers: HeaderMap,
+    Path(dictionary_id): Path<String>,
+    Json(body): Json<AddRulesBody>,
+) -> Response {
+    if let Some(resp) = reject_api_key(&headers) {
+        return resp;
+    }
+    if let Some(resp) = reject_pronunciation_rules(&body.rules) {
+        return resp;
+    }
+
+    let mut dictionaries = PRONUNCIATION_DICTIONARIES.lock().unwrap();
+    let Some(dictionary) = dictionaries.iter_mut().find(|d| d.id == dictionary_id) else {
+        return dictionary_not_found(&dictionary_id);
+    };
+    // New rules replace any for the same string
+    let mut rules = dictionary.latest().1.clone();
+    rules.retain(|r| {
+        !body
+            .rules
+            .iter()
+            .any(|n| n.string_to_replace == r.string_to_replace)
+    });
+    rules.extend(body.rules);
+    dictionary.add_version(rules)
+}
+
+#[derive(Debug, Deserialize)]
+struct RemoveRulesBody {
+    rule_strings: Vec<String>,
+}
+
+async fn remove_pronunciation_rules(
+    headers: HeaderMap,
+    Path(dictionary_id): Path<String>,
+    Json(body): Json<RemoveRulesBody>,
+) -> Response {
+    if let Some(resp) = reject_api_key(&headers) {
+        return resp;
+    }
+
+    let mut dictionaries = PRONUNCIATION_DICTIONARIES.lock().unwrap();
+    let Some(dictionary) = dictionaries.iter_mut().find(|d| d.id == dictionary_id) else {
+        return dictionary_not_found(&dictionary_id);
+    };
+    let mut rules = dictionary.latest().1.clone();
+    rules.retain(|r| !body.rule_strings.contains(&r.string_to_replace));
+    dictionary.add_version(rules)
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+async fn download_pronunciation_dictionary(
+    headers: HeaderMap,
+    Path((dictionary_id, version_id)): Path<(String, String)>,
+) -> Response {
+    if let Some(resp) = reject_api_key(&headers) {
+        return resp;
+    }
+
+    let dictionaries = PRONUNCIATION_DICTIONARIES.lock().unwrap();
+    let Some(rules) = dictionaries
+        .iter()
+        .find(|d| d.id == dictionary_id)
+        .and_then(|d| d.versions.iter().find(|(v, _)| *v == version_id))
+        .map(|(_, rules)| rules)
+    else {
+        return dictionary_not_found(&dictionary_id);
+    };
+
+    let mut pls = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<lexicon version=\"1.0\" xmlns=\"http://www.w3.org/2005/01/pronunciation-lexicon\" alphabet=\"ipa\" xml:lang=\"en-US\">\n",
+    );
+    for rule in rules {
+        pls.push_str("  <lexeme>\n");
+        pls.push_str(&format!(
+            "    <grapheme>{}</grapheme>\n",
+            escape_xml(&rule.string_to_replace)
+        ));
+        match (&rule.alias, &rule.phoneme) {
+            (Some(alias), _) => {
+                pls.push_str(&format!("    <alias>{}</alias>\n", escape_xml(alias)))
+            }
+            (None, Some(phoneme)) => pls.push_str(&format!(
+                "    <phoneme alphabet=\"{}\">{}</phoneme>\n",
+                if rule.alphabet.as_deref() == Some("cmu-arpabet") {
+                    "x-cmu-arpabet"
+                } else {
+                    "ipa"
+                },
+                escape_xml(phoneme)
+            )),
+            (None, None) => {}
+        }
+        pls.push_str("  </lexeme>\n");
+    }
+    pls.push_str("</lexicon>\n");
+
+    ([(header::CONTENT_TYPE, "application/pls+xml")], pls).into_response()
+}
+
+#[derive(Debug, Deserialize)]
+struct DictionaryLocator {
+    pronunciation_dictionary_id: String,
+    version_id: String,
+}
+
+/// Speech requests can use up to 3 dictionaries, and each has to exist
+fn reject_dictionary_locators(locators: &[DictionaryLocator]) -> Option<Response> {
+    if locators.len() > 3 {
+        return Some(invalid_form(
+            "At most 3 pronunciation dictionaries can be used",
+        ));
+    }
+    let dictionaries = PRONUNCIATION_DICTIONARIES.lock().unwrap();
+    let missing = locators.iter().find(|l| {
+        !dictionaries.iter().any(|d| {
+            d.id == l.pronunciation_dictionary_id
+                && d.versions.iter().any(|(v, _)| *v == l.version_id)
+        })
+    })?;
+    Some(dictionary_not_found(&missing.pronunciation_dictionary_id))
+}
+
+/// Saves a generation, returning its request ID
+fn record_history(voice_id: &str, text: &str, content_type: &'static str, audio: &[u8]) -> String {
+    let number = NEXT_HISTORY_ID.fetch_add(1, Ordering::SeqCst);
+    let request_id = format!("mockRequest{:09}", number);
+    HISTORY.lock().unwrap().push(HistoryEntry {
+        id: format!("mockHistory{:09}", number),
+        request_id: request_id.clone(),
+        voice_id: voice_id.to_string(),
+        text: text.to_string(),
+        date_unix: chrono::Utc::now().timestamp(),
+        content_type,
+        audio: audio.to_vec(),
+    });
+    request_id
+}
+
+fn history_item_not_found(history_item_id: &str) -> Response {
+    error_response(
+        StatusCode::NOT_FOUND,
+        "history_item_not_found",
+        &format!("History item {} was not found.", history_item_id),
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryQuery {
+    page_size: Option<usize>,
+    start_after_history_item_id: Option<String>,
+    voice_id: Option<String>,
+}
+
+async fn get_history(headers: HeaderMap, Query(query): Query<HistoryQuery>) -> Response {
+    if let Some(resp) = reject_api_key(&headers) {
+        return resp;
+    }
+
+    let history = HISTORY.lock().unwrap();
+    let matching: Vec<_> = history
+        .iter()
+        .rev()
+        .filter(|e| query.voice_id.as_ref().is_none_or(|v| *v == e.voice_id))
+        .collect();
+    let start = match &query.start_after_history_item_id {
+        Some(after) => match matching.iter().position(|e| e.id == *after) {
+            Some(i) => i + 1,
+            None => return history_item_not_found(after),
+        },
+        None => 0,
+    };
+    let end = (start + query.page_size.unwrap_or(100)).min(matching.len());
+
+    let items: Vec<_> = matching[start..end]
+        .iter()
+        .map(|e| history_item_json(e))
+        .collect();
+    Json(json!({
+        "history": items,
+        "last_history_item_id": matching[start..end].last().map(|e| e.id.clone()),
+        "has_more": end < matching.len(),
+    }))
+    .into_response()
+}
+
+fn history_item_json(e: &HistoryEntry) -> serde_json::Value {
+    json!({
+        "history_item_id": e.id,
+        "request_id": e.request_id,
+        "voice_id": e.voice_id,
+        "voice_name": get_voice_name(&e.voice_id),
+        "text": e.text,
+        "date_unix": e.date_unix,
+        "character_count_change_from": 1234,
+        "character_count_change_to": 1234 + e.text.chars().count(),
+        "content_type": e.content_type,
+        "state": "created",
+    })
+}
+
+async fn get_history_item(headers: HeaderMap, Path(history_item_id): Path<String>) -> Response {
+    if let Some(resp) = reject_api_key(&headers) {
+        return resp;
+    }
+
+    let history = HISTORY.lock().unwrap();
+    match history.iter().find(|e| e.id == history_item_id) {
+        Some(e) => Json(history_item_json(e)).into_response(),
+        None => history_item_not_found(&history_item_id),
+    }
+}
+
+async fn get_history_item_audio(
+    headers: HeaderMap,
+    Path(history_item_id): Path<String>,
+) -> Response {
+    if let Some(resp) = reject_api_key(&headers) {
+        return resp;
+    }
+
+    let history = HISTORY.lock().unwrap();
+    match history.iter().find(|e| e.id == history_item_id) {
+        Some(e) => ([(header::CONTENT_TYPE, e.content_type)], e.audio.clone()).into_response(),
+        None => history_item_not_found(&history_item_id),
+    }
+}
+
+async fn delete_history_item(headers: HeaderMap, Path(history_item_id): Path<String>) -> Response {
+    if let Some(resp) = reject_api_key(&headers) {
+        return resp;
+    }
+
+    let mut history = HISTORY.lock().unwrap();
+    match history.iter().position(|e| e.id == history_item_id) {
+        Some(i) => {
+            history.remove(i);
+            Json(json!({ "status": "ok" })).into_response()
+        }
+        None => history_item_not_found(&history_item_id),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DownloadHistoryBody {
+    history_item_ids: Vec<String>,
+}
+
+async fn download_history(headers: HeaderMap, Json(body): Json<DownloadHistoryBody>) -> Response {
+    if let Some(resp) = reject_api_key(&headers) {
+        return resp;
+    }
+
+    let history = HISTORY.lock().unwrap();
+    let mut entries = Vec::new();
+    for id in &body.history_item_ids {
+        match history.iter().find(|e| e.id == *id) {
+            Some(e) => entries.push(e),
+            None => return history_item_not_found(id),
+        }
+    }
+
+    // Like the real API: a single item comes back as-is, several as a zip
+    match entries.as_slice() {
+        [] => error_response(
+            StatusCode::BAD_REQUEST,
+            "invalid_request",
+            "No history items given.",
+        ),
+        [entry] => (
+            [(header::CONTENT_TYPE, entry.content_type)],
+            entry.audio.clone(),
+        )
+            .into_response(),
+        _ => {
+            let files: Vec<_> = entries
+                .iter()
+                .map(|e| (format!("{}.mp3", e.id), e.audio.as_slice()))
+                .collect();
+            (
+                [(header::CONTENT_TYPE, "application/zip")],
+                stored_zip(&files),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Builds a zip archive without compression, which is all the mock needs
+fn stored_zip(files: &[(String, &[u8])]) -> Vec<u8> {
+    let mut zip = Vec::new();
+    let mut central_directory = Vec::new();
+
+    for (name, data) in files {
+        let offset = zip.len() as u32;
+        let crc = crc32fast::hash(data);
+        // Version needed, flags, compression (stored), mod time, mod date, CRC, sizes, name length
+        let mut common = Vec::new();
+        common.extend_from_slice(&20u16.to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes());
+        common.extend_from_slice(&0x21u16.to_le_bytes());
+        common.extend_from_slice(&crc.to_le_bytes());
+        common.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        common.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        common.extend_from_slice(&(name.len() as u16).to_le_bytes());
+
+        zip.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        zip.extend_from_slice(&common);
+        zip.extend_from_slice(&0u16.to_le_bytes()); // Extra field length
+        zip.extend_from_slice(name.as_bytes());
+        zip.extend_from_slice(data);
+
+        central_directory.extend_from_slice(&0x02014b50u32.to_le_bytes());
+        central_directory.extend_from_slice(&20u16.to_le_bytes()); // Version made by
+        central_directory.extend_from_slice(&common);
+        // Extra field length, comment length, disk number, internal and external attributes
+        central_directory.extend_from_slice(&[0u8; 12]);
+        central_directory.extend_from_slice(&offset.to_le_bytes());
+        central_directory.extend_from_slice(name.as_bytes());
+    }
+
+    let central_directory_offset = zip.len() as u32;
+    zip.extend_from_slice(&central_directory);
+    zip.extend_from_slice(&0x06054b50u32.to_le_bytes());
+    zip.extend_from_slice(&[0u8; 4]); // Disk numbers
+    zip.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    zip.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    zip.extend_from_slice(&(central_directory.len() as u32).to_le_bytes());
+    zip.extend_from_slice(&central_directory_offset.to_le_bytes());
+    zip.extend_from_slice(&0u16.to_le_bytes()); // Comment length
+    zip
+}
+
+#[derive(Debug, Deserialize)]
+struct SoundEffectBody {
+    text: String,
+    duration_seconds: Option<f32>,
+    prompt_influence: Option<f32>,
+}
+
+async fn sound_generation(
+    headers: HeaderMap,
+    Query(query): Query<SpeechQuery>,
+    Json(body): Json<SoundEffectBody>,
+) -> Response {
+    if let Some(resp) = reject_api_key(&headers) {
+        return resp;
+    }
+    if body.text.trim().is_empty() {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "empty_text",
+            "The sound effect prompt must not be empty.",
+        );
+    }
+    if body
+        .duration_seconds
+        .is_some_and(|d| !(0.5..=30.0).contains(&d))
+    {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "invalid_duration",
+            "Duration must be between 0.5 and 30 seconds.",
+        );
+    }
+
+    info!(
+        text = body.text.as_str(),
+        prompt_influence = body.prompt_influence.unwrap_or(0.3);
+        "Mock sound generation request"
+    );
+    // Like the real API, pick a length if none was asked for
+    let seconds = body.duration_seconds.unwrap_or(2.0);
+    let (content_type, audio) = silent_audio(query.output_format.as_deref(), seconds);
+    ([(header::CONTENT_TYPE, content_type)], audio).into_response()
+}
+
+/// A field of a multipart/form-data body
+struct FormField {
+    /// Only set for file uploads
+    filename: Option<String>,
+    data: Vec<u8>,
+}
+
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Just enough of multipart/form-data to read what the bot uploads. Returns None if the body is malformed.
+fn parse_multipart(headers: &HeaderMap, body: &[u8]) -> Option<HashMap<String, FormField>> {
+    Some(parse_multipart_parts(headers, body)?.into_iter().collect())
+}
+
+/// Like `parse_multipart`, but keeps every part in order, for fields that can be repeated
+fn parse_multipart_parts(headers: &HeaderMap, body: &[u8]) -> Option<Vec<(String, FormField)>> {
+    let content_type = headers.get(header::CONTENT_TYPE)?.to_str().ok()?;
+    let boundary = content_type
+        .split(';')
+        .find_map(|p| p.trim().strip_prefix("boundary="))?
+        .trim_matches('"');
+    let delimiter = format!("--{}", boundary).into_bytes();
+
+    let mut fields = Vec::new();
+    let mut rest = &body[find_bytes(body, &delimiter)? + delimiter.len()..];
+    // Every part starts right after a delimiter, and the last delimiter is followed by "--"
+    while !rest.starts_with(b"--") {
+        let part_end = find_bytes(rest, &delimiter)?;
+        let part = rest[..part_end].strip_prefix(b"\r\n")?;
+        let part = part.strip_suffix(b"\r\n").unwrap_or(part);
+        rest = &rest[part_end + delimiter.len()..];
+
+        let header_end = find_bytes(part, b"\r\n\r\n")?;
+        let part_headers = std::str::from_utf8(&part[..header_end]).ok()?;
+        let disposition = part_headers
+            .lines()
+            .find(|l| l.to_lowercase().starts_with("content-disposition:"))?;
+        let param = |name: &str| {
+            disposition.split(';').find_map(|p| {
+                p.trim()
+                    .strip_prefix(name)
+                    .and_then(|v| v.strip_prefix('='))
+                    .map(|v| v.trim_matches('"').to_string())
+            })
+        };
+
+        fields.push((
+            param("name")?,
+            FormField {
+                filename: param("filename"),
+                data: part[header_end + 4..].to_vec(),
+            },
+        ));
+    }
+    Some(fields)
+}
+
+fn invalid_form(message: &str) -> Response {
+    error_response(StatusCode::UNPROCESSABLE_ENTITY, "invalid_request", message)
+}
+
+async fn speech_to_speech(
+    headers: HeaderMap,
+    Path(voice_id): Path<String>,
+    Query(query): Query<SpeechQuery>,
+    body: Bytes,
+) -> Response {
+    if let Some(resp) = reject_api_key(&headers) {
+        return resp;
+    }
+    if !is_known_voice(&voice_id) {
+        return voice_not_found(&voice_id);
+    }
+    let Some(fields) = parse_multipart(&headers, &body) else {
+        return invalid_form("Expected a multipart/form-data body");
+    };
+    let Some(audio) = fields.get("audio").filter(|f| !f.data.is_empty()) else {
+        return invalid_form("The audio field is required");
+    };
+
+    let field_text = |name: &str| {
+        fields
+            .get(name)
+            .map(|f| String::from_utf8_lossy(&f.data).to_string())
+    };
+    info!(
+        voice_id = voice_id.as_str(),
+        filename = audio.filename.as_deref().unwrap_or(""),
+        size = audio.data.len(),
+        model_id = field_text("model_id").as_deref().unwrap_or("default"),
+        remove_background_noise = field_text("remove_background_noise").as_deref().unwrap_or("false");
+        "Mock speech-to-speech request"
+    );
+
+    // Assume the upload is 128kbps audio, so the result is about as long as the recording
+    let seconds = (audio.data.len() as f32 / 16000.0).clamp(0.5, 30.0);
+    let (content_type, audio) = silent_audio(query.output_format.as_deref(), seconds);
+    ([(header::CONTENT_TYPE, content_type)], audio).into_response()
+}
+
+// What every mock transcription says, split between two speakers when diarizing
+const MOCK_TRANSCRIPT: &[&str] = &[
+    "Welcome back to the show.",
+    "Thanks, it's great to be here.",
+];
+
+async fn speech_to_text(headers: HeaderMap, body: Bytes) -> Response {
+    if let Some(resp) = reject_api_key(&headers) {
+        return resp;
+    }
+    let Some(fields) = parse_multipart(&headers, &body) else {
+        return invalid_form("Expected a multipart/form-data body");
+    };
+    let field_text = |name: &str| {
+        fields
+            .get(name)
+            .map(|f| String::from_utf8_lossy(&f.data).to_string())
+    };
+    if field_text("model_id").is_none() {
+        return invalid_form("The model_id field is required");
+    }
+    let Some(file) = fields.get("file").filter(|f| !f.data.is_empty()) else {
+        return invalid_form("The file field is required");
+    };
+
+    let diarize = field_text("diarize").as_deref() == Some("true");
+    let granularity = field_text("timestamps_granularity").unwrap_or_else(|| "word".to_string());
+    info!(
+        filename = file.filename.as_deref().unwrap_or(""),
+        size = file.data.len(),
+        diarize = diarize,
+        timestamps_granularity = granularity.as_str();
+        "Mock speech-to-text request"
+    );
+
+    let mut words = Vec::new();
+    let mut time = 0.0;
+    for (i, sentence) in MOCK_TRANSCRIPT.iter().enumerate() {
+        let speaker_id = format!("speaker_{}", if diarize { i } else { 0 });
+        for word in sentence.split(' ') {
+            if !words.is_empty() {
+                words.push(json!({ "text": " ", "type": "spacing", "start": time, "end": time, "speaker_id": speaker_id }));
+            }
+            let end = time + word.len() as f32 * SECONDS_PER_CHAR;
+            words.push(json!({ "text": word, "type": "word", "start": time, "end": end, "speaker_id": speaker_id }));
+            time = end + 0.1;
+        }
+    }
+
+    Json(json!({
+        "language_code": field_text("language_code").unwrap_or_else(|| "en".to_string()),
+        "language_probability": 0.98,
+        "text": MOCK_TRANSCRIPT.join(" "),
+        "words": if granularity == "none" { Vec::new() } else { words },
+    }))
+    .into_response()
+}
+
+#[derive(Debug, Deserialize)]
+struct SpeechQuery {
+    output_format: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SpeechBody {
+    text: String,
+    #[serde(default)]
+    pronunciation_dictionary_locators: Vec<DictionaryLocator>,
+    previous_text: Option<String>,
+    next_text: Option<String>,
+    #[serde(default)]
+    previous_request_ids: Vec<String>,
+    #[serde(default)]
+    next_request_ids: Vec<String>,
+    seed: Option<i64>,
+    language_code: Option<String>,
+    apply_text_normalization: Option<String>,
+    apply_language_text_normalization: Option<bool>,
+}
+
+/// Seeds have to fit in a u32, and normalization is one of three words
+fn reject_speech_tuning(body: &SpeechBody) -> Option<Response> {
+    if let Some(seed) = body.seed
+        && !(0..=i64::from(u32::MAX)).contains(&seed)
+    {
+        return Some(invalid_form("seed must be between 0 and 4294967295"));
+    }
+    if let Some(normalization) = body.apply_text_normalization.as_deref()
+        && !["auto", "on", "off"].contains(&normalization)
+    {
+        return Some(invalid_form(
+            "apply_text_normalization must be one of auto, on, off",
+        ));
+    }
+    None
+}
+
+fn log_speech_tuning(body: &SpeechBody) {
+    info!(
+        seed = body.seed,
+        language_code = body.language_code.as_deref().unwrap_or("auto"),
+        apply_text_normalization = body.apply_text_normalization.as_deref().unwrap_or("auto"),
+        apply_language_text_normalization = body.apply_language_text_normalization.unwrap_or(false);
+        "Mock speech tuning"
+    );
+}
+
+/// Stitched requests can name up to 3 requests on either side, and each has to be a past generation
+fn reject_stitching(body: &SpeechBody) -> Option<Response> {
+    for ids in [&body.previous_request_ids, &body.next_request_ids] {
+        if ids.len() > 3 {
+            return Some(invalid_form("At most 3 request IDs can be stitched"));
+        }
+    }
+    let history = HISTORY.lock().unwrap();
+    let unknown = body
+        .previous_request_ids
+        .iter()
+        .chain(body.next_request_ids.iter())
+        .find(|id| !history.iter().any(|e| e.request_id == **id))?;
+    Some(error_response(
+        StatusCode::BAD_REQUEST,
+        "invalid_request_id",
+        &format!("No generation with the request ID {} was found.", unknown),
+    ))
+}
+
+fn log_stitching(body: &SpeechBody) {
+    if !body.previous_request_ids.is_empty()
+        || !body.next_request_ids.is_empty()
+        || body.previous_text.is_some()
+        || body.next_text.is_some()
+    {
+        info!(
+            previous_request_ids = body.previous_request_ids.join(", ").as_str(),
+            next_request_ids = body.next_request_ids.join(", ").as_str(),
+            previous_text = body.previous_text.as_deref().unwrap_or(""),
+            next_text = body.next_text.as_deref().unwrap_or("");
+            "Mock request is stitched"
+        );
+    }
+}
+
+fn with_request_id(request_id: String, resp: impl IntoResponse) -> Response {
+    let mut resp = resp.into_response();
+    if let Ok(value) = header::HeaderValue::from_str(&request_id) {
+        resp.headers_mut().insert("request-id", value);
+    }
+    resp
+}
+
+/// The checks shared by all text-to-speech endpoints, returning the error response if any fail
+fn reject_speech_request(headers: &HeaderMap, voice_id: &str, text: &str) -> Option<Response> {
+    if let Some(resp) = reject_api_key(headers) {
+        return Some(resp);
+    }
+    if BUSY_REQUESTS_LEFT
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| {
+            left.checked_sub(1)
+        })
+        .is_ok()
+    {
+        let mut resp = error_response(
+            StatusCode::TOO_MANY_REQUESTS,
+            "too_many_concurrent_requests",
+            "Too many concurrent requests.",
+        );
+        resp.headers_mut()
+            .insert(header::RETRY_AFTER, header::HeaderValue::from_static("1"));
+        return Some(resp);
+    }
+    if !is_known_voice(voice_id) {
+        return Some(voice_not_found(voice_id));
+    }
+    if text.trim().is_empty() {
+        return Some(error_response(
+            StatusCode::BAD_REQUEST,
+            "empty_text",
+            "The text to generate speech for must not be empty.",
+        ));
+    }
+    None
+}
+
+// Roughly match the length of real speech so playback takes a realistic amount of time
+const SECONDS_PER_CHAR: f32 = 1.0 / 15.0;
+
+async fn text_to_speech(
+    headers: HeaderMap,
+    Path(voice_id): Path<String>,
+    Query(query): Query<SpeechQuery>,
+    Json(body): Json<SpeechBody>,
+) -> Response {
+    if let Some(resp) = reject_speech_request(&headers, &voice_id, &body.text) {
+        return resp;
+    }
+    if let Some(resp) = reject_dictionary_locators(&body.pronunciation_dictionary_locators) {
+        return resp;
+    }
+
+    if let Some(resp) = reject_stitching(&body) {
+        return resp;
+    }
+    if let Some(resp) = reject_speech_tuning(&body) {
+        return resp;
+    }
+
+    info!(
+        voice_id = voice_id.as_str(),
+        text = body.text.as_str(),
+        output_format = query.output_format.as_deref().unwrap_or("default");
+        "Mock text-to-speech request"
+    );
+    log_stitching(&body);
+    log_speech_tuning(&body);
+
+    let seconds = (body.text.chars().count() as f32 * SECONDS_PER_CHAR).clamp(0.5, 30.0);
+    let (content_type, audio) = silent_audio(query.output_format.as_deref(), seconds);
+    let request_id = record_history(&voice_id, &body.text, content_type, &audio);
+    with_request_id(request_id, ([(header::CONTENT_TYPE, content_type)], audio))
+}
+
+const OPENAI_MODELS: &[&str] = &["tts-1", "tts-1-hd", "gpt-4o-mini-tts"];
+const OPENAI_VOICES: &[&str] = &[
+    "alloy", "ash", "ballad", "coral", "echo", "fable", "nova", "onyx", "sage", "shimmer", "verse",
+];
+// The API's limit on input length
+const OPENAI_MAX_INPUT_CHARS: usize = 4096;
+// Everything OpenAI's raw PCM comes out at
+const OPENAI_PCM_SAMPLE_RATE: f32 = 24000.0;
+
+fn openai_error(status: StatusCode, error_type: &str, message: &str) -> Response {
+    (
+        status,
+        Json(json!({ "error": { "message": message, "type": error_type, "param": null, "code": null } })),
+    )
+        .into_response()
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiSpeechBody {
+    model: String,
+    voice: String,
+    input: String,
+    response_format: Option<String>,
+    speed: Option<f32>,
+}
+
+async fn openai_speech(headers: HeaderMap, Json(body): Json<OpenAiSpeechBody>) -> Response {
+    if let Ok(expected) = std::env::var(OPENAI_API_KEY_ENV)
+        && headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            != Some(expected.as_str())
+    {
+        return openai_error(
+            StatusCode::UNAUTHORIZED,
+            "invalid_request_error",
+            "Incorrect API key provided.",
+        );
+    }
+    let invalid =
+        |message: &str| openai_error(StatusCode::BAD_REQUEST, "invalid_request_error", message);
+    if !OPENAI_MODELS.contains(&body.model.as_str()) {
+        return invalid(&format!("Invalid model: {}", body.model));
+    }
+    if !OPENAI_VOICES.contains(&body.voice.as_str()) {
+        return invalid(&format!("Invalid voice: {}", body.voice));
+    }
+    if body.input.trim().is_empty() || body.input.chars().count() > OPENAI_MAX_INPUT_CHARS {
+        return invalid("input must be between 1 and 4096 characters");
+    }
+    if let Some(speed) = body.speed
+        && !(0.25..=4.0).contains(&speed)
+    {
+        return invalid("speed must be between 0.25 and 4.0");
+    }
+    let response_format = body.response_format.as_deref().unwrap_or("mp3");
+
+    info!(
+        model = body.model.as_str(),
+        voice = body.voice.as_str(),
+        input = body.input.as_str(),
+        response_format = response_format,
+        speed = body.speed.unwrap_or(1.0);
+        "Mock OpenAI speech request"
+    );
+
+    // Faster speech makes for shorter audio, so the speed that was asked for can be checked
+    let seconds = (body.input.chars().count() as f32 * SECONDS_PER_CHAR
+        / body.speed.unwrap_or(1.0))
+    .clamp(0.5, 30.0);
+    let (content_type, audio) = match response_format {
+        "mp3" => ("audio/mpeg", silent_mp3(seconds)),
+        "pcm" => (
+            "audio/pcm",
+            vec![0; (seconds * OPENAI_PCM_SAMPLE_RATE) as usize * 2],
+        ),
+        // Real encoders would be overkill for a mock
+        "opus" | "aac" | "flac" | "wav" => {
+            return invalid(&format!(
+                "The mock can't produce {}, use mp3 or pcm",
+                response_format
+            ));
+        }
+        _ => return invalid(&format!("Invalid response_format: {}", response_format)),
+    };
+    ([(header::CONTENT_TYPE, content_type)], audio).into_response()
+}
+
+#[derive(Debug, Deserialize)]
+struct DialogueInput {
+    text: String,
+    voice_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DialogueBody {
+    inputs: Vec<DialogueInput>,
+    model_id: Option<String>,
+    #[serde(default)]
+    pronunciation_dictionary_locators: Vec<DictionaryLocator>,
+}
+
+async fn text_to_dialogue(
+    headers: HeaderMap,
+    Query(query): Query<SpeechQuery>,
+    Json(body): Json<DialogueBody>,
+) -> Response {
+    let Some(first) = body.inputs.first() else {
+        return invalid_form("At least one input is required");
+    };
+    // Checked like a single speech request, so it only counts once towards the busy requests
+    if let Some(resp) = reject_speech_request(&headers, &first.voice_id, &first.text) {
+        return resp;
+    }
+    if let Some(resp) = reject_dictionary_locators(&body.pronunciation_dictionary_locators) {
+        return resp;
+    }
+    if let Some(model_id) = body.model_id.as_deref()
+        && model_id != "eleven_v3"
+    {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "invalid_model",
+            &format!("Text to dialogue isn't supported by {}", model_id),
+        );
+    }
+    if let Some(input) = body.inputs.iter().find(|i| !is_known_voice(&i.voice_id)) {
+        return voice_not_found(&input.voice_id);
+    }
+
+    info!(
+        lines = body.inputs.len(),
+        voices = body
+            .inputs
+            .iter()
+            .map(|i| i.voice_id.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+            .as_str(),
+        output_format = query.output_format.as_deref().unwrap_or("default");
+        "Mock text-to-dialogue request"
+    );
+
+    let chars: usize = body.inputs.iter().map(|i| i.text.chars().count()).sum();
+    let seconds = (chars as f32 * SECONDS_PER_CHAR).clamp(0.5, 30.0);
+    let (content_type, audio) = silent_audio(query.output_format.as_deref(), seconds);
+    ([(header::CONTENT_TYPE, content_type)], audio).into_response()
+}
+
+async fn text_to_speech_with_timestamps(
+    headers: HeaderMap,
+    Path(voice_id): Path<String>,
+    Json(body): Json<SpeechBody>,
+) -> Response {
+    if let Some(resp) = reject_speech_request(&headers, &voice_id, &body.text) {
+        return resp;
+    }
+    if let Some(resp) = reject_dictionary_locators(&body.pronunciation_dictionary_locators) {
+        return resp;
+    }
+    if let Some(resp) = reject_stitching(&body) {
+        return resp;
+    }
+    if let Some(resp) = reject_speech_tuning(&body) {
+        return resp;
+    }
+    log_stitching(&body);
+    log_speech_tuning(&body);
+
+    let chars: Vec<String> = body.text.chars().map(|c| c.to_string()).collect();
+    let starts: Vec<f32> = (0..chars.len())
+        .map(|i| i as f32 * SECONDS_PER_CHAR)
+        .collect();
+    let ends: Vec<f32> = starts.iter().map(|s| s + SECONDS_PER_CHAR).collect();
+    let alignment = json!({
+        "characters": chars,
+        "character_start_times_seconds": starts,
+        "character_end_times_seconds": ends,
+    });
+    let audio = silent_mp3(chars.len() as f32 * SECONDS_PER_CHAR);
+    let request_id = record_history(&voice_id, &body.text, "audio/mpeg", &audio);
+    with_request_id(
+        request_id,
+        Json(json!({
+            "audio_base64": BASE64.encode(audio),
+            "alignment": alignment,
+            "normalized_alignment": alignment,
+        })),
+    )
+}
+
+async fn text_to_speech_input_stream(
+    headers: HeaderMap,
+    Path(voice_id): Path<String>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    if let Some(resp) = reject_api_key(&headers) {
+        return resp;
+    }
+    if !is_known_voice(&voice_id) {
+        return voice_not_found(&voice_id);
+    }
+    ws.on_upgrade(handle_input_stream)
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamInputMessage {
+    text: String,
+}
+
+/// Answers every piece of text with a chunk of silence covering it, and finishes when sent an empty string
+async fn handle_input_stream(mut socket: WebSocket) {
+    let mut first = true;
+    while let Some(Ok(message)) = socket.recv().await {
+        let Message::Text(text) = message else {
+            continue;
+        };
+        let Ok(input) = serde_json::from_str::<StreamInputMessage>(text.as_str()) else {
+            let _ = socket
+                .send(Message::text(
+                    json!({ "message": "Invalid message", "error": "invalid_message" }).to_string(),
+                ))
+                .await;
+            break;
+        };
+
+        // The first message only opens the stream
+        if std::mem::take(&mut first) {
+            continue;
+        }
+        if input.text.is_empty() {
+            let _ = socket
+                .send(Message::text(json!({ "isFinal": true }).to_string()))
+                .await;
+            break;
+        }
+
+        let chars: Vec<String> = input.text.chars().map(|c| c.to_string()).collect();
+        let chunk = json!({
+            "audio": BASE64.encode(silent_mp3(chars.len() as f32 * SECONDS_PER_CHAR)),
+            "isFinal": false,
+            "alignment": {
+                "chars": chars,
+                "charStartTimesMs": (0..chars.len()).map(|i| i * 66).collect::<Vec<_>>(),
+                "charsDurationsMs": vec![66; chars.len()],
+            },
+        });
+        if socket.send(Message::text(chunk.to_string())).await.is_err() {
+            break;
+        }
+    }
+    let _ = socket.send(Message::Close(None)).await;
+}
+
+/// Silence in the requested output format. Raw formats are honoured, everything else gets MP3.
+fn silent_audio(output_format: Option<&str>, seconds: f32) -> (&'static str, Vec<u8>) {
+    let mut parts = output_format.unwrap_or("mp3_44100_128").split('_');
+    let codec = parts.next().unwrap_or("mp3");
+    let sample_rate = parts
+        .next()
+        .and_then(|r| r.parse::<f32>().ok())
+        .unwrap_or(44100.0);
+    let samples = (seconds * sample_rate) as usize;
+    match codec {
+        "pcm" => ("audio/pcm", vec![0; samples * 2]),
+        // The G.711 encodings of zero amplitude
+        "ulaw" => ("audio/basic", vec![0xFF; samples]),
+        "alaw" => ("audio/basic", vec![0xD5; samples]),
+        _ => ("audio/mpeg", silent_mp3(seconds)),
+    }
+}
+
+/// Builds a valid MP3 stream of silence: MPEG-1 Layer III frames at 44.1kHz / 128kbps,
+/// whose side info is all zeroes so every frame decodes to nothing.
+fn silent_mp3(seconds: f32) -> Vec<u8> {
+    const FRAME_HEADER: [u8; 4] = [0xFF, 0xFB, 0x90, 0xC4];
+    const FRAME_LEN: usize = 417; // 144 * 128000 / 44100
+    const SAMPLES_PER_FRAME: f32 = 1152.0;
+
+    let frames = (seconds * 44100.0 / SAMPLES_PER_FRAME).ceil() as usize;
+    let mut out = Vec::with_capacity(frames * FRAME_LEN);
+    for _ in 0..frames {
+        out.extend_from_slice(&FRAME_HEADER);
+        out.resize(out.len() + FRAME_LEN - FRAME_HEADER.len(), 0);
+    }
+    out
+}
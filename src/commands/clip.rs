@@ -0,0 +1,298 @@
+use std::path::Path;
+
+use ::poise::{CreateReply, Modal};
+use ::serenity::all::{AutocompleteChoice, CreateAttachment, Message};
+use log::{error, info};
+
+use crate::commands::speak::{generate_speech_bytes, resolve_speech_model};
+use crate::commands::util::{
+    autocomplete_voice, get_channel_name, get_clip_source, get_speech_options, get_voice_handler,
+    is_audio_attachment, resolve_voice,
+};
+use crate::elevenlabs::media::get_default_output_format;
+use crate::elevenlabs::types::SpeechSpeed;
+use crate::soundboard::{Clip, check_clip_name};
+use crate::types::{ApplicationContext, Context, Error};
+
+const CLIPS_PER_PAGE: usize = 10;
+// Long lines are cut short in /clip list, so pages stay readable
+const MAX_LISTED_TEXT_LEN: usize = 80;
+
+/// Saves lines under a name, to play them in voice channels again without generating them
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    subcommands("save", "list", "play")
+)]
+pub async fn clip(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Saves a line under a name. Lines generated before come from the cache, so they cost nothing.
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn save(
+    ctx: Context<'_>,
+    #[description = "Name to save the clip as"] name: String,
+    #[description = "Voice to use"]
+    #[autocomplete = "autocomplete_voice"]
+    voice: String,
+    #[description = "Text to speak"] text: String,
+    #[description = "Speed of the speech"] speed: Option<SpeechSpeed>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    let name = match check_clip_name(&name) {
+        Ok(n) => n,
+        Err(msg) => {
+            ctx.send(CreateReply::default().content(msg)).await?;
+            return Ok(());
+        }
+    };
+    let voice = match resolve_voice(&ctx, &voice).await {
+        Ok(v) => v,
+        Err(msg) => {
+            ctx.send(CreateReply::default().content(msg)).await?;
+            return Ok(());
+        }
+    };
+    let data = ctx.data();
+    let model = match resolve_speech_model(data, &voice, None, &text) {
+        Ok(m) => m,
+        Err(msg) => {
+            ctx.send(CreateReply::default().content(msg)).await?;
+            return Ok(());
+        }
+    };
+    let options = get_speech_options(&ctx).await;
+    ctx.defer().await?;
+
+    let source = get_clip_source(&ctx, voice.get_name(), &text);
+    let (speech, provenance) = match generate_speech_bytes(
+        data,
+        voice,
+        text,
+        speed,
+        model,
+        get_default_output_format(),
+        options,
+    )
+    .await
+    {
+        Ok(s) => s,
+        Err(e) => {
+            ctx.send(CreateReply::default().content(format!("Failed to generate voice: {}", e)))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let extension = speech.format.get_file_extension();
+    let audio = speech.format.to_playable(speech.audio);
+    let clip = Clip::new(name.clone(), source.clone());
+    if let Err(msg) = data
+        .soundboard
+        .save(guild_id, clip, &audio, extension)
+        .await
+    {
+        ctx.send(CreateReply::default().content(msg)).await?;
+        return Ok(());
+    }
+
+    let sent_msg = ctx
+        .send(
+            CreateReply::default()
+                .content(format!(
+                    "Saved clip \"{}\", generated {}",
+                    name,
+                    provenance.describe()
+                ))
+                .attachment(CreateAttachment::bytes(
+                    audio,
+                    format!("{}.{}", name, extension),
+                )),
+        )
+        .await?
+        .into_message()
+        .await?;
+    data.recent_clips.record(sent_msg.id, source);
+
+    Ok(())
+}
+
+#[derive(Debug, poise::Modal)]
+#[name = "Save as clip"]
+struct ClipNameModal {
+    #[name = "Name to save the clip as"]
+    name: String,
+}
+
+/// Saves a clip the bot posted to this server's soundboard, as it is
+#[poise::command(context_menu_command = "Save as clip", guild_only)]
+pub async fn save_clip(
+    ctx: ApplicationContext<'_>,
+    #[description = "Message with a generated clip"] msg: Message,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    let reply = |content: &str| CreateReply::default().content(content).ephemeral(true);
+    let Some(attachment) = msg.attachments.iter().find(|a| is_audio_attachment(a)) else {
+        ctx.send(reply("That message has no clip to save")).await?;
+        return Ok(());
+    };
+    let Some(source) = ctx.data().recent_clips.get(msg.id) else {
+        ctx.send(reply(
+            "Only clips the bot posted since it last started can be saved from here, use /clip save for older lines",
+        ))
+        .await?;
+        return Ok(());
+    };
+    let Some(modal) = ClipNameModal::execute(ctx).await? else {
+        return Ok(());
+    };
+    let name = match check_clip_name(&modal.name) {
+        Ok(n) => n,
+        Err(e) => {
+            ctx.send(reply(&e)).await?;
+            return Ok(());
+        }
+    };
+
+    let audio = attachment.download().await?;
+    let extension = Path::new(&attachment.filename)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("mp3");
+    let clip = Clip::new(name.clone(), source);
+    if let Err(e) = ctx
+        .data()
+        .soundboard
+        .save(guild_id, clip, &audio, extension)
+        .await
+    {
+        ctx.send(reply(&e)).await?;
+        return Ok(());
+    }
+
+    ctx.send(
+        CreateReply::default().content(format!("Saved clip \"{}\", play it with /clip play", name)),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Lists the clips saved in this server
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn list(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    let clips = ctx.data().soundboard.list(guild_id).await;
+    if clips.is_empty() {
+        ctx.send(CreateReply::default().content(
+            "No clips saved yet, add one with /clip save or by picking \"Save as clip\" in the apps menu of a generated clip",
+        ))
+            .await?;
+        return Ok(());
+    }
+
+    let page_count = clips.len().div_ceil(CLIPS_PER_PAGE);
+    let pages = clips
+        .chunks(CLIPS_PER_PAGE)
+        .enumerate()
+        .map(|(i, chunk)| {
+            format!(
+                "**Saved clips** (page {} of {})\n\n{}",
+                i + 1,
+                page_count,
+                chunk
+                    .iter()
+                    .map(describe_clip)
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            )
+        })
+        .collect::<Vec<_>>();
+
+    poise::builtins::paginate(ctx, &pages.iter().map(|p| p.as_str()).collect::<Vec<_>>()).await?;
+
+    Ok(())
+}
+
+fn describe_clip(clip: &Clip) -> String {
+    let text = if clip.text.chars().count() > MAX_LISTED_TEXT_LEN {
+        format!(
+            "{}...",
+            clip.text
+                .chars()
+                .take(MAX_LISTED_TEXT_LEN)
+                .collect::<String>()
+        )
+    } else {
+        clip.text.clone()
+    };
+    format!(
+        "**{}** - {}: \"{}\" (saved by {}, <t:{}:d>)",
+        clip.name,
+        clip.voice,
+        text,
+        clip.creator_name,
+        clip.created_at.timestamp()
+    )
+}
+
+/// Plays a saved clip in the currently joined voice channel
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn play(
+    ctx: Context<'_>,
+    #[description = "Clip to play"]
+    #[autocomplete = "autocomplete_clip"]
+    name: String,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    let Some(clip) = ctx.data().soundboard.find(guild_id, &name).await else {
+        ctx.send(CreateReply::default().content(format!("There's no clip called \"{}\"", name)))
+            .await?;
+        return Ok(());
+    };
+    let Some((handler_lock, channel)) = get_voice_handler(&ctx).await? else {
+        ctx.send(CreateReply::default().content("Not in a voice channel"))
+            .await?;
+        return Ok(());
+    };
+
+    let bytes = match ctx.data().soundboard.read_audio(guild_id, &clip).await {
+        Ok(b) => b,
+        Err(e) => {
+            error!(clip = clip.name.as_str(), error = e.to_string().as_str(); "Failed to read clip");
+            ctx.send(CreateReply::default().content(format!("Failed to read the clip: {}", e)))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    info!(guild_id = guild_id.get(), clip = clip.name.as_str(); "Playing clip");
+    let _ = handler_lock.lock().await.play_input(bytes.into());
+    ctx.send(CreateReply::default().content(format!(
+        "Playing \"{}\" in channel \"{}\"",
+        clip.name,
+        get_channel_name(&ctx, channel)?
+    )))
+    .await?;
+
+    Ok(())
+}
+
+async fn autocomplete_clip(
+    ctx: Context<'_>,
+    partial: &str,
+) -> impl Iterator<Item = AutocompleteChoice> {
+    let clips = match ctx.guild_id() {
+        Some(guild_id) => ctx.data().soundboard.list(guild_id).await,
+        None => Vec::new(),
+    };
+    let partial = partial.to_lowercase();
+    clips
+        .into_iter()
+        .filter(move |c| c.name.to_lowercase().contains(&partial))
+        // Discord shows at most 25 choices
+        .take(25)
+        .map(|c| AutocompleteChoice::new(c.name.clone(), c.name))
+}
@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+
+use ::poise::CreateReply;
+use ::serenity::all::Attachment;
+use log::{error, info};
+
+use crate::commands::util::{autocomplete_custom_voice, is_audio_attachment, resolve_voice};
+use crate::types::{Context, Error};
+use crate::voice_registry::{BotVoice, CustomVoice};
+
+/// Clones a voice from recordings of someone speaking, and makes it available to all voice commands
+#[allow(clippy::too_many_arguments)]
+#[poise::command(
+    slash_command,
+    prefix_command,
+    required_permissions = "ADMINISTRATOR",
+    default_member_permissions = "ADMINISTRATOR"
+)]
+pub async fn clone_voice(
+    ctx: Context<'_>,
+    #[description = "Name to give the voice"] name: String,
+    #[description = "Recording of the voice (a minute or two of clean speech works best)"]
+    sample: Attachment,
+    #[description = "Another recording of the voice"] sample_2: Option<Attachment>,
+    #[description = "Another recording of the voice"] sample_3: Option<Attachment>,
+    #[description = "Description of the voice"] description: Option<String>,
+    #[description = "Labels describing the voice, e.g. \"accent=british, age=old\""] labels: Option<
+        String,
+    >,
+    #[description = "Remove background noise from the recordings first"]
+    remove_background_noise: Option<bool>,
+) -> Result<(), Error> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        ctx.send(CreateReply::default().content("Voices need a name"))
+            .await?;
+        return Ok(());
+    }
+    if let Some(existing) = ctx.data().voices.read().await.get(&name) {
+        ctx.send(CreateReply::default().content(format!(
+            "There's already a voice called \"{}\"",
+            existing.get_name()
+        )))
+        .await?;
+        return Ok(());
+    }
+    let samples = [Some(sample), sample_2, sample_3]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+    if let Some(sample) = samples.iter().find(|s| !is_audio_attachment(s)) {
+        ctx.send(CreateReply::default().content(format!(
+            "\"{}\" doesn't look like an audio recording",
+            sample.filename
+        )))
+        .await?;
+        return Ok(());
+    }
+    let labels = match labels.as_deref().map(parse_labels).transpose() {
+        Ok(l) => l.unwrap_or_default(),
+        Err(msg) => {
+            ctx.send(CreateReply::default().content(msg)).await?;
+            return Ok(());
+        }
+    };
+    ctx.defer().await?;
+
+    let mut downloaded = Vec::with_capacity(samples.len());
+    for sample in &samples {
+        downloaded.push((sample.filename.clone(), sample.download().await?));
+    }
+
+    info!(name = name.as_str(), user = ctx.author().name.as_str(); "Cloning voice");
+    let voice_id = match ctx
+        .data()
+        .client
+        .add_voice(
+            name.clone(),
+            downloaded,
+            description,
+            labels,
+            remove_background_noise.unwrap_or(false),
+        )
+        .await
+    {
+        Ok(id) => id,
+        Err(e) => {
+            error!(name = name.as_str(), error = e.to_string().as_str(); "Failed to clone voice");
+            ctx.send(CreateReply::default().content(format!("Failed to clone voice: {}", e)))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    ctx.data().voices.write().await.add(CustomVoice {
+        voice_id: voice_id.clone(),
+        name: name.clone(),
+    });
+    ctx.send(CreateReply::default().content(format!(
+        "Cloned voice \"{}\" (`{}`), it can now be used with /speak and /speak_vs",
+        name, voice_id
+    )))
+    .await?;
+
+    Ok(())
+}
+
+/// Permanently deletes a cloned voice
+#[poise::command(
+    slash_command,
+    prefix_command,
+    required_permissions = "ADMINISTRATOR",
+    default_member_permissions = "ADMINISTRATOR"
+)]
+pub async fn delete_voice(
+    ctx: Context<'_>,
+    #[description = "Cloned voice to delete"]
+    #[autocomplete = "autocomplete_custom_voice"]
+    voice: String,
+) -> Result<(), Error> {
+    let voice = match resolve_voice(&ctx, &voice).await {
+        Ok(BotVoice::Custom(v)) => v,
+        Ok(BotVoice::Known(_) | BotVoice::Provider(_)) => {
+            ctx.send(CreateReply::default().content("Built-in voices can't be deleted"))
+                .await?;
+            return Ok(());
+        }
+        Err(msg) => {
+            ctx.send(CreateReply::default().content(msg)).await?;
+            return Ok(());
+        }
+    };
+    ctx.defer().await?;
+
+    info!(voice_id = voice.voice_id.as_str(), user = ctx.author().name.as_str(); "Deleting voice");
+    let content = match ctx.data().client.delete_voice(&voice.voice_id).await {
+        Ok(()) => {
+            ctx.data().voices.write().await.remove(&voice.voice_id);
+            format!("Deleted voice \"{}\"", voice.name)
+        }
+        Err(e) => format!("Failed to delete voice: {}", e),
+    };
+    ctx.send(CreateReply::default().content(content)).await?;
+
+    Ok(())
+}
+
+/// Parses "key=value, key=value" (or with colons) into labels
+fn parse_labels(labels: &str) -> Result<HashMap<String, String>, String> {
+    labels
+        .split(',')
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(|label| {
+            label
+                .split_once(['=', ':'])
+                .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+                .ok_or_else(|| {
+                    format!(
+                        "Couldn't understand the label \"{}\", use key=value pairs like \"accent=british, age=old\"",
+                        label
+                    )
+                })
+        })
+        .collect()
+}
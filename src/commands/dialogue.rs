@@ -0,0 +1,254 @@
+use ::poise::CreateReply;
+use ::serenity::all::CreateAttachment;
+use log::{error, info};
+use serenity::all::EditMessage;
+
+use crate::commands::util::{
+    autocomplete_output_format, get_channel_name, get_clip_source, get_speech_options,
+    get_voice_handler, resolve_output_format,
+};
+use crate::elevenlabs::types::DialogueLine;
+use crate::streamutil::write_stream_to_vec_u8;
+use crate::types::{Context, Error};
+use crate::voice_registry::VoiceRegistry;
+
+/// Generates a conversation between several voices as one clip
+#[poise::command(slash_command, prefix_command)]
+pub async fn dialogue(
+    ctx: Context<'_>,
+    #[description = "Lines like \"June: Welcome back! / Scotty: Good to be here.\""] script: String,
+    #[description = "Play it in the currently joined voice channel instead of just posting it"]
+    play_in_voice: Option<bool>,
+    #[description = "Audio format to generate"]
+    #[autocomplete = "autocomplete_output_format"]
+    format: Option<String>,
+) -> Result<(), Error> {
+    let format = match resolve_output_format(format) {
+        Ok(f) => f,
+        Err(msg) => {
+            ctx.send(CreateReply::default().content(msg)).await?;
+            return Ok(());
+        }
+    };
+    let (lines, speakers) = match parse_script(&script, &*ctx.data().voices.read().await) {
+        Ok(l) => l,
+        Err(msg) => {
+            ctx.send(CreateReply::default().content(msg)).await?;
+            return Ok(());
+        }
+    };
+    let model = ctx.data().models.get_dialogue_model();
+    let length = lines.iter().map(|l| l.text.chars().count()).sum::<usize>();
+    if let Some(model) = model
+        && let Some(max) = model.get_max_characters()
+        && length > max
+    {
+        ctx.send(CreateReply::default().content(format!(
+            "That script is {} characters long, but {} can only speak {} at a time",
+            length, model.name, max
+        )))
+        .await?;
+        return Ok(());
+    }
+    let voice = if play_in_voice.unwrap_or(false) {
+        match get_voice_handler(&ctx).await? {
+            Some(v) => Some(v),
+            None => {
+                ctx.send(CreateReply::default().content("Not in a voice channel"))
+                    .await?;
+                return Ok(());
+            }
+        }
+    } else {
+        None
+    };
+
+    let sent_msg_handle = ctx
+        .send(CreateReply::default().content("Generating dialogue..."))
+        .await?;
+    let mut sent_msg = sent_msg_handle.into_message().await.map_err(|e| {
+        error!(error = e.to_string().as_str(); "Failed to convert message to Message");
+        Error::from(e)
+    })?;
+
+    info!(lines = lines.len(), speakers = speakers.join(", ").as_str(); "Generating dialogue");
+    let generated = match ctx
+        .data()
+        .client
+        .generate_dialogue(
+            lines,
+            model.map(|m| m.model_id.clone()),
+            Some(format),
+            get_speech_options(&ctx).await,
+        )
+        .await
+    {
+        Ok(stream) => write_stream_to_vec_u8(stream).await,
+        Err(e) => Err(e.into()),
+    };
+    let bytes = match generated {
+        Err(e) => {
+            error!(error = e.to_string().as_str(); "Failed to generate dialogue");
+            ctx.send(CreateReply::default().content(format!("Failed to generate dialogue: {}", e)))
+                .await?;
+            return Ok(());
+        }
+        Ok(b) => format.to_playable(b),
+    };
+
+    let content = match voice {
+        Some((handler_lock, channel)) => {
+            let _ = handler_lock.lock().await.play_input(bytes.clone().into());
+            format!(
+                "Playing dialogue between {} in channel \"{}\"",
+                speakers.join(", "),
+                get_channel_name(&ctx, channel)?
+            )
+        }
+        None => format!("Generated dialogue between {}", speakers.join(", ")),
+    };
+    sent_msg
+        .edit(
+            ctx.http(),
+            EditMessage::default()
+                .new_attachment(CreateAttachment::bytes(
+                    bytes,
+                    format!("Dialogue.{}", format.get_file_extension()),
+                ))
+                .content(content),
+        )
+        .await?;
+    ctx.data().recent_clips.record(
+        sent_msg.id,
+        get_clip_source(&ctx, &speakers.join(", "), &script),
+    );
+
+    Ok(())
+}
+
+/// Splits a script into lines, which are separated by " / " or newlines and start with "Speaker:".
+/// Also returns the names of everyone speaking, in order of appearance.
+/// The error is meant to be shown to the user.
+fn parse_script(
+    script: &str,
+    voices: &VoiceRegistry,
+) -> Result<(Vec<DialogueLine>, Vec<String>), String> {
+    let mut lines = Vec::new();
+    let mut speakers: Vec<String> = Vec::new();
+    for turn in script
+        .lines()
+        .flat_map(|l| l.split(" / "))
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+    {
+        let Some((speaker, text)) = turn.split_once(':') else {
+            return Err(format!(
+                "\"{}\" doesn't say who's speaking, start each line with a voice name like \"June: ...\"",
+                turn
+            ));
+        };
+        let voice = voices.resolve(speaker)?;
+        if !voice.is_elevenlabs() {
+            return Err(format!(
+                "{} isn't an ElevenLabs voice, dialogues only work with those",
+                voice.get_name()
+            ));
+        }
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(format!("{} has nothing to say", voice.get_name()));
+        }
+        if !speakers.iter().any(|s| s == voice.get_name()) {
+            speakers.push(voice.get_name().to_string());
+        }
+        lines.push(DialogueLine {
+            voice_id: voice.get_id(),
+            text: text.to_string(),
+        });
+    }
+    if lines.is_empty() {
+        return Err("The script is empty".to_string());
+    }
+
+    Ok((lines, speakers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elevenlabs::types::KnownVoice;
+    use crate::tts::ProviderVoice;
+    use crate::voice_registry::{BotVoice, CustomVoice};
+
+    fn voices() -> VoiceRegistry {
+        VoiceRegistry::new(vec![CustomVoice {
+            voice_id: "custom1".to_string(),
+            name: "Narrator".to_string(),
+        }])
+        .with_provider_voices(vec![ProviderVoice {
+            provider_id: "espeak".to_string(),
+            voice_id: "en".to_string(),
+            name: "Robot".to_string(),
+            description: None,
+        }])
+    }
+
+    #[test]
+    fn splits_turns_on_slashes_and_newlines() {
+        let (lines, speakers) = parse_script(
+            "June: Welcome back! / scotty: Good to be here.\nNarrator:  And so it began ",
+            &voices(),
+        )
+        .unwrap();
+        let june = BotVoice::Known(KnownVoice::June).get_id();
+        let scotty = BotVoice::Known(KnownVoice::Scotty).get_id();
+        assert_eq!(
+            lines
+                .iter()
+                .map(|l| (l.voice_id.as_str(), l.text.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (june.as_str(), "Welcome back!"),
+                (scotty.as_str(), "Good to be here."),
+                ("custom1", "And so it began"),
+            ]
+        );
+        assert_eq!(speakers, vec!["June", "Scotty", "Narrator"]);
+    }
+
+    #[test]
+    fn lists_each_speaker_once() {
+        let (lines, speakers) =
+            parse_script("June: One / Scotty: Two / June: Three", &voices()).unwrap();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(speakers, vec!["June", "Scotty"]);
+    }
+
+    #[test]
+    fn rejects_bad_scripts() {
+        let voices = voices();
+        assert!(
+            parse_script("Just talking", &voices)
+                .unwrap_err()
+                .contains("doesn't say who's speaking")
+        );
+        assert!(
+            parse_script("Nobody: Hello", &voices)
+                .unwrap_err()
+                .starts_with("Unknown voice \"Nobody\"")
+        );
+        assert!(
+            parse_script("Robot: Beep", &voices)
+                .unwrap_err()
+                .contains("isn't an ElevenLabs voice")
+        );
+        assert_eq!(
+            parse_script("June: Hi / Scotty:  ", &voices).unwrap_err(),
+            "Scotty has nothing to say"
+        );
+        assert_eq!(
+            parse_script(" / \n", &voices).unwrap_err(),
+            "The script is empty"
+        );
+    }
+}
@@ -0,0 +1,364 @@
+use crate::commands::util::{
+    autocomplete_elevenlabs_voice, get_channel_name, get_clip_source, get_voice_handler,
+    resolve_elevenlabs_voice,
+};
+use crate::elevenlabs::media::get_file_extension_for_content_type;
+use crate::elevenlabs::types::HistoryItem;
+use crate::soundboard::ClipSource;
+use crate::streamutil::write_stream_to_vec_u8;
+use crate::types::{Context, Error};
+
+use ::poise::CreateReply;
+use ::serenity::all::{AutocompleteChoice, CreateAttachment};
+use chrono::{DateTime, Local};
+use log::{error, info};
+
+// Keeps each page comfortably under Discord's message length limit
+const ITEMS_PER_PAGE: usize = 10;
+// The most history items the API returns per page
+const MAX_HISTORY_PAGE_SIZE: u32 = 100;
+const DEFAULT_HISTORY_COUNT: u32 = 25;
+// Discord limits autocomplete choice names to 100 characters
+const MAX_CHOICE_NAME_LEN: usize = 100;
+
+/// Browses, replays and downloads past generations without spending characters on them again
+#[poise::command(
+    slash_command,
+    prefix_command,
+    subcommands("list", "play", "download", "delete")
+)]
+pub async fn history(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Lists the most recent generations
+#[poise::command(slash_command, prefix_command)]
+async fn list(
+    ctx: Context<'_>,
+    #[description = "Only show generations with this voice"]
+    #[autocomplete = "autocomplete_elevenlabs_voice"]
+    voice: Option<String>,
+    #[description = "How many generations to show (default 25)"]
+    #[min = 1]
+    #[max = 100]
+    count: Option<u32>,
+) -> Result<(), Error> {
+    let voice = match voice {
+        Some(v) => match resolve_elevenlabs_voice(&ctx, &v).await {
+            Ok(v) => Some(v),
+            Err(msg) => {
+                ctx.send(CreateReply::default().content(msg)).await?;
+                return Ok(());
+            }
+        },
+        None => None,
+    };
+    ctx.defer().await?;
+
+    let count = count
+        .unwrap_or(DEFAULT_HISTORY_COUNT)
+        .min(MAX_HISTORY_PAGE_SIZE);
+    let voice_id = voice.map(|v| v.get_id());
+    let items = match ctx
+        .data()
+        .client
+        .get_history(count, None, voice_id.as_deref())
+        .await
+    {
+        Ok(page) => page.history,
+        Err(e) => {
+            ctx.send(CreateReply::default().content(format!("Failed to get history: {}", e)))
+                .await?;
+            return Ok(());
+        }
+    };
+    if items.is_empty() {
+        ctx.send(CreateReply::default().content("No generations found"))
+            .await?;
+        return Ok(());
+    }
+
+    let page_count = items.len().div_ceil(ITEMS_PER_PAGE);
+    let pages = items
+        .chunks(ITEMS_PER_PAGE)
+        .enumerate()
+        .map(|(i, chunk)| {
+            format!(
+                "**Recent generations** (page {} of {})\n\n{}",
+                i + 1,
+                page_count,
+                chunk
+                    .iter()
+                    .map(describe_item)
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            )
+        })
+        .collect::<Vec<_>>();
+
+    poise::builtins::paginate(ctx, &pages.iter().map(|p| p.as_str()).collect::<Vec<_>>()).await?;
+
+    Ok(())
+}
+
+/// Replays a past generation in the currently joined voice channel
+#[poise::command(slash_command, prefix_command)]
+async fn play(
+    ctx: Context<'_>,
+    #[description = "Generation to play"]
+    #[autocomplete = "autocomplete_history_item"]
+    item: String,
+) -> Result<(), Error> {
+    let Some((handler_lock, channel)) = get_voice_handler(&ctx).await? else {
+        ctx.send(CreateReply::default().content("Not in a voice channel"))
+            .await?;
+        return Ok(());
+    };
+    ctx.defer().await?;
+
+    let bytes = match ctx.data().client.get_history_item_audio(&item).await {
+        Ok(stream) => write_stream_to_vec_u8(stream).await,
+        Err(e) => Err(e.into()),
+    };
+    let bytes = match bytes {
+        Ok(b) => b,
+        Err(e) => {
+            error!(history_item_id = item.as_str(), error = e.to_string().as_str(); "Failed to get history audio");
+            ctx.send(CreateReply::default().content(format!("Failed to get audio: {}", e)))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let details = get_item_details(&ctx, &item).await;
+    let extension = get_file_extension_for_content_type(
+        details
+            .as_ref()
+            .and_then(|d| d.content_type.as_deref())
+            .unwrap_or(""),
+    );
+
+    info!(history_item_id = item.as_str(); "Replaying history item");
+    let _ = handler_lock.lock().await.play_input(bytes.clone().into());
+    // Also posted, so it can be saved as a clip like any other
+    let sent_msg = ctx
+        .send(
+            CreateReply::default()
+                .content(format!(
+                    "Replaying in channel \"{}\"",
+                    get_channel_name(&ctx, channel)?
+                ))
+                .attachment(CreateAttachment::bytes(
+                    bytes,
+                    format!("Generation {}.{}", item, extension),
+                )),
+        )
+        .await?
+        .into_message()
+        .await?;
+    if let Some(details) = details {
+        ctx.data()
+            .recent_clips
+            .record(sent_msg.id, get_item_clip_source(&ctx, &details));
+    }
+
+    Ok(())
+}
+
+/// Downloads one or more past generations (several are sent as a zip)
+#[poise::command(slash_command, prefix_command)]
+async fn download(
+    ctx: Context<'_>,
+    #[description = "Generations to download, separated by spaces or commas"]
+    #[autocomplete = "autocomplete_history_items"]
+    items: String,
+) -> Result<(), Error> {
+    let ids = split_item_ids(&items);
+    if ids.is_empty() {
+        ctx.send(CreateReply::default().content("No generations given"))
+            .await?;
+        return Ok(());
+    }
+    ctx.defer().await?;
+
+    let (content_type, bytes) = match ctx.data().client.download_history_items(ids.clone()).await {
+        Ok(d) => d,
+        Err(e) => {
+            ctx.send(CreateReply::default().content(format!("Failed to download: {}", e)))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let extension = get_file_extension_for_content_type(content_type.as_deref().unwrap_or(""));
+    let filename = match ids.as_slice() {
+        [id] => format!("Generation {}.{}", id, extension),
+        _ => format!("Generations.{}", extension),
+    };
+    let sent_msg = ctx
+        .send(
+            CreateReply::default()
+                .content(format!("Downloaded {} generation(s)", ids.len()))
+                .attachment(CreateAttachment::bytes(bytes.to_vec(), filename)),
+        )
+        .await?
+        .into_message()
+        .await?;
+    // A single generation comes as the audio itself, which can be saved as a clip
+    if let [id] = ids.as_slice()
+        && let Some(details) = get_item_details(&ctx, id).await
+    {
+        ctx.data()
+            .recent_clips
+            .record(sent_msg.id, get_item_clip_source(&ctx, &details));
+    }
+
+    Ok(())
+}
+
+/// Permanently deletes a past generation
+#[poise::command(
+    slash_command,
+    prefix_command,
+    required_permissions = "ADMINISTRATOR",
+    default_member_permissions = "ADMINISTRATOR"
+)]
+async fn delete(
+    ctx: Context<'_>,
+    #[description = "Generation to delete"]
+    #[autocomplete = "autocomplete_history_item"]
+    item: String,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+
+    let content = match ctx.data().client.delete_history_item(&item).await {
+        Ok(()) => format!("Deleted generation `{}`", item),
+        Err(e) => format!("Failed to delete generation: {}", e),
+    };
+    ctx.send(CreateReply::default().content(content)).await?;
+
+    Ok(())
+}
+
+/// A generation's details, to save it as a clip once it's posted. Not being able to get them shouldn't stop it
+/// from being posted.
+async fn get_item_details(ctx: &Context<'_>, history_item_id: &str) -> Option<HistoryItem> {
+    match ctx.data().client.get_history_item(history_item_id).await {
+        Ok(item) => Some(item),
+        Err(e) => {
+            error!(history_item_id = history_item_id, error = e.to_string().as_str(); "Failed to get history item");
+            None
+        }
+    }
+}
+
+fn get_item_clip_source(ctx: &Context<'_>, item: &HistoryItem) -> ClipSource {
+    get_clip_source(
+        ctx,
+        item.voice_name.as_deref().unwrap_or("Unknown voice"),
+        item.text.as_deref().unwrap_or(""),
+    )
+}
+
+fn split_item_ids(items: &str) -> Vec<String> {
+    items
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|id| !id.is_empty())
+        .map(|id| id.to_string())
+        .collect()
+}
+
+fn describe_item(item: &HistoryItem) -> String {
+    let date = item
+        .get_date()
+        .map(|d| DateTime::<Local>::from(d).format("%b %d %H:%M").to_string())
+        .unwrap_or_else(|| "unknown date".to_string());
+    format!(
+        "`{}` **{}** ({}): {}",
+        item.history_item_id,
+        item.voice_name.as_deref().unwrap_or("Unknown voice"),
+        date,
+        truncate(item.text.as_deref().unwrap_or(""), 80)
+    )
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut truncated = text.chars().take(max_chars - 1).collect::<String>();
+    truncated.push('…');
+    truncated
+}
+
+async fn get_recent_items(ctx: &Context<'_>, partial: &str) -> Vec<HistoryItem> {
+    // Autocomplete has to answer quickly, so only look at the latest page
+    let items = match ctx
+        .data()
+        .client
+        .get_history(DEFAULT_HISTORY_COUNT, None, None)
+        .await
+    {
+        Ok(page) => page.history,
+        Err(e) => {
+            error!(error = e.to_string().as_str(); "Failed to get history for autocomplete");
+            return Vec::new();
+        }
+    };
+
+    let partial = partial.to_lowercase();
+    items
+        .into_iter()
+        .filter(|i| {
+            i.history_item_id.to_lowercase().contains(&partial)
+                || i.text
+                    .as_deref()
+                    .is_some_and(|t| t.to_lowercase().contains(&partial))
+                || i.voice_name
+                    .as_deref()
+                    .is_some_and(|v| v.to_lowercase().contains(&partial))
+        })
+        .collect()
+}
+
+fn get_choice_name(item: &HistoryItem) -> String {
+    truncate(
+        &format!(
+            "{}: {}",
+            item.voice_name.as_deref().unwrap_or("Unknown voice"),
+            item.text.as_deref().unwrap_or("")
+        ),
+        MAX_CHOICE_NAME_LEN,
+    )
+}
+
+async fn autocomplete_history_item(
+    ctx: Context<'_>,
+    partial: &str,
+) -> impl Iterator<Item = AutocompleteChoice> {
+    get_recent_items(&ctx, partial)
+        .await
+        .into_iter()
+        .map(|i| AutocompleteChoice::new(get_choice_name(&i), i.history_item_id))
+}
+
+/// Like `autocomplete_history_item`, but only completes the last of several IDs
+async fn autocomplete_history_items(
+    ctx: Context<'_>,
+    partial: &str,
+) -> impl Iterator<Item = AutocompleteChoice> {
+    let (previous, last) = match partial.rfind(|c: char| c == ',' || c.is_whitespace()) {
+        Some(i) => partial.split_at(i + 1),
+        None => ("", partial),
+    };
+    let previous = previous.to_string();
+    get_recent_items(&ctx, last)
+        .await
+        .into_iter()
+        .map(move |i| {
+            AutocompleteChoice::new(
+                get_choice_name(&i),
+                format!("{}{}", previous, i.history_item_id),
+            )
+        })
+}
@@ -23,7 +23,7 @@ pub async fn join_voice(
     let guild = ctx.guild().ok_or("Not in a guild")?.id;
     let sctx = ctx.serenity_context();
 
-    let manager = songbird::get(&sctx)
+    let manager = songbird::get(sctx)
         .await
         .expect("Songbird Voice client placed in at initialization")
         .clone();
@@ -52,7 +52,7 @@ pub async fn leave_voice(ctx: Context<'_>) -> Result<(), Error> {
     let guild = ctx.guild().ok_or("Not in a guild")?.id;
     let sctx = ctx.serenity_context();
 
-    let manager = songbird::get(&sctx)
+    let manager = songbird::get(sctx)
         .await
         .expect("Songbird Voice client placed in at initialization")
         .clone();
@@ -1,6 +1,16 @@
+pub mod clip;
+pub mod clone_voice;
+pub mod dialogue;
+pub mod history;
 pub mod join_leave;
+pub mod pronunciation;
+pub mod sfx;
 pub mod speak;
+pub mod transcribe;
 pub mod usage;
+pub mod voice_changer;
+pub mod voice_settings;
+pub mod voices;
 
 mod util;
 
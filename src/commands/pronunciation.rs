@@ -0,0 +1,269 @@
+use ::poise::{ChoiceParameter, CreateReply};
+use ::serenity::all::CreateAttachment;
+use log::{error, info};
+
+use crate::elevenlabs::pronunciation::{PhonemeAlphabet, PronunciationRule};
+use crate::pronunciation_registry::PronunciationRegistry;
+use crate::types::{Context, Error};
+
+// Leaves room for the header within Discord's 2000 character message limit
+const MAX_INLINE_RULES_LEN: usize = 1800;
+
+/// Teaches the voices how to say names and game terms in this server
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    subcommands("alias", "phoneme", "remove", "list")
+)]
+pub async fn pronunciation(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Says something else instead of a word, e.g. "GG" as "good game"
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "ADMINISTRATOR",
+    default_member_permissions = "ADMINISTRATOR"
+)]
+async fn alias(
+    ctx: Context<'_>,
+    #[description = "Word or phrase to replace, matched case-sensitively"] word: String,
+    #[description = "What to say instead"] say_as: String,
+) -> Result<(), Error> {
+    add_rule(
+        ctx,
+        PronunciationRule::Alias {
+            string_to_replace: word.trim().to_string(),
+            alias: say_as.trim().to_string(),
+        },
+    )
+    .await
+}
+
+/// Says a word with exact sounds. Only the Flash, Turbo and English v1 models support this.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "ADMINISTRATOR",
+    default_member_permissions = "ADMINISTRATOR"
+)]
+async fn phoneme(
+    ctx: Context<'_>,
+    #[description = "Word to pronounce, matched case-sensitively"] word: String,
+    #[description = "How to pronounce it, e.g. \"ˈtʃeɪnsɔː\" in IPA"] phoneme: String,
+    #[description = "Phonetic alphabet the pronunciation is written in (default IPA)"]
+    alphabet: Option<PhonemeAlphabet>,
+) -> Result<(), Error> {
+    add_rule(
+        ctx,
+        PronunciationRule::Phoneme {
+            string_to_replace: word.trim().to_string(),
+            phoneme: phoneme.trim().to_string(),
+            alphabet: alphabet.unwrap_or(PhonemeAlphabet::Ipa),
+        },
+    )
+    .await
+}
+
+/// Forgets how to pronounce a word
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "ADMINISTRATOR",
+    default_member_permissions = "ADMINISTRATOR"
+)]
+async fn remove(
+    ctx: Context<'_>,
+    #[description = "Word or phrase to forget"] word: String,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Err("Not in a guild".into());
+    };
+    let word = word.trim().to_string();
+    ctx.defer().await?;
+
+    let data = ctx.data();
+    let edit_lock = data.pronunciations.read().await.get_edit_lock(guild_id);
+    let _editing = edit_lock.lock().await;
+    let locator = data.pronunciations.read().await.get(guild_id).cloned();
+    let Some(locator) = locator else {
+        ctx.send(CreateReply::default().content("This server has no pronunciations yet"))
+            .await?;
+        return Ok(());
+    };
+
+    let rules = match data.client.get_pronunciation_rules(&locator).await {
+        Ok(r) => r,
+        Err(e) => {
+            ctx.send(
+                CreateReply::default().content(format!("Failed to get pronunciations: {}", e)),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    if !rules.iter().any(|r| r.get_string_to_replace() == word) {
+        ctx.send(
+            CreateReply::default().content(format!("There's no pronunciation for \"{}\"", word)),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    info!(guild_id = guild_id.get(), word = word.as_str(); "Removing pronunciation");
+    let content = match data
+        .client
+        .remove_pronunciation_rules(&locator.pronunciation_dictionary_id, vec![word.clone()])
+        .await
+    {
+        Ok(locator) => {
+            data.pronunciations.write().await.set(guild_id, locator);
+            format!("Removed the pronunciation for \"{}\"", word)
+        }
+        Err(e) => {
+            error!(guild_id = guild_id.get(), error = e.to_string().as_str(); "Failed to remove pronunciation");
+            format!("Failed to remove pronunciation: {}", e)
+        }
+    };
+    ctx.send(CreateReply::default().content(content)).await?;
+
+    Ok(())
+}
+
+/// Lists the pronunciations set up in this server
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn list(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Err("Not in a guild".into());
+    };
+    let locator = ctx
+        .data()
+        .pronunciations
+        .read()
+        .await
+        .get(guild_id)
+        .cloned();
+    let Some(locator) = locator else {
+        ctx.send(CreateReply::default().content("This server has no pronunciations yet"))
+            .await?;
+        return Ok(());
+    };
+    ctx.defer().await?;
+
+    let mut rules = match ctx.data().client.get_pronunciation_rules(&locator).await {
+        Ok(r) => r,
+        Err(e) => {
+            ctx.send(
+                CreateReply::default().content(format!("Failed to get pronunciations: {}", e)),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    if rules.is_empty() {
+        ctx.send(CreateReply::default().content("This server has no pronunciations yet"))
+            .await?;
+        return Ok(());
+    }
+    rules.sort_by_key(|r| r.get_string_to_replace().to_lowercase());
+
+    let listing = rules
+        .iter()
+        .map(describe_rule)
+        .collect::<Vec<_>>()
+        .join("\n");
+    let header = format!("**Pronunciations** ({})", rules.len());
+    let reply = if listing.len() > MAX_INLINE_RULES_LEN {
+        CreateReply::default()
+            .content(format!(
+                "{}\n\nToo many to post, see the attached file",
+                header
+            ))
+            .attachment(CreateAttachment::bytes(
+                listing.into_bytes(),
+                "Pronunciations.txt",
+            ))
+    } else {
+        CreateReply::default().content(format!("{}\n{}", header, listing))
+    };
+    ctx.send(reply).await?;
+
+    Ok(())
+}
+
+/// Adds the rule to the server's dictionary, creating the dictionary if it's the first one
+async fn add_rule(ctx: Context<'_>, rule: PronunciationRule) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Err("Not in a guild".into());
+    };
+    let word = rule.get_string_to_replace().to_string();
+    if word.is_empty() {
+        ctx.send(CreateReply::default().content("The word can't be empty"))
+            .await?;
+        return Ok(());
+    }
+    ctx.defer().await?;
+
+    let data = ctx.data();
+    let edit_lock = data.pronunciations.read().await.get_edit_lock(guild_id);
+    let _editing = edit_lock.lock().await;
+    let locator = data.pronunciations.read().await.get(guild_id).cloned();
+    info!(guild_id = guild_id.get(), word = word.as_str(); "Adding pronunciation");
+    let added = match locator {
+        Some(locator) => {
+            data.client
+                .add_pronunciation_rules(&locator.pronunciation_dictionary_id, vec![rule])
+                .await
+        }
+        None => {
+            let description = ctx
+                .guild()
+                .map(|g| format!("Pronunciations for the Discord server \"{}\"", g.name));
+            data.client
+                .create_pronunciation_dictionary(
+                    PronunciationRegistry::get_dictionary_name(guild_id),
+                    vec![rule],
+                    description,
+                )
+                .await
+        }
+    };
+
+    let content = match added {
+        Ok(locator) => {
+            data.pronunciations.write().await.set(guild_id, locator);
+            format!("Saved the pronunciation for \"{}\"", word)
+        }
+        Err(e) => {
+            error!(guild_id = guild_id.get(), error = e.to_string().as_str(); "Failed to add pronunciation");
+            format!("Failed to add pronunciation: {}", e)
+        }
+    };
+    ctx.send(CreateReply::default().content(content)).await?;
+
+    Ok(())
+}
+
+fn describe_rule(rule: &PronunciationRule) -> String {
+    match rule {
+        PronunciationRule::Alias {
+            string_to_replace,
+            alias,
+        } => format!("**{}** → \"{}\"", string_to_replace, alias),
+        PronunciationRule::Phoneme {
+            string_to_replace,
+            phoneme,
+            alphabet,
+        } => format!(
+            "**{}** → /{}/ ({})",
+            string_to_replace,
+            phoneme,
+            alphabet.name()
+        ),
+    }
+}
@@ -0,0 +1,111 @@
+use ::poise::CreateReply;
+use ::serenity::all::CreateAttachment;
+use log::{error, info};
+use serenity::all::EditMessage;
+
+use crate::commands::util::{
+    autocomplete_output_format, get_channel_name, get_clip_source, get_voice_handler,
+    resolve_output_format,
+};
+use crate::streamutil::write_stream_to_vec_u8;
+use crate::types::{Context, Error};
+
+// What sound effects are listed under on the soundboard, where spoken lines show their voice
+const SFX_CLIP_VOICE: &str = "Sound effect";
+
+/// Generates a sound effect from a description, e.g. "crowd cheering" or "game show buzzer"
+#[poise::command(slash_command, prefix_command)]
+pub async fn sfx(
+    ctx: Context<'_>,
+    #[description = "Description of the sound"] prompt: String,
+    #[description = "Length in seconds (0.5 to 30). Picked automatically if left out."]
+    #[min = 0.5]
+    #[max = 30.0]
+    duration: Option<f32>,
+    #[description = "How closely to follow the prompt, from 0 (creative) to 1 (literal)"]
+    #[min = 0.0]
+    #[max = 1.0]
+    prompt_influence: Option<f32>,
+    #[description = "Play it in the currently joined voice channel instead of just posting it"]
+    play_in_voice: Option<bool>,
+    #[description = "Audio format to generate"]
+    #[autocomplete = "autocomplete_output_format"]
+    format: Option<String>,
+) -> Result<(), Error> {
+    let format = match resolve_output_format(format) {
+        Ok(f) => f,
+        Err(msg) => {
+            ctx.send(CreateReply::default().content(msg)).await?;
+            return Ok(());
+        }
+    };
+    let voice = if play_in_voice.unwrap_or(false) {
+        match get_voice_handler(&ctx).await? {
+            Some(v) => Some(v),
+            None => {
+                ctx.send(CreateReply::default().content("Not in a voice channel"))
+                    .await?;
+                return Ok(());
+            }
+        }
+    } else {
+        None
+    };
+
+    let sent_msg_handle = ctx
+        .send(CreateReply::default().content("Generating sound effect..."))
+        .await?;
+    let mut sent_msg = sent_msg_handle.into_message().await.map_err(|e| {
+        error!(error = e.to_string().as_str(); "Failed to convert message to Message");
+        Error::from(e)
+    })?;
+
+    info!(prompt = prompt.as_str(); "Generating sound effect");
+    let generated = match ctx
+        .data()
+        .client
+        .generate_sound_effect(prompt.clone(), duration, prompt_influence, Some(format))
+        .await
+    {
+        Ok(stream) => write_stream_to_vec_u8(stream).await,
+        Err(e) => Err(e.into()),
+    };
+    let bytes = match generated {
+        Err(e) => {
+            error!(prompt = prompt.as_str(), error = e.to_string().as_str(); "Failed to generate sound effect");
+            ctx.send(
+                CreateReply::default().content(format!("Failed to generate sound effect: {}", e)),
+            )
+            .await?;
+            return Ok(());
+        }
+        Ok(b) => format.to_playable(b),
+    };
+
+    let content = match voice {
+        Some((handler_lock, channel)) => {
+            let _ = handler_lock.lock().await.play_input(bytes.clone().into());
+            format!(
+                "Playing sound effect in channel \"{}\"",
+                get_channel_name(&ctx, channel)?
+            )
+        }
+        None => "Generated sound effect".to_string(),
+    };
+    sent_msg
+        .edit(
+            ctx.http(),
+            EditMessage::default()
+                .new_attachment(CreateAttachment::bytes(
+                    bytes,
+                    format!("Sound effect.{}", format.get_file_extension()),
+                ))
+                .content(content),
+        )
+        .await?;
+    ctx.data()
+        .recent_clips
+        .record(sent_msg.id, get_clip_source(&ctx, SFX_CLIP_VOICE, &prompt));
+
+    Ok(())
+}
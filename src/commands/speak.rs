@@ -1,26 +1,100 @@
 use ::poise::CreateReply;
 use ::serenity::all::CreateAttachment;
+use ::songbird::input::{AudioStream, Input, LiveInput};
+use ::symphonia::core::probe::Hint;
+use bytes::Bytes;
 use log::{error, info};
+use rand::Rng;
 use serenity::all::EditMessage;
 
-use crate::commands::util::get_channel_name;
-use crate::elevenlabs::ElevenLabs;
-use crate::elevenlabs::media::MP3_44100HZ_128KBPS;
-use crate::elevenlabs::types::{
-    KnownVoice, SpeechModel, SpeechSpeed, VoiceSettings, get_default_speech_model,
+use crate::commands::util::{
+    autocomplete_language, autocomplete_output_format, autocomplete_speech_model,
+    autocomplete_voice, get_channel_name, get_clip_source, get_speech_options, get_voice_handler,
+    resolve_output_format, resolve_voice,
 };
-use crate::streamutil::write_stream_to_vec_u8;
-use crate::types::{Context, Error};
+use crate::elevenlabs::ElevenLabs;
+use crate::elevenlabs::media::OutputFormat;
+use crate::elevenlabs::models::Model;
+use crate::elevenlabs::types::{SpeechOptions, SpeechSpeed, TextNormalization, VoiceSettings};
+use crate::speech_cache::SpeechCache;
+use crate::streamutil::{StreamingSource, tee_stream_to_vec_u8};
+use crate::tts::elevenlabs::is_elevenlabs_provider;
+use crate::tts::{Speech, SpeechRequest, SpeechStream};
+use crate::types::{Context, Data, Error};
+use crate::voice_registry::BotVoice;
 
 /// Generates some speech using the given voice and posts it as a sound snippet
 #[poise::command(slash_command, prefix_command)]
+#[allow(clippy::too_many_arguments)]
 pub async fn speak(
     ctx: Context<'_>,
-    #[description = "Voice to use"] voice: KnownVoice,
+    #[description = "Voice to use"]
+    #[autocomplete = "autocomplete_voice"]
+    voice: String,
     #[description = "Text to speak"] text: String,
     #[description = "Speed of the speech"] speed: Option<SpeechSpeed>,
-    #[description = "Speech model to use"] model: Option<SpeechModel>,
+    #[description = "Speech model to use"]
+    #[autocomplete = "autocomplete_speech_model"]
+    model: Option<String>,
+    #[description = "Also attach captions (.srt) with the timing of each word"] captions: Option<
+        bool,
+    >,
+    #[description = "Audio format to generate"]
+    #[autocomplete = "autocomplete_output_format"]
+    format: Option<String>,
+    #[description = "Seed to reproduce an earlier take, random if not given"] seed: Option<u32>,
+    #[description = "Language to speak, for models that support several"]
+    #[autocomplete = "autocomplete_language"]
+    language: Option<String>,
+    #[description = "Whether to spell out numbers, dates and the like"] normalization: Option<
+        TextNormalization,
+    >,
+    #[description = "Also apply language-specific normalization (slower, e.g. for Japanese)"]
+    language_normalization: Option<bool>,
 ) -> Result<(), Error> {
+    let voice = match resolve_voice(&ctx, &voice).await {
+        Ok(v) => v,
+        Err(msg) => {
+            ctx.send(CreateReply::default().content(msg)).await?;
+            return Ok(());
+        }
+    };
+    let format = match resolve_output_format(format) {
+        Ok(f) => f,
+        Err(msg) => {
+            ctx.send(CreateReply::default().content(msg)).await?;
+            return Ok(());
+        }
+    };
+    let model = match resolve_speech_model(ctx.data(), &voice, model.as_deref(), &text) {
+        Ok(m) => m,
+        Err(msg) => {
+            ctx.send(CreateReply::default().content(msg)).await?;
+            return Ok(());
+        }
+    };
+    let mut options = get_speech_options(&ctx).await;
+    if let Err(msg) = apply_speech_tuning(
+        &mut options,
+        &voice,
+        model,
+        seed,
+        language,
+        normalization,
+        language_normalization,
+    ) {
+        ctx.send(CreateReply::default().content(msg)).await?;
+        return Ok(());
+    }
+    let captions = captions.unwrap_or(false);
+    if captions && !voice.is_elevenlabs() {
+        ctx.send(
+            CreateReply::default().content("Captions only work with ElevenLabs voices for now"),
+        )
+        .await?;
+        return Ok(());
+    }
+
     let sent_msg_handle = ctx
         .send(CreateReply::default().content("Generating voice..."))
         .await?;
@@ -29,7 +103,20 @@ pub async fn speak(
         Error::from(e)
     })?;
 
-    let bytes = match generate_speech_bytes(&ctx.data().client, voice, text, speed, model).await {
+    let data = ctx.data();
+    let source = get_clip_source(&ctx, voice.get_name(), &text);
+    let generated = if captions {
+        let seed = pick_seed(&mut options);
+        generate_speech_with_captions(&data.client, voice, text, speed, model, format, options)
+            .await
+            .map(|(b, srt)| (b, format, srt, format!("with ElevenLabs (seed {})", seed)))
+    } else {
+        generate_speech_bytes(data, voice, text, speed, model, format, options)
+            .await
+            .map(|(s, provenance)| (s.audio, s.format, None, provenance.describe()))
+    };
+    // Not every provider can make every format, so go by what we actually got
+    let (bytes, format, srt, produced_by) = match generated {
         Err(e) => {
             ctx.send(CreateReply::default().content(format!("Failed to generate voice: {}", e)))
                 .await?;
@@ -38,132 +125,506 @@ pub async fn speak(
         Ok(b) => b,
     };
 
-    sent_msg
-        .edit(
-            ctx.http(),
-            EditMessage::default()
-                .new_attachment(CreateAttachment::bytes(
-                    bytes.clone(),
-                    "Generated voice.mp3",
-                ))
-                .content("Generated voice"),
-        )
-        .await?;
+    let mut edit = EditMessage::default()
+        .new_attachment(CreateAttachment::bytes(
+            format.to_playable(bytes),
+            format!("Generated voice.{}", format.get_file_extension()),
+        ))
+        .content(format!("Generated voice {}", produced_by));
+    if let Some(srt) = srt {
+        edit = edit.new_attachment(CreateAttachment::bytes(srt, "Generated voice.srt"));
+    }
+    sent_msg.edit(ctx.http(), edit).await?;
+    data.recent_clips.record(sent_msg.id, source);
 
     Ok(())
 }
 
 /// Generates some speech using the given voice and posts it in the currently joined voice channel
 #[poise::command(slash_command, prefix_command)]
+#[allow(clippy::too_many_arguments)]
 pub async fn speak_vs(
     ctx: Context<'_>,
-    #[description = "Voice to use"] voice: KnownVoice,
+    #[description = "Voice to use"]
+    #[autocomplete = "autocomplete_voice"]
+    voice: String,
     #[description = "Text to speak"] text: String,
     #[description = "Speed of the speech"] speed: Option<SpeechSpeed>,
-    #[description = "Speech model to use"] model: Option<SpeechModel>,
+    #[description = "Speech model to use"]
+    #[autocomplete = "autocomplete_speech_model"]
+    model: Option<String>,
+    #[description = "Audio format to generate"]
+    #[autocomplete = "autocomplete_output_format"]
+    format: Option<String>,
+    #[description = "Seed to reproduce an earlier take, random if not given"] seed: Option<u32>,
+    #[description = "Language to speak, for models that support several"]
+    #[autocomplete = "autocomplete_language"]
+    language: Option<String>,
+    #[description = "Whether to spell out numbers, dates and the like"] normalization: Option<
+        TextNormalization,
+    >,
+    #[description = "Also apply language-specific normalization (slower, e.g. for Japanese)"]
+    language_normalization: Option<bool>,
 ) -> Result<(), Error> {
-    let guild = ctx.guild().ok_or("Not in a guild")?.id;
-    let sctx = ctx.serenity_context();
-    let manager = songbird::get(&sctx)
-        .await
-        .expect("Songbird Voice client placed in at initialization")
-        .clone();
-
-    if let Some(handler_lock) = manager.get(guild) {
-        let mut handler = handler_lock.lock().await;
-
-        if let Some(channel) = handler.current_channel() {
-            let sent_msg_handle = ctx
-                .send(
-                    CreateReply::default().content(
-                        format!(
-                            "Generating voice to speak in channel \"{}\"...",
-                            get_channel_name(&ctx, channel)?
-                        )
-                        .as_str(),
-                    ),
+    let voice = match resolve_voice(&ctx, &voice).await {
+        Ok(v) => v,
+        Err(msg) => {
+            ctx.send(CreateReply::default().content(msg)).await?;
+            return Ok(());
+        }
+    };
+    let format = match resolve_output_format(format) {
+        Ok(f) => f,
+        Err(msg) => {
+            ctx.send(CreateReply::default().content(msg)).await?;
+            return Ok(());
+        }
+    };
+    let model = match resolve_speech_model(ctx.data(), &voice, model.as_deref(), &text) {
+        Ok(m) => m,
+        Err(msg) => {
+            ctx.send(CreateReply::default().content(msg)).await?;
+            return Ok(());
+        }
+    };
+    let mut options = get_speech_options(&ctx).await;
+    if let Err(msg) = apply_speech_tuning(
+        &mut options,
+        &voice,
+        model,
+        seed,
+        language,
+        normalization,
+        language_normalization,
+    ) {
+        ctx.send(CreateReply::default().content(msg)).await?;
+        return Ok(());
+    }
+    let Some((handler_lock, channel)) = get_voice_handler(&ctx).await? else {
+        ctx.send(CreateReply::default().content("Not in a voice channel"))
+            .await?;
+        return Ok(());
+    };
+    let mut handler = handler_lock.lock().await;
+
+    let sent_msg_handle = ctx
+        .send(
+            CreateReply::default().content(
+                format!(
+                    "Generating voice to speak in channel \"{}\"...",
+                    get_channel_name(&ctx, channel)?
+                )
+                .as_str(),
+            ),
+        )
+        .await?;
+    let mut sent_msg = sent_msg_handle.into_message().await.map_err(|e| {
+        error!(error = e.to_string().as_str(); "Failed to convert message to Message");
+        Error::from(e)
+    })?;
+
+    let data = ctx.data();
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    // Consecutive lines from the same voice carry on from each other, so split up announcements flow
+    let stitch = voice.is_elevenlabs() && model.supports_request_stitching();
+    if stitch {
+        data.stitcher
+            .continue_from_previous(guild_id, &voice.get_id(), &mut options);
+    } else {
+        data.stitcher.reset(guild_id);
+    }
+    let (speech, provenance) =
+        match stream_speech(data, &voice, &text, speed, model, format, options).await {
+            Err(e) => {
+                ctx.send(
+                    CreateReply::default().content(format!("Failed to generate voice: {}", e)),
                 )
                 .await?;
-            let mut sent_msg = sent_msg_handle.into_message().await.map_err(|e| {
-                error!(error = e.to_string().as_str(); "Failed to convert message to Message");
-                Error::from(e)
-            })?;
-
-            let bytes = match generate_speech_bytes(&ctx.data().client, voice, text, speed, model)
-                .await
-            {
-                Err(e) => {
-                    ctx.send(
-                        CreateReply::default().content(format!("Failed to generate voice: {}", e)),
-                    )
-                    .await?;
-                    return Ok(());
+                return Ok(());
+            }
+            Ok((s, provenance)) => {
+                // Another provider's request IDs mean nothing to the voice's own
+                if stitch && !provenance.fell_back {
+                    data.stitcher
+                        .record(guild_id, &voice.get_id(), s.request_id.clone(), &text);
+                } else if stitch {
+                    data.stitcher.reset(guild_id);
                 }
-                Ok(b) => b,
-            };
-            sent_msg
-                .edit(
-                    ctx.http(),
-                    EditMessage::default()
-                        .new_attachment(CreateAttachment::bytes(
-                            bytes.clone(),
-                            "Generated voice.mp3",
-                        ))
-                        .content(
-                            format!(
-                                "Speaking in channel \"{}\"",
-                                get_channel_name(&ctx, channel)?
-                            )
-                            .as_str(),
-                        ),
+                (s, provenance)
+            }
+        };
+    let format = speech.format;
+
+    // Start playing as soon as the first chunk arrives, rather than waiting for the whole clip
+    let (mut source, sender) = StreamingSource::new();
+    if let Some(header) = format.get_wav_header(None) {
+        source = source.with_header(header);
+    }
+    let mut hint = Hint::new();
+    hint.with_extension(format.get_file_extension());
+    let input = Input::Live(
+        LiveInput::Raw(AudioStream {
+            input: Box::new(source),
+            hint: Some(hint),
+        }),
+        None,
+    );
+    let _ = handler.play_input(input);
+
+    sent_msg
+        .edit(
+            ctx.http(),
+            EditMessage::default().content(
+                format!(
+                    "Speaking in channel \"{}\" {}",
+                    get_channel_name(&ctx, channel)?,
+                    provenance.describe()
                 )
-                .await?;
+                .as_str(),
+            ),
+        )
+        .await?;
 
-            let _ = handler.play_input(bytes.into());
-        } else {
-            ctx.send(CreateReply::default().content("Not in a voice channel"))
+    // Once everything has arrived, also post the full clip
+    let bytes = match tee_stream_to_vec_u8(speech.audio, sender).await {
+        Err(e) => {
+            error!(
+                voice = voice, text = text.as_str(), error = e.to_string().as_str();
+                "Failed to stream generated voice",
+            );
+            ctx.send(CreateReply::default().content(format!("Failed to generate voice: {}", e)))
                 .await?;
             return Ok(());
         }
+        Ok(b) => b,
+    };
+    if let Some((key, request)) = &provenance.to_cache {
+        data.cache.insert(key, request, format, &bytes).await;
+    }
+    sent_msg
+        .edit(
+            ctx.http(),
+            EditMessage::default().new_attachment(CreateAttachment::bytes(
+                format.to_playable(bytes),
+                format!("Generated voice.{}", format.get_file_extension()),
+            )),
+        )
+        .await?;
+    data.recent_clips
+        .record(sent_msg.id, get_clip_source(&ctx, voice.get_name(), &text));
+
+    Ok(())
+}
+
+/// The model to generate with. Only ElevenLabs voices use one, so for any other voice it isn't checked against
+/// the text. The error is meant to be shown to the user.
+pub fn resolve_speech_model<'a>(
+    data: &'a Data,
+    voice: &BotVoice,
+    model_id: Option<&str>,
+    text: &str,
+) -> Result<&'a Model, String> {
+    if voice.is_elevenlabs() {
+        data.models.resolve_speech_model(model_id, text)
     } else {
-        ctx.send(CreateReply::default().content("Not in a voice channel (failed to get manager)"))
-            .await?;
+        Ok(data.models.get_default_model())
     }
+}
 
+/// Adds the options picked in the command to the server's speech options. The error is meant to be shown to
+/// the user.
+fn apply_speech_tuning(
+    options: &mut SpeechOptions,
+    voice: &BotVoice,
+    model: &Model,
+    seed: Option<u32>,
+    language: Option<String>,
+    normalization: Option<TextNormalization>,
+    language_normalization: Option<bool>,
+) -> Result<(), String> {
+    if let Some(language) = language.as_deref().map(str::trim).filter(|l| !l.is_empty()) {
+        // Other providers have no list of languages to check against
+        if voice.is_elevenlabs() && !model.supports_language(language) {
+            return Err(format!(
+                "{} can't speak \"{}\". It supports: {}",
+                model.name,
+                language,
+                model
+                    .languages
+                    .iter()
+                    .map(|l| l.language_id.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        options.language_code = Some(language.to_lowercase());
+    }
+    // Only ElevenLabs takes a seed, so for anything else it would just split up the cache
+    options.seed = seed.filter(|_| voice.is_elevenlabs());
+    options.apply_text_normalization = normalization;
+    options.apply_language_text_normalization = language_normalization;
     Ok(())
 }
 
-async fn generate_speech_bytes(
+/// The seed to generate with, picking a random one if none was asked for. ElevenLabs always gets one, and it's
+/// shown, so a take someone liked can be made again.
+fn pick_seed(options: &mut SpeechOptions) -> u32 {
+    *options.seed.get_or_insert_with(|| rand::rng().random())
+}
+
+/// Where a clip came from, for the reply
+pub struct Provenance {
+    /// E.g. "with ElevenLabs", or "from the cache"
+    produced_by: String,
+    seed: Option<u32>,
+    /// Whether another provider had to stand in for the voice's own
+    fell_back: bool,
+    /// The cache key and request to store a streamed clip under once it's all arrived
+    to_cache: Option<(String, SpeechRequest)>,
+}
+
+impl Provenance {
+    fn cached(seed: Option<u32>) -> Self {
+        Self {
+            produced_by: "from the cache".to_string(),
+            seed,
+            fell_back: false,
+            to_cache: None,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self.seed {
+            Some(seed) => format!("{} (seed {})", self.produced_by, seed),
+            None => self.produced_by.clone(),
+        }
+    }
+}
+
+/// The voice's stored settings from ElevenLabs (as tuned with `/voice_settings`), with the requested speed.
+/// Falls back to our local defaults if they can't be fetched, since that shouldn't stop anyone from speaking.
+async fn get_voice_settings(
     client: &ElevenLabs,
-    voice: KnownVoice,
+    voice: &BotVoice,
+    speed: Option<SpeechSpeed>,
+) -> VoiceSettings {
+    let defaults = voice.get_default_voice_settings();
+    // Only ElevenLabs keeps settings for its voices
+    if !voice.is_elevenlabs() {
+        return VoiceSettings {
+            speed: Some(voice.get_speed(speed)),
+            ..defaults
+        };
+    }
+    let stored = match client.get_cached_voice_settings(&voice.get_id()).await {
+        Ok(s) => s,
+        Err(e) => {
+            error!(voice = voice, error = e.to_string().as_str(); "Failed to get stored voice settings, using defaults");
+            defaults.clone()
+        }
+    };
+
+    VoiceSettings {
+        style: stored.style.or(defaults.style),
+        speed: Some(voice.get_speed(speed)),
+        ..stored
+    }
+}
+
+/// What to ask the voice's provider for. Only ElevenLabs voices get the model, the other providers have their own.
+async fn build_speech_request(
+    data: &Data,
+    voice: &BotVoice,
+    text: &str,
+    speed: Option<SpeechSpeed>,
+    model: &Model,
+    format: &'static OutputFormat,
+    options: SpeechOptions,
+) -> SpeechRequest {
+    SpeechRequest {
+        voice_id: voice.get_id(),
+        text: text.to_string(),
+        voice_settings: Some(get_voice_settings(&data.client, voice, speed).await),
+        model_id: voice.is_elevenlabs().then(|| model.model_id.clone()),
+        format,
+        options,
+    }
+}
+
+async fn stream_speech<'a>(
+    data: &'a Data,
+    voice: &BotVoice,
+    text: &str,
+    speed: Option<SpeechSpeed>,
+    model: &Model,
+    format: &'static OutputFormat,
+    options: SpeechOptions,
+) -> Result<(SpeechStream<'a>, Provenance), Error> {
+    info!(
+        voice = voice, speed = speed, text = text, provider = voice.get_provider_id();
+        "Streaming text"
+    );
+
+    let mut request = build_speech_request(data, voice, text, speed, model, format, options).await;
+    // Taken before the seed is picked, so lines without one reuse whichever take is cached
+    let cache_key = SpeechCache::get_key(voice.get_provider_id(), &request);
+    if let Some(key) = &cache_key
+        && let Some(cached) = data.cache.get(key).await
+    {
+        info!(voice = voice, text = text; "Using cached speech");
+        let audio = Bytes::from(cached.audio);
+        return Ok((
+            SpeechStream {
+                request_id: None,
+                format: cached.format,
+                audio: Box::pin(futures::stream::once(async move { Ok(audio) })),
+            },
+            Provenance::cached(cached.seed),
+        ));
+    }
+
+    let seed = voice
+        .is_elevenlabs()
+        .then(|| pick_seed(&mut request.options));
+    let streamed = data
+        .fallback
+        .run(
+            &data.providers,
+            voice,
+            request.clone(),
+            |provider, request| provider.stream(request),
+        )
+        .await;
+    let (speech, producer) = streamed.map_err(|e| {
+        error!(
+            voice = voice, speed = speed, text = text, error = e.to_string().as_str();
+            "Failed to stream text",
+        );
+        Error::from(e)
+    })?;
+    let fell_back = producer.fell_back_from.is_some();
+    Ok((
+        speech,
+        Provenance {
+            produced_by: producer.describe(),
+            // Other providers ignore the seed, so it wouldn't get the same take again
+            seed: seed.filter(|_| is_elevenlabs_provider(&producer.provider_id)),
+            fell_back,
+            // Stand-ins from further down the chain aren't what was asked for, so they're not kept
+            to_cache: cache_key.filter(|_| !fell_back).map(|key| (key, request)),
+        },
+    ))
+}
+
+pub async fn generate_speech_bytes(
+    data: &Data,
+    voice: BotVoice,
     text: String,
     speed: Option<SpeechSpeed>,
-    model: Option<SpeechModel>,
-) -> Result<Vec<u8>, Error> {
+    model: &Model,
+    format: &'static OutputFormat,
+    options: SpeechOptions,
+) -> Result<(Speech, Provenance), Error> {
     info!(
-        voice = voice, speed = speed, text = text.as_str();
+        voice = voice, speed = speed, text = text.as_str(), provider = voice.get_provider_id();
         "Generating text"
     );
 
-    Ok(write_stream_to_vec_u8(
-        client
-            .generate_voice(
-                voice.get_id(),
-                text.clone(),
-                Some(VoiceSettings {
-                    speed: Some(voice.get_speed(speed)),
-                    ..voice.get_default_voice_settings()
-                }),
-                model.or_else(get_default_speech_model),
-                Some(MP3_44100HZ_128KBPS),
-            )
-            .await?,
-    )
-    .await
-    .inspect_err(|e| {
+    let mut request =
+        build_speech_request(data, &voice, &text, speed, model, format, options).await;
+    // Taken before the seed is picked, so lines without one reuse whichever take is cached
+    let cache_key = SpeechCache::get_key(voice.get_provider_id(), &request);
+    if let Some(key) = &cache_key
+        && let Some(cached) = data.cache.get(key).await
+    {
+        info!(voice = voice, text = text.as_str(); "Using cached speech");
+        return Ok((
+            Speech {
+                request_id: None,
+                format: cached.format,
+                audio: cached.audio,
+            },
+            Provenance::cached(cached.seed),
+        ));
+    }
+
+    let seed = voice
+        .is_elevenlabs()
+        .then(|| pick_seed(&mut request.options));
+    let generated = data
+        .fallback
+        .run(
+            &data.providers,
+            &voice,
+            request.clone(),
+            |provider, request| provider.generate(request),
+        )
+        .await;
+    let (speech, producer) = generated.map_err(|e| {
         error!(
             voice = voice, speed = speed, text = text.as_str(), error = e.to_string().as_str();
             "Failed to generate text",
         );
-    })?)
+        Error::from(e)
+    })?;
+    let fell_back = producer.fell_back_from.is_some();
+    // Stand-ins from further down the chain aren't what was asked for, so they're not kept
+    if let Some(key) = cache_key.filter(|_| !fell_back) {
+        data.cache
+            .insert(&key, &request, speech.format, &speech.audio)
+            .await;
+    }
+    Ok((
+        speech,
+        Provenance {
+            produced_by: producer.describe(),
+            // Other providers ignore the seed, so it wouldn't get the same take again
+            seed: seed.filter(|_| is_elevenlabs_provider(&producer.provider_id)),
+            fell_back,
+            to_cache: None,
+        },
+    ))
+}
+
+// How many words to show per caption when captions are requested
+const WORDS_PER_CAPTION: usize = 6;
+
+/// Generates speech plus SRT captions for it, if ElevenLabs returned timing information
+async fn generate_speech_with_captions(
+    client: &ElevenLabs,
+    voice: BotVoice,
+    text: String,
+    speed: Option<SpeechSpeed>,
+    model: &Model,
+    format: &OutputFormat,
+    options: SpeechOptions,
+) -> Result<(Vec<u8>, Option<String>), Error> {
+    info!(
+        voice = voice, speed = speed, text = text.as_str();
+        "Generating text with timestamps"
+    );
+
+    let speech = client
+        .generate_voice_with_timestamps(
+            voice.get_id(),
+            text.clone(),
+            Some(get_voice_settings(client, &voice, speed).await),
+            Some(model.model_id.clone()),
+            Some(format),
+            options,
+        )
+        .await
+        .inspect_err(|e| {
+            error!(
+                voice = voice, speed = speed, text = text.as_str(), error = e.to_string().as_str();
+                "Failed to generate text with timestamps",
+            );
+        })?;
+
+    // Prefer the original text's alignment so the captions match what was typed
+    let srt = speech
+        .alignment
+        .or(speech.normalized_alignment)
+        .map(|a| a.to_srt(WORDS_PER_CAPTION));
+    Ok((speech.audio, srt))
 }
@@ -0,0 +1,237 @@
+use ::poise::CreateReply;
+use ::serenity::all::{Attachment, CreateAttachment, Message};
+use log::{error, info};
+
+use crate::commands::util::{MAX_CLIP_SIZE, is_audio_attachment};
+use crate::elevenlabs::types::{Transcription, TranscriptionOptions};
+use crate::types::{Context, Error};
+
+// Leaves room for the header within Discord's 2000 character message limit
+const MAX_INLINE_TRANSCRIPT_LEN: usize = 1800;
+
+/// Transcribes an audio recording or voice message
+#[poise::command(slash_command, prefix_command)]
+pub async fn transcribe(
+    ctx: Context<'_>,
+    #[description = "Recording to transcribe"] clip: Attachment,
+    #[description = "Tell apart who's speaking when"] diarize: Option<bool>,
+    #[description = "Show when each part was said"] timestamps: Option<bool>,
+    #[description = "Language code of the recording (e.g. en, de), detected if left out"]
+    language: Option<String>,
+) -> Result<(), Error> {
+    let options = TranscriptionOptions {
+        model_id: None,
+        language_code: language,
+        diarize: diarize.unwrap_or(false),
+        timestamps: timestamps.unwrap_or(false),
+    };
+    reply_with_transcription(ctx, &clip, options).await
+}
+
+/// Transcribes the first recording or voice message attached to a message
+#[poise::command(context_menu_command = "Transcribe")]
+pub async fn transcribe_message(
+    ctx: Context<'_>,
+    #[description = "Message with a recording"] msg: Message,
+) -> Result<(), Error> {
+    let Some(clip) = msg.attachments.iter().find(|a| is_audio_attachment(a)) else {
+        ctx.send(
+            CreateReply::default()
+                .content("That message has no recording to transcribe")
+                .ephemeral(true),
+        )
+        .await?;
+        return Ok(());
+    };
+    reply_with_transcription(ctx, clip, TranscriptionOptions::default()).await
+}
+
+async fn reply_with_transcription(
+    ctx: Context<'_>,
+    clip: &Attachment,
+    options: TranscriptionOptions,
+) -> Result<(), Error> {
+    if !is_audio_attachment(clip) {
+        ctx.send(CreateReply::default().content("That doesn't look like an audio recording"))
+            .await?;
+        return Ok(());
+    }
+    if clip.size > MAX_CLIP_SIZE {
+        ctx.send(CreateReply::default().content(format!(
+            "That recording is too big, the limit is {}MB",
+            MAX_CLIP_SIZE / 1024 / 1024
+        )))
+        .await?;
+        return Ok(());
+    }
+    ctx.defer().await?;
+
+    let audio = clip.download().await?;
+    info!(filename = clip.filename.as_str(), size = audio.len(); "Transcribing audio");
+    let show_details = options.diarize || options.timestamps;
+    let transcription = match ctx
+        .data()
+        .client
+        .transcribe(audio, clip.filename.clone(), &options)
+        .await
+    {
+        Ok(t) => t,
+        Err(e) => {
+            error!(filename = clip.filename.as_str(), error = e.to_string().as_str(); "Failed to transcribe audio");
+            ctx.send(CreateReply::default().content(format!("Failed to transcribe: {}", e)))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let header = describe_language(&transcription);
+    let transcript = if show_details {
+        format_segments(&transcription, &options)
+    } else {
+        transcription.text.trim().to_string()
+    };
+
+    let reply = if transcript.is_empty() {
+        CreateReply::default().content(format!("{}\n\n*No speech found*", header))
+    } else if transcript.len() > MAX_INLINE_TRANSCRIPT_LEN {
+        CreateReply::default()
+            .content(format!(
+                "{}\n\nToo long to post, see the attached file",
+                header
+            ))
+            .attachment(CreateAttachment::bytes(
+                transcript.into_bytes(),
+                "Transcription.txt",
+            ))
+    } else {
+        CreateReply::default().content(format!("{}\n\n{}", header, transcript))
+    };
+    ctx.send(reply).await?;
+
+    Ok(())
+}
+
+fn describe_language(transcription: &Transcription) -> String {
+    match (
+        &transcription.language_code,
+        transcription.language_probability,
+    ) {
+        (Some(code), Some(probability)) => format!(
+            "**Transcription** (language: {}, {:.0}% sure)",
+            code,
+            probability * 100.0
+        ),
+        (Some(code), None) => format!("**Transcription** (language: {})", code),
+        _ => "**Transcription**".to_string(),
+    }
+}
+
+/// One line per speaker change, e.g. "[1:05] Speaker 2: Hello"
+fn format_segments(transcription: &Transcription, options: &TranscriptionOptions) -> String {
+    transcription
+        .segments()
+        .iter()
+        .map(|segment| {
+            let mut line = String::new();
+            if options.timestamps
+                && let Some(start) = segment.start_seconds
+            {
+                let seconds = start as u64;
+                line.push_str(&format!("[{}:{:02}] ", seconds / 60, seconds % 60));
+            }
+            if options.diarize
+                && let Some(speaker) = &segment.speaker_id
+            {
+                line.push_str(&format!("**{}**: ", describe_speaker(speaker)));
+            }
+            line.push_str(&segment.text);
+            line
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Turns the API's zero-based "speaker_0" into "Speaker 1"
+fn describe_speaker(speaker_id: &str) -> String {
+    match speaker_id
+        .strip_prefix("speaker_")
+        .and_then(|n| n.parse::<u32>().ok())
+    {
+        Some(n) => format!("Speaker {}", n + 1),
+        None => speaker_id.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elevenlabs::types::TranscribedWord;
+
+    fn word(text: &str, start: f64, speaker: &str) -> TranscribedWord {
+        TranscribedWord {
+            text: text.to_string(),
+            start: Some(start),
+            end: Some(start + 0.5),
+            word_type: Some(if text == " " { "spacing" } else { "word" }.to_string()),
+            speaker_id: Some(speaker.to_string()),
+        }
+    }
+
+    fn transcription() -> Transcription {
+        Transcription {
+            language_code: Some("en".to_string()),
+            language_probability: Some(0.5),
+            text: "Hi there. Hello".to_string(),
+            words: vec![
+                word("Hi", 2.0, "speaker_0"),
+                word(" ", 2.5, "speaker_0"),
+                word("there.", 3.0, "speaker_0"),
+                word(" ", 3.5, "speaker_0"),
+                word("Hello", 65.4, "speaker_1"),
+            ],
+        }
+    }
+
+    #[test]
+    fn formats_timestamps_and_speakers() {
+        let options = TranscriptionOptions {
+            diarize: true,
+            timestamps: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            format_segments(&transcription(), &options),
+            "[0:02] **Speaker 1**: Hi there.\n[1:05] **Speaker 2**: Hello"
+        );
+    }
+
+    #[test]
+    fn leaves_out_what_was_not_asked_for() {
+        let timestamps = TranscriptionOptions {
+            timestamps: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            format_segments(&transcription(), &timestamps),
+            "[0:02] Hi there.\n[1:05] Hello"
+        );
+        let diarize = TranscriptionOptions {
+            diarize: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            format_segments(&transcription(), &diarize),
+            "**Speaker 1**: Hi there.\n**Speaker 2**: Hello"
+        );
+    }
+
+    #[test]
+    fn describes_speakers_and_language() {
+        assert_eq!(describe_speaker("speaker_0"), "Speaker 1");
+        assert_eq!(describe_speaker("narrator"), "narrator");
+        assert_eq!(
+            describe_language(&transcription()),
+            "**Transcription** (language: en, 50% sure)"
+        );
+    }
+}
@@ -1,16 +1,193 @@
+use crate::elevenlabs::types::{CharacterStats, UsageBreakdown};
 use crate::types::{Context, Error};
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Local, Utc};
 use ::poise::CreateReply;
+use ::serenity::all::CreateAttachment;
 
+// Days shown when only a breakdown is asked for
+const DEFAULT_USAGE_DAYS: u32 = 7;
+// Leaves room for the summary within Discord's 2000 character message limit
+const MAX_INLINE_TABLE_LEN: usize = 1700;
+
+/// Shows the character usage of each speech provider, and ElevenLabs' day by day
 #[poise::command(slash_command, prefix_command)]
-pub async fn show_usage(ctx: Context<'_>) -> Result<(), Error> {
-    let usage = &ctx.data().client.get_usage().await?;
-    ctx.send(CreateReply::default().content(format!(
-        "**Current ElevenLabs API Usage**\nUsed characters: {} of {}\nResets on: {}",
-        usage.0, usage.1,
-        DateTime::<Local>::from(usage.2.unwrap_or(DateTime::from_timestamp_nanos(0))).format("%B %d %Y at %r (%Z)")
-    )))
-    .await?;
+pub async fn show_usage(
+    ctx: Context<'_>,
+    #[description = "Also show the characters used each day over this many days"]
+    #[min = 1]
+    #[max = 90]
+    days: Option<u32>,
+    #[description = "Also split the usage over those days by voice or model"] breakdown: Option<
+        UsageBreakdown,
+    >,
+) -> Result<(), Error> {
+    let summary = get_usage_summary(ctx).await;
+    if days.is_none() && breakdown.is_none() {
+        ctx.send(CreateReply::default().content(summary)).await?;
+        return Ok(());
+    }
+
+    let days = days.unwrap_or(DEFAULT_USAGE_DAYS);
+    let breakdown = breakdown.unwrap_or(UsageBreakdown::None);
+    ctx.defer().await?;
+
+    let end = Utc::now();
+    let start = (end.date_naive() - chrono::Days::new(u64::from(days) - 1))
+        .and_hms_opt(0, 0, 0)
+        .expect("midnight is a valid time")
+        .and_utc();
+    let stats = match ctx
+        .data()
+        .client
+        .get_character_stats(start, end, breakdown)
+        .await
+    {
+        Ok(s) => s,
+        Err(e) => {
+            ctx.send(CreateReply::default().content(format!(
+                "{}\n\nFailed to get the daily usage: {}",
+                summary, e
+            )))
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let mut tables = format_daily_table(&stats, days);
+    if breakdown != UsageBreakdown::None {
+        tables.push_str(&format!(
+            "\n\n**By {}**\n{}",
+            breakdown.get_id(),
+            format_breakdown_table(ctx, &stats, breakdown).await
+        ));
+    }
+
+    let reply = if tables.len() > MAX_INLINE_TABLE_LEN {
+        CreateReply::default()
+            .content(format!(
+                "{}\n\nThe usage breakdown is too long to post, see the attached file",
+                summary
+            ))
+            .attachment(CreateAttachment::bytes(
+                tables.replace("```", "").into_bytes(),
+                "Usage.txt",
+            ))
+    } else {
+        CreateReply::default().content(format!("{}\n\n{}", summary, tables))
+    };
+    ctx.send(reply).await?;
 
     Ok(())
 }
+
+/// Every provider's quota, or a note that it doesn't have one
+async fn get_usage_summary(ctx: Context<'_>) -> String {
+    let mut sections = Vec::new();
+    for provider in ctx.data().providers.all() {
+        let section = match provider.get_usage().await {
+            Ok(Some(usage)) => {
+                let mut section = format!(
+                    "**Current {} API Usage**\nUsed characters: {} of {}",
+                    provider.name(),
+                    usage.used_characters,
+                    usage
+                        .character_limit
+                        .map(|l| l.to_string())
+                        .unwrap_or_else(|| "unlimited".to_string())
+                );
+                if let Some(resets_at) = usage.resets_at {
+                    section.push_str(&format!(
+                        "\nResets on: {}",
+                        DateTime::<Local>::from(resets_at).format("%B %d %Y at %r (%Z)")
+                    ));
+                }
+                section
+            }
+            Ok(None) => format!("**{}**\nNo character limit", provider.name()),
+            Err(e) => format!(
+                "**Current {} API Usage**\nFailed to get usage: {}",
+                provider.name(),
+                e
+            ),
+        };
+        sections.push(section);
+    }
+    sections.join("\n\n")
+}
+
+fn format_daily_table(stats: &CharacterStats, days: u32) -> String {
+    let daily = stats.get_daily_totals();
+    let total = daily.iter().map(|(_, c)| c).sum::<u64>();
+    let rows = daily
+        .iter()
+        .map(|(date, count)| (date.format("%a %b %d").to_string(), *count))
+        .chain(std::iter::once(("Total".to_string(), total)))
+        .collect::<Vec<_>>();
+
+    format!(
+        "**Last {} day{}**\n{}",
+        days,
+        if days == 1 { "" } else { "s" },
+        format_table(&rows)
+    )
+}
+
+/// Totals per voice or model, with IDs swapped for names where we know them
+async fn format_breakdown_table(
+    ctx: Context<'_>,
+    stats: &CharacterStats,
+    breakdown: UsageBreakdown,
+) -> String {
+    let voices = ctx.data().voices.read().await;
+    let models = &ctx.data().models;
+    let rows = stats
+        .get_totals_by_key()
+        .into_iter()
+        .map(|(key, count)| {
+            let name = match breakdown {
+                UsageBreakdown::Voice => voices.get(&key).map(|v| v.get_name().to_string()),
+                UsageBreakdown::Model => models.get(&key).map(|m| m.name.clone()),
+                UsageBreakdown::None => None,
+            };
+            (name.unwrap_or(key), count)
+        })
+        .collect::<Vec<_>>();
+    format_table(&rows)
+}
+
+/// A code block with the labels on the left and the counts lined up on the right
+fn format_table(rows: &[(String, u64)]) -> String {
+    if rows.is_empty() {
+        return "*Nothing used*".to_string();
+    }
+    let rows = rows
+        .iter()
+        .map(|(label, count)| (label, format_count(*count)))
+        .collect::<Vec<_>>();
+    let label_width = rows
+        .iter()
+        .map(|(l, _)| l.chars().count())
+        .max()
+        .unwrap_or(0);
+    let count_width = rows.iter().map(|(_, c)| c.len()).max().unwrap_or(0);
+
+    let lines = rows
+        .iter()
+        .map(|(label, count)| format!("{:<label_width$}  {:>count_width$}", label, count))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!("```\n{}\n```", lines)
+}
+
+/// 1234567 as "1,234,567"
+fn format_count(count: u64) -> String {
+    let digits = count.to_string();
+    let mut formatted = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
@@ -1,6 +1,19 @@
+use std::sync::Arc;
+
+use crate::elevenlabs::media::{
+    ALL_OUTPUT_FORMATS, OutputFormat, get_default_output_format, parse_output_format,
+};
+use crate::elevenlabs::types::SpeechOptions;
+use crate::soundboard::ClipSource;
 use crate::types::{Context, Error};
-use serenity::all::ChannelId as SerenityChannelId;
+use crate::voice_registry::BotVoice;
+use serenity::all::{Attachment, AutocompleteChoice, ChannelId as SerenityChannelId};
+use songbird::Call;
 use songbird::id::ChannelId as SongbirdChannelId;
+use tokio::sync::Mutex;
+
+// Largest recording to send to ElevenLabs. It accepts more, but anything this long would take ages to process.
+pub const MAX_CLIP_SIZE: u32 = 20 * 1024 * 1024;
 
 pub fn get_channel_name(ctx: &Context<'_>, channel: SongbirdChannelId) -> Result<String, Error> {
     let guild = ctx.guild().ok_or("Not in a guild")?;
@@ -11,3 +24,199 @@ pub fn get_channel_name(ctx: &Context<'_>, channel: SongbirdChannelId) -> Result
         .name
         .clone())
 }
+
+/// The bot's voice connection in the current guild and the channel it's in, or None if it hasn't joined one
+pub async fn get_voice_handler(
+    ctx: &Context<'_>,
+) -> Result<Option<(Arc<Mutex<Call>>, SongbirdChannelId)>, Error> {
+    let guild = ctx.guild().ok_or("Not in a guild")?.id;
+    let manager = songbird::get(ctx.serenity_context())
+        .await
+        .expect("Songbird Voice client placed in at initialization")
+        .clone();
+
+    let Some(handler_lock) = manager.get(guild) else {
+        return Ok(None);
+    };
+    let channel = handler_lock.lock().await.current_channel();
+    Ok(channel.map(|c| (handler_lock, c)))
+}
+
+pub async fn autocomplete_voice(
+    ctx: Context<'_>,
+    partial: &str,
+) -> impl Iterator<Item = AutocompleteChoice> {
+    get_voice_choices(ctx, partial, |_| true).await
+}
+
+/// Like `autocomplete_voice`, but only offers ElevenLabs voices
+pub async fn autocomplete_elevenlabs_voice(
+    ctx: Context<'_>,
+    partial: &str,
+) -> impl Iterator<Item = AutocompleteChoice> {
+    get_voice_choices(ctx, partial, BotVoice::is_elevenlabs).await
+}
+
+/// Like `autocomplete_voice`, but only offers cloned voices
+pub async fn autocomplete_custom_voice(
+    ctx: Context<'_>,
+    partial: &str,
+) -> impl Iterator<Item = AutocompleteChoice> {
+    get_voice_choices(ctx, partial, BotVoice::is_custom).await
+}
+
+async fn get_voice_choices(
+    ctx: Context<'_>,
+    partial: &str,
+    include: fn(&BotVoice) -> bool,
+) -> std::vec::IntoIter<AutocompleteChoice> {
+    let partial = partial.to_lowercase();
+    ctx.data()
+        .voices
+        .read()
+        .await
+        .all()
+        .filter(include)
+        .filter(|v| v.get_name().to_lowercase().contains(&partial))
+        .map(|v| AutocompleteChoice::new(v.get_name(), v.get_id()))
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+/// Speech options for the current server, e.g. its pronunciation dictionary
+pub async fn get_speech_options(ctx: &Context<'_>) -> SpeechOptions {
+    let locator = match ctx.guild_id() {
+        Some(guild_id) => ctx
+            .data()
+            .pronunciations
+            .read()
+            .await
+            .get(guild_id)
+            .cloned(),
+        None => None,
+    };
+    SpeechOptions {
+        pronunciation_dictionary_locators: locator.into_iter().collect(),
+        ..Default::default()
+    }
+}
+
+/// Looks up a voice given as a command argument. The error is meant to be shown to the user.
+pub async fn resolve_voice(ctx: &Context<'_>, voice: &str) -> Result<BotVoice, String> {
+    ctx.data().voices.read().await.resolve(voice)
+}
+
+/// Like `resolve_voice`, for commands that only ElevenLabs can do
+pub async fn resolve_elevenlabs_voice(ctx: &Context<'_>, voice: &str) -> Result<BotVoice, String> {
+    let voice = resolve_voice(ctx, voice).await?;
+    if !voice.is_elevenlabs() {
+        return Err(format!(
+            "{} isn't an ElevenLabs voice, this only works with those",
+            voice.get_name()
+        ));
+    }
+    Ok(voice)
+}
+
+pub async fn autocomplete_provider(
+    ctx: Context<'_>,
+    partial: &str,
+) -> impl Iterator<Item = AutocompleteChoice> {
+    let partial = partial.to_lowercase();
+    ctx.data()
+        .providers
+        .all()
+        .filter(|p| p.id().contains(&partial) || p.name().to_lowercase().contains(&partial))
+        .map(|p| AutocompleteChoice::new(p.name(), p.id()))
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+pub async fn autocomplete_speech_model(
+    ctx: Context<'_>,
+    partial: &str,
+) -> impl Iterator<Item = AutocompleteChoice> {
+    let partial = partial.to_lowercase();
+    ctx.data()
+        .models
+        .speech_models()
+        .filter(|m| m.model_id.contains(&partial) || m.name.to_lowercase().contains(&partial))
+        .map(|m| AutocompleteChoice::new(m.describe(), m.model_id.clone()))
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+/// Languages any of the speech models can speak
+pub async fn autocomplete_language(
+    ctx: Context<'_>,
+    partial: &str,
+) -> impl Iterator<Item = AutocompleteChoice> {
+    let partial = partial.to_lowercase();
+    let mut languages = ctx
+        .data()
+        .models
+        .speech_models()
+        .flat_map(|m| m.languages.iter())
+        .filter(|l| {
+            l.language_id.to_lowercase().contains(&partial)
+                || l.name.to_lowercase().contains(&partial)
+        })
+        .map(|l| (l.language_id.clone(), l.name.clone()))
+        .collect::<Vec<_>>();
+    languages.sort();
+    languages.dedup_by(|a, b| a.0 == b.0);
+    languages
+        .into_iter()
+        .map(|(id, name)| AutocompleteChoice::new(format!("{} ({})", name, id), id))
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+pub async fn autocomplete_output_format(
+    _ctx: Context<'_>,
+    partial: &str,
+) -> impl Iterator<Item = String> {
+    let partial = partial.to_lowercase();
+    ALL_OUTPUT_FORMATS
+        .iter()
+        .map(|f| f.to_string())
+        .filter(move |f| f.contains(&partial))
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+/// The requested output format, or the default one. The error is meant to be shown to the user.
+pub fn resolve_output_format(format: Option<String>) -> Result<&'static OutputFormat, String> {
+    match format {
+        None => Ok(get_default_output_format()),
+        Some(f) => parse_output_format(&f).ok_or_else(|| {
+            format!(
+                "Unknown audio format \"{}\". Valid formats are: {}",
+                f,
+                ALL_OUTPUT_FORMATS
+                    .iter()
+                    .map(|f| f.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        }),
+    }
+}
+
+/// Whether an attachment is something ElevenLabs can take audio from (which includes videos, and voice messages)
+pub fn is_audio_attachment(attachment: &Attachment) -> bool {
+    attachment
+        .content_type
+        .as_deref()
+        .is_some_and(|t| t.starts_with("audio/") || t.starts_with("video/"))
+}
+
+/// What a clip about to be posted says and who asked for it, to remember it by so it can be saved as a clip
+pub fn get_clip_source(ctx: &Context<'_>, voice: &str, text: &str) -> ClipSource {
+    ClipSource {
+        voice: voice.to_string(),
+        text: text.to_string(),
+        creator_id: ctx.author().id.get(),
+        creator_name: ctx.author().name.clone(),
+    }
+}
@@ -0,0 +1,145 @@
+use ::poise::CreateReply;
+use ::serenity::all::{Attachment, CreateAttachment};
+use log::{error, info};
+use serenity::all::EditMessage;
+
+use crate::commands::util::{
+    MAX_CLIP_SIZE, autocomplete_elevenlabs_voice, autocomplete_output_format, get_channel_name,
+    get_clip_source, get_voice_handler, is_audio_attachment, resolve_elevenlabs_voice,
+    resolve_output_format,
+};
+use crate::streamutil::write_stream_to_vec_u8;
+use crate::types::{Context, Error};
+
+/// Re-voices an uploaded recording with one of our voices, keeping your timing and emotion
+#[poise::command(slash_command, prefix_command)]
+pub async fn voice_changer(
+    ctx: Context<'_>,
+    #[description = "Recording of the line to re-voice"] clip: Attachment,
+    #[description = "Voice to use"]
+    #[autocomplete = "autocomplete_elevenlabs_voice"]
+    voice: String,
+    #[description = "Remove background noise from the recording first"]
+    remove_background_noise: Option<bool>,
+    #[description = "Also play it in the currently joined voice channel"] play_in_voice: Option<
+        bool,
+    >,
+    #[description = "Audio format to generate"]
+    #[autocomplete = "autocomplete_output_format"]
+    format: Option<String>,
+) -> Result<(), Error> {
+    let voice = match resolve_elevenlabs_voice(&ctx, &voice).await {
+        Ok(v) => v,
+        Err(msg) => {
+            ctx.send(CreateReply::default().content(msg)).await?;
+            return Ok(());
+        }
+    };
+    let format = match resolve_output_format(format) {
+        Ok(f) => f,
+        Err(msg) => {
+            ctx.send(CreateReply::default().content(msg)).await?;
+            return Ok(());
+        }
+    };
+    if !is_audio_attachment(&clip) {
+        ctx.send(CreateReply::default().content("That doesn't look like an audio recording"))
+            .await?;
+        return Ok(());
+    }
+    if clip.size > MAX_CLIP_SIZE {
+        ctx.send(CreateReply::default().content(format!(
+            "That recording is too big, the limit is {}MB",
+            MAX_CLIP_SIZE / 1024 / 1024
+        )))
+        .await?;
+        return Ok(());
+    }
+    let voice_handler = if play_in_voice.unwrap_or(false) {
+        match get_voice_handler(&ctx).await? {
+            Some(v) => Some(v),
+            None => {
+                ctx.send(CreateReply::default().content("Not in a voice channel"))
+                    .await?;
+                return Ok(());
+            }
+        }
+    } else {
+        None
+    };
+
+    let sent_msg_handle = ctx
+        .send(CreateReply::default().content("Converting voice..."))
+        .await?;
+    let mut sent_msg = sent_msg_handle.into_message().await.map_err(|e| {
+        error!(error = e.to_string().as_str(); "Failed to convert message to Message");
+        Error::from(e)
+    })?;
+
+    let audio = clip.download().await?;
+    info!(voice = voice, filename = clip.filename.as_str(), size = audio.len(); "Converting voice");
+
+    let data = ctx.data();
+    let model_id = data
+        .models
+        .get_default_voice_conversion_model()
+        .map(|m| m.model_id.clone());
+    let converted = match data
+        .client
+        .convert_speech(
+            voice.get_id(),
+            audio,
+            clip.filename.clone(),
+            model_id,
+            None,
+            remove_background_noise.unwrap_or(false),
+            Some(format),
+        )
+        .await
+    {
+        Ok(stream) => write_stream_to_vec_u8(stream).await,
+        Err(e) => Err(e.into()),
+    };
+    let bytes = match converted {
+        Err(e) => {
+            error!(voice = voice, error = e.to_string().as_str(); "Failed to convert voice");
+            ctx.send(CreateReply::default().content(format!("Failed to convert voice: {}", e)))
+                .await?;
+            return Ok(());
+        }
+        Ok(b) => format.to_playable(b),
+    };
+
+    let content = match voice_handler {
+        Some((handler_lock, channel)) => {
+            let _ = handler_lock.lock().await.play_input(bytes.clone().into());
+            format!(
+                "Playing converted voice in channel \"{}\"",
+                get_channel_name(&ctx, channel)?
+            )
+        }
+        None => "Converted voice".to_string(),
+    };
+    sent_msg
+        .edit(
+            ctx.http(),
+            EditMessage::default()
+                .new_attachment(CreateAttachment::bytes(
+                    bytes,
+                    format!("Converted voice.{}", format.get_file_extension()),
+                ))
+                .content(content),
+        )
+        .await?;
+    // There's no text to go by, only the recording it was made from
+    ctx.data().recent_clips.record(
+        sent_msg.id,
+        get_clip_source(
+            &ctx,
+            voice.get_name(),
+            &format!("Re-voiced {}", clip.filename),
+        ),
+    );
+
+    Ok(())
+}
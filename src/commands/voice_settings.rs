@@ -0,0 +1,161 @@
+use crate::commands::util::{autocomplete_elevenlabs_voice, resolve_elevenlabs_voice};
+use crate::elevenlabs::types::VoiceSettings;
+use crate::types::{Context, Error};
+use crate::voice_registry::BotVoice;
+
+use ::poise::CreateReply;
+use log::info;
+
+/// Views and tunes how each voice sounds. Changes are stored on ElevenLabs and apply to future speech
+#[poise::command(
+    slash_command,
+    prefix_command,
+    subcommands("show", "set", "reset"),
+    required_permissions = "ADMINISTRATOR",
+    default_member_permissions = "ADMINISTRATOR"
+)]
+pub async fn voice_settings(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Shows the settings currently stored for a voice
+#[poise::command(slash_command, prefix_command)]
+async fn show(
+    ctx: Context<'_>,
+    #[description = "Voice to show the settings of"]
+    #[autocomplete = "autocomplete_elevenlabs_voice"]
+    voice: String,
+) -> Result<(), Error> {
+    let voice = match resolve_elevenlabs_voice(&ctx, &voice).await {
+        Ok(v) => v,
+        Err(msg) => {
+            ctx.send(CreateReply::default().content(msg)).await?;
+            return Ok(());
+        }
+    };
+    ctx.defer().await?;
+
+    let content = match ctx.data().client.get_voice_settings(&voice.get_id()).await {
+        Ok(settings) => describe_settings(&voice, &settings),
+        Err(e) => format!("Failed to get voice settings: {}", e),
+    };
+    ctx.send(CreateReply::default().content(content)).await?;
+
+    Ok(())
+}
+
+/// Changes some of the settings stored for a voice. Anything left out stays as it is.
+#[poise::command(slash_command, prefix_command)]
+async fn set(
+    ctx: Context<'_>,
+    #[description = "Voice to change"]
+    #[autocomplete = "autocomplete_elevenlabs_voice"]
+    voice: String,
+    #[description = "Higher is more consistent, lower is more expressive (0 to 1)"]
+    #[min = 0.0]
+    #[max = 1.0]
+    stability: Option<f32>,
+    #[description = "How closely to stick to the original voice (0 to 1)"]
+    #[min = 0.0]
+    #[max = 1.0]
+    similarity_boost: Option<f32>,
+    #[description = "How much to exaggerate the voice's style (0 to 1)"]
+    #[min = 0.0]
+    #[max = 1.0]
+    style: Option<f32>,
+    #[description = "Boost similarity to the original speaker, at the cost of some latency"]
+    speaker_boost: Option<bool>,
+) -> Result<(), Error> {
+    let voice = match resolve_elevenlabs_voice(&ctx, &voice).await {
+        Ok(v) => v,
+        Err(msg) => {
+            ctx.send(CreateReply::default().content(msg)).await?;
+            return Ok(());
+        }
+    };
+    if stability.is_none()
+        && similarity_boost.is_none()
+        && style.is_none()
+        && speaker_boost.is_none()
+    {
+        ctx.send(CreateReply::default().content("Nothing to change"))
+            .await?;
+        return Ok(());
+    }
+    ctx.defer().await?;
+
+    let settings = VoiceSettings {
+        stability,
+        similarity_boost,
+        style,
+        use_speaker_boost: speaker_boost,
+        speed: None,
+    };
+    info!(voice = voice, user = ctx.author().name.as_str(); "Changing voice settings to {:?}", settings);
+    save_and_show(ctx, voice, settings).await
+}
+
+/// Puts a voice back on ElevenLabs' default settings
+#[poise::command(slash_command, prefix_command)]
+async fn reset(
+    ctx: Context<'_>,
+    #[description = "Voice to reset"]
+    #[autocomplete = "autocomplete_elevenlabs_voice"]
+    voice: String,
+) -> Result<(), Error> {
+    let voice = match resolve_elevenlabs_voice(&ctx, &voice).await {
+        Ok(v) => v,
+        Err(msg) => {
+            ctx.send(CreateReply::default().content(msg)).await?;
+            return Ok(());
+        }
+    };
+    ctx.defer().await?;
+
+    let settings = match ctx.data().client.get_default_voice_settings().await {
+        Ok(s) => s,
+        Err(e) => {
+            ctx.send(
+                CreateReply::default().content(format!("Failed to get default settings: {}", e)),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    info!(voice = voice, user = ctx.author().name.as_str(); "Resetting voice settings to {:?}", settings);
+    save_and_show(ctx, voice, settings).await
+}
+
+async fn save_and_show(
+    ctx: Context<'_>,
+    voice: BotVoice,
+    settings: VoiceSettings,
+) -> Result<(), Error> {
+    let client = &ctx.data().client;
+    let voice_id = voice.get_id();
+    let content = match client.edit_voice_settings(&voice_id, &settings).await {
+        Err(e) => format!("Failed to save voice settings: {}", e),
+        Ok(()) => match client.get_voice_settings(&voice_id).await {
+            Ok(saved) => format!("Saved! {}", describe_settings(&voice, &saved)),
+            Err(e) => format!("Saved, but failed to read the settings back: {}", e),
+        },
+    };
+    ctx.send(CreateReply::default().content(content)).await?;
+
+    Ok(())
+}
+
+fn describe_settings(voice: &BotVoice, settings: &VoiceSettings) -> String {
+    fn describe<T: ToString>(value: Option<T>) -> String {
+        value.map_or("not set".to_string(), |v| v.to_string())
+    }
+
+    format!(
+        "**Settings for {}**\nStability: {}\nSimilarity boost: {}\nStyle exaggeration: {}\nSpeaker boost: {}",
+        voice.get_name(),
+        describe(settings.stability),
+        describe(settings.similarity_boost),
+        describe(settings.style),
+        describe(settings.use_speaker_boost),
+    )
+}
@@ -0,0 +1,130 @@
+use crate::commands::util::autocomplete_provider;
+use crate::elevenlabs::types::{Voice, VoiceCategory, VoiceListFilter, VoiceType};
+use crate::tts::{ProviderVoice, elevenlabs::ELEVENLABS_PROVIDER_ID};
+use crate::types::{Context, Error};
+
+use ::poise::CreateReply;
+
+// Keeps each page comfortably under Discord's embed description limit
+const VOICES_PER_PAGE: usize = 5;
+
+/// Lists the voices available to the bot, from ElevenLabs unless another provider is picked
+#[poise::command(slash_command, prefix_command)]
+pub async fn voices(
+    ctx: Context<'_>,
+    #[description = "Only show voices whose name, description or labels match this"] search: Option<
+        String,
+    >,
+    #[description = "Whose voices to show"] voice_type: Option<VoiceType>,
+    #[description = "Only show voices of this category"] category: Option<VoiceCategory>,
+    #[description = "Speech provider to list the voices of (default ElevenLabs)"]
+    #[autocomplete = "autocomplete_provider"]
+    provider: Option<String>,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+    if let Some(provider_id) = provider.filter(|p| p != ELEVENLABS_PROVIDER_ID) {
+        return list_provider_voices(ctx, &provider_id, search).await;
+    }
+
+    let filter = VoiceListFilter {
+        search,
+        voice_type,
+        category,
+    };
+    let voices = match ctx.data().client.get_voice_list(&filter).await {
+        Ok(v) => v,
+        Err(e) => {
+            ctx.send(CreateReply::default().content(format!("Failed to list voices: {}", e)))
+                .await?;
+            return Ok(());
+        }
+    };
+    paginate_voices(ctx, voices.iter().map(describe_voice).collect()).await
+}
+
+/// The voices of a provider other than ElevenLabs, which can only be searched by name
+async fn list_provider_voices(
+    ctx: Context<'_>,
+    provider_id: &str,
+    search: Option<String>,
+) -> Result<(), Error> {
+    let listed = match ctx.data().providers.get(provider_id) {
+        Ok(provider) => provider.list_voices().await,
+        Err(e) => Err(e),
+    };
+    let mut voices = match listed {
+        Ok(v) => v,
+        Err(e) => {
+            ctx.send(CreateReply::default().content(format!("Failed to list voices: {}", e)))
+                .await?;
+            return Ok(());
+        }
+    };
+    if let Some(search) = search.map(|s| s.to_lowercase()) {
+        voices.retain(|v| {
+            v.name.to_lowercase().contains(&search) || v.voice_id.to_lowercase().contains(&search)
+        });
+    }
+
+    paginate_voices(ctx, voices.iter().map(describe_provider_voice).collect()).await
+}
+
+async fn paginate_voices(ctx: Context<'_>, voices: Vec<String>) -> Result<(), Error> {
+    if voices.is_empty() {
+        ctx.send(CreateReply::default().content("No voices found"))
+            .await?;
+        return Ok(());
+    }
+
+    let page_count = voices.len().div_ceil(VOICES_PER_PAGE);
+    let pages = voices
+        .chunks(VOICES_PER_PAGE)
+        .enumerate()
+        .map(|(i, chunk)| {
+            format!(
+                "**Voices** ({} total, page {} of {})\n\n{}",
+                voices.len(),
+                i + 1,
+                page_count,
+                chunk.join("\n\n")
+            )
+        })
+        .collect::<Vec<_>>();
+
+    poise::builtins::paginate(ctx, &pages.iter().map(|p| p.as_str()).collect::<Vec<_>>()).await?;
+
+    Ok(())
+}
+
+fn describe_voice(voice: &Voice) -> String {
+    let mut description = format!(
+        "**{}** ({}) `{}`",
+        voice.name,
+        voice.category.as_deref().unwrap_or("unknown category"),
+        voice.voice_id
+    );
+
+    if !voice.labels.is_empty() {
+        let mut labels = voice
+            .labels
+            .iter()
+            .map(|(k, v)| format!("{}: {}", k, v))
+            .collect::<Vec<_>>();
+        // HashMap order is random, keep it stable between pages
+        labels.sort();
+        description.push_str(&format!("\nLabels: {}", labels.join(", ")));
+    }
+    if let Some(preview_url) = &voice.preview_url {
+        description.push_str(&format!("\n[Preview]({})", preview_url));
+    }
+
+    description
+}
+
+fn describe_provider_voice(voice: &ProviderVoice) -> String {
+    let mut description = format!("**{}** `{}`", voice.name, voice.voice_id);
+    if let Some(details) = &voice.description {
+        description.push_str(&format!("\n{}", details));
+    }
+    description
+}
@@ -0,0 +1,340 @@
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+/// Everything that can go wrong when talking to ElevenLabs.
+///
+/// The `Display` output is meant to be shown to Discord users as-is, so it should never contain raw response bodies.
+#[derive(Debug)]
+pub enum ElevenLabsError {
+    /// The request never got a (complete) response: connection failures, timeouts, etc.
+    Transport(reqwest::Error),
+    /// The API responded successfully, but not with what we expected
+    Parse(serde_json::Error),
+    /// The WebSocket connection used for input streaming failed
+    WebSocket(Box<tokio_tungstenite::tungstenite::Error>),
+    /// Audio embedded in a JSON response wasn't valid base64
+    InvalidAudio(base64::DecodeError),
+    /// The API reported an error over an already open stream
+    Stream {
+        message: String,
+    },
+    InvalidApiKey {
+        message: String,
+    },
+    QuotaExceeded {
+        message: String,
+    },
+    VoiceNotFound {
+        message: String,
+    },
+    RateLimited {
+        status: StatusCode,
+        message: String,
+    },
+    Validation {
+        issues: Vec<ValidationIssue>,
+    },
+    /// An ID that would have been put in a request's URL had characters IDs never contain, so it wasn't sent
+    InvalidId {
+        kind: &'static str,
+        id: String,
+    },
+    /// Any other non-2xx response
+    Api {
+        status: StatusCode,
+        detail_status: Option<String>,
+        message: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct ValidationIssue {
+    pub location: String,
+    pub message: String,
+}
+
+// The API reports errors in a few different shapes, all under a top-level "detail" key
+#[derive(Debug, Deserialize)]
+struct ErrorBody {
+    detail: ErrorDetail,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ErrorDetail {
+    Status {
+        status: String,
+        message: Option<String>,
+    },
+    Validation(Vec<ValidationDetail>),
+    Message(String),
+}
+
+#[derive(Debug, Deserialize)]
+struct ValidationDetail {
+    #[serde(default)]
+    loc: Vec<serde_json::Value>,
+    msg: String,
+}
+
+impl ElevenLabsError {
+    /// Builds the error for a non-2xx response from its status code and body
+    pub fn from_response(status: StatusCode, body: &str) -> Self {
+        let detail = match serde_json::from_str::<ErrorBody>(body) {
+            Ok(parsed) => parsed.detail,
+            Err(_) => {
+                return Self::from_status(status, None, fallback_message(status, body));
+            }
+        };
+
+        match detail {
+            ErrorDetail::Status { status: s, message } => {
+                let message = message.unwrap_or_else(|| s.clone());
+                Self::from_status(status, Some(s), message)
+            }
+            ErrorDetail::Validation(details) => Self::Validation {
+                issues: details
+                    .into_iter()
+                    .map(|d| ValidationIssue {
+                        location: d
+                            .loc
+                            .iter()
+                            // The first element is always where the field lives ("body", "query", ...), which isn't useful to users
+                            .skip(1)
+                            .map(|l| match l {
+                                serde_json::Value::String(s) => s.clone(),
+                                other => other.to_string(),
+                            })
+                            .collect::<Vec<_>>()
+                            .join("."),
+                        message: d.msg,
+                    })
+                    .collect(),
+            },
+            ErrorDetail::Message(message) => Self::from_status(status, None, message),
+        }
+    }
+
+    fn from_status(status: StatusCode, detail_status: Option<String>, message: String) -> Self {
+        match (status, detail_status.as_deref()) {
+            (_, Some("quota_exceeded")) => Self::QuotaExceeded { message },
+            (_, Some("voice_not_found")) => Self::VoiceNotFound { message },
+            (_, Some("invalid_api_key")) | (StatusCode::UNAUTHORIZED, _) => {
+                Self::InvalidApiKey { message }
+            }
+            (_, Some("too_many_concurrent_requests" | "system_busy" | "rate_limit_exceeded"))
+            | (StatusCode::TOO_MANY_REQUESTS, _) => Self::RateLimited { status, message },
+            _ => Self::Api {
+                status,
+                detail_status,
+                message,
+            },
+        }
+    }
+}
+
+fn fallback_message(status: StatusCode, body: &str) -> String {
+    match status.canonical_reason() {
+        Some(reason) if body.trim().is_empty() || body.trim_start().starts_with('<') => {
+            reason.to_string()
+        }
+        // Not a JSON error body, but short enough that it's probably a readable message
+        _ if body.len() <= 200 => body.trim().to_string(),
+        Some(reason) => reason.to_string(),
+        None => "Unknown error".to_string(),
+    }
+}
+
+impl std::fmt::Display for ElevenLabsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Transport(e) => write!(f, "Couldn't reach ElevenLabs: {}", e),
+            Self::Parse(e) => write!(f, "Unexpected response from ElevenLabs: {}", e),
+            Self::WebSocket(e) => write!(f, "Lost connection to ElevenLabs: {}", e),
+            Self::InvalidAudio(e) => write!(f, "ElevenLabs sent audio we couldn't decode: {}", e),
+            Self::Stream { message } => write!(f, "ElevenLabs stream failed: {}", message),
+            Self::InvalidApiKey { message } => {
+                write!(f, "ElevenLabs rejected the bot's API key: {}", message)
+            }
+            Self::QuotaExceeded { message } => {
+                write!(f, "ElevenLabs character quota exceeded: {}", message)
+            }
+            Self::VoiceNotFound { message } => {
+                write!(f, "ElevenLabs couldn't find that voice: {}", message)
+            }
+            Self::RateLimited { status, message } => write!(
+                f,
+                "ElevenLabs is too busy right now (HTTP {}: {}), try again in a moment",
+                status.as_u16(),
+                message
+            ),
+            Self::InvalidId { kind, id } => write!(f, "\"{}\" isn't a valid {}", id, kind),
+            Self::Validation { issues } => {
+                write!(f, "ElevenLabs rejected the request: ")?;
+                let described = issues
+                    .iter()
+                    .map(|i| {
+                        if i.location.is_empty() {
+                            i.message.clone()
+                        } else {
+                            format!("{}: {}", i.location, i.message)
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join("; ");
+                write!(f, "{}", described)
+            }
+            Self::Api {
+                status,
+                detail_status: Some(detail_status),
+                message,
+            } => write!(
+                f,
+                "ElevenLabs returned HTTP {} ({}): {}",
+                status.as_u16(),
+                detail_status,
+                message
+            ),
+            Self::Api {
+                status,
+                detail_status: None,
+                message,
+            } => write!(
+                f,
+                "ElevenLabs returned HTTP {}: {}",
+                status.as_u16(),
+                message
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ElevenLabsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Transport(e) => Some(e),
+            Self::Parse(e) => Some(e),
+            Self::WebSocket(e) => Some(e.as_ref()),
+            Self::InvalidAudio(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ElevenLabsError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Transport(e)
+    }
+}
+
+impl From<tokio_tungstenite::tungstenite::Error> for ElevenLabsError {
+    fn from(e: tokio_tungstenite::tungstenite::Error) -> Self {
+        Self::WebSocket(Box::new(e))
+    }
+}
+
+impl From<base64::DecodeError> for ElevenLabsError {
+    fn from(e: base64::DecodeError) -> Self {
+        Self::InvalidAudio(e)
+    }
+}
+
+impl From<serde_json::Error> for ElevenLabsError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Parse(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detail(status: &str) -> String {
+        format!(
+            r#"{{"detail":{{"status":"{}","message":"Something went wrong"}}}}"#,
+            status
+        )
+    }
+
+    #[test]
+    fn maps_detail_status() {
+        // ElevenLabs reports an exhausted quota with a 401, which mustn't be mistaken for a bad key
+        assert!(matches!(
+            ElevenLabsError::from_response(StatusCode::UNAUTHORIZED, &detail("quota_exceeded")),
+            ElevenLabsError::QuotaExceeded { .. }
+        ));
+        assert!(matches!(
+            ElevenLabsError::from_response(StatusCode::UNAUTHORIZED, &detail("invalid_api_key")),
+            ElevenLabsError::InvalidApiKey { .. }
+        ));
+        assert!(matches!(
+            ElevenLabsError::from_response(StatusCode::NOT_FOUND, &detail("voice_not_found")),
+            ElevenLabsError::VoiceNotFound { .. }
+        ));
+        assert!(matches!(
+            ElevenLabsError::from_response(
+                StatusCode::TOO_MANY_REQUESTS,
+                &detail("too_many_concurrent_requests")
+            ),
+            ElevenLabsError::RateLimited { .. }
+        ));
+        match ElevenLabsError::from_response(
+            StatusCode::BAD_REQUEST,
+            &detail("max_length_exceeded"),
+        ) {
+            ElevenLabsError::Api {
+                status,
+                detail_status,
+                message,
+            } => {
+                assert_eq!(status, StatusCode::BAD_REQUEST);
+                assert_eq!(detail_status.as_deref(), Some("max_length_exceeded"));
+                assert_eq!(message, "Something went wrong");
+            }
+            other => panic!("expected Api, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn maps_status_without_detail() {
+        assert!(matches!(
+            ElevenLabsError::from_response(StatusCode::TOO_MANY_REQUESTS, "slow down"),
+            ElevenLabsError::RateLimited { .. }
+        ));
+        assert!(matches!(
+            ElevenLabsError::from_response(StatusCode::UNAUTHORIZED, ""),
+            ElevenLabsError::InvalidApiKey { .. }
+        ));
+        match ElevenLabsError::from_response(StatusCode::BAD_GATEWAY, "<html>oops</html>") {
+            ElevenLabsError::Api { message, .. } => assert_eq!(message, "Bad Gateway"),
+            other => panic!("expected Api, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn maps_validation_issues() {
+        let body = r#"{"detail":[
+            {"loc":["body","text"],"msg":"Field required","type":"missing"},
+            {"loc":["body","voice_settings","stability"],"msg":"Input should be less than or equal to 1"}
+        ]}"#;
+        let ElevenLabsError::Validation { issues } =
+            ElevenLabsError::from_response(StatusCode::UNPROCESSABLE_ENTITY, body)
+        else {
+            panic!("expected Validation");
+        };
+        let issues = issues
+            .iter()
+            .map(|i| (i.location.as_str(), i.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            issues,
+            [
+                ("text", "Field required"),
+                (
+                    "voice_settings.stability",
+                    "Input should be less than or equal to 1"
+                ),
+            ]
+        );
+    }
+}
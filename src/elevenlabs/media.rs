@@ -1,11 +1,51 @@
+use log::error;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MediaFormat {
     MP3,
+    /// Raw signed 16-bit little-endian mono samples, without any header
+    Pcm,
+    /// Raw 8-bit G.711 mu-law samples, as used by telephony
+    ULaw,
+    /// Raw 8-bit G.711 A-law samples
+    ALaw,
+    /// Opus in an Ogg container
+    Opus,
 }
 
 impl MediaFormat {
     pub fn to_str(&self) -> &str {
         match self {
             MediaFormat::MP3 => "mp3",
+            MediaFormat::Pcm => "pcm",
+            MediaFormat::ULaw => "ulaw",
+            MediaFormat::ALaw => "alaw",
+            MediaFormat::Opus => "opus",
+        }
+    }
+
+    /// Whether the API returns bare samples that need a WAV header before anything else can play them
+    pub fn is_raw(&self) -> bool {
+        matches!(
+            self,
+            MediaFormat::Pcm | MediaFormat::ULaw | MediaFormat::ALaw
+        )
+    }
+
+    // https://www.mmsp.ece.mcgill.ca/Documents/AudioFormats/WAVE/WAVE.html
+    fn wav_format_tag(&self) -> Option<u16> {
+        match self {
+            MediaFormat::Pcm => Some(1),
+            MediaFormat::ALaw => Some(6),
+            MediaFormat::ULaw => Some(7),
+            MediaFormat::MP3 | MediaFormat::Opus => None,
+        }
+    }
+
+    fn bits_per_sample(&self) -> u16 {
+        match self {
+            MediaFormat::Pcm => 16,
+            _ => 8,
         }
     }
 }
@@ -13,7 +53,52 @@ impl MediaFormat {
 pub struct OutputFormat(MediaFormat, i32, i32); // Use &str as it's a constant string
 
 pub static DEFAULT_OUTPUT_FORMAT: &OutputFormat = MP3_44100HZ_128KBPS;
+
+pub static MP3_22050HZ_32KBPS: &OutputFormat = &OutputFormat(MediaFormat::MP3, 22050, 32000);
+pub static MP3_44100HZ_32KBPS: &OutputFormat = &OutputFormat(MediaFormat::MP3, 44100, 32000);
+pub static MP3_44100HZ_64KBPS: &OutputFormat = &OutputFormat(MediaFormat::MP3, 44100, 64000);
+pub static MP3_44100HZ_96KBPS: &OutputFormat = &OutputFormat(MediaFormat::MP3, 44100, 96000);
 pub static MP3_44100HZ_128KBPS: &OutputFormat = &OutputFormat(MediaFormat::MP3, 44100, 128000);
+pub static MP3_44100HZ_192KBPS: &OutputFormat = &OutputFormat(MediaFormat::MP3, 44100, 192000);
+
+// Uncompressed formats have a fixed bitrate: 16 bits per sample for PCM, 8 for the G.711 ones
+pub static PCM_8000HZ: &OutputFormat = &OutputFormat(MediaFormat::Pcm, 8000, 128000);
+pub static PCM_16000HZ: &OutputFormat = &OutputFormat(MediaFormat::Pcm, 16000, 256000);
+pub static PCM_22050HZ: &OutputFormat = &OutputFormat(MediaFormat::Pcm, 22050, 352800);
+pub static PCM_24000HZ: &OutputFormat = &OutputFormat(MediaFormat::Pcm, 24000, 384000);
+pub static PCM_44100HZ: &OutputFormat = &OutputFormat(MediaFormat::Pcm, 44100, 705600);
+pub static PCM_48000HZ: &OutputFormat = &OutputFormat(MediaFormat::Pcm, 48000, 768000);
+pub static ULAW_8000HZ: &OutputFormat = &OutputFormat(MediaFormat::ULaw, 8000, 64000);
+pub static ALAW_8000HZ: &OutputFormat = &OutputFormat(MediaFormat::ALaw, 8000, 64000);
+
+pub static OPUS_48000HZ_32KBPS: &OutputFormat = &OutputFormat(MediaFormat::Opus, 48000, 32000);
+pub static OPUS_48000HZ_64KBPS: &OutputFormat = &OutputFormat(MediaFormat::Opus, 48000, 64000);
+pub static OPUS_48000HZ_96KBPS: &OutputFormat = &OutputFormat(MediaFormat::Opus, 48000, 96000);
+pub static OPUS_48000HZ_128KBPS: &OutputFormat = &OutputFormat(MediaFormat::Opus, 48000, 128000);
+pub static OPUS_48000HZ_192KBPS: &OutputFormat = &OutputFormat(MediaFormat::Opus, 48000, 192000);
+
+/// Every output format the API offers. Some need a higher subscription tier (e.g. 192kbps MP3, 44.1kHz PCM).
+pub static ALL_OUTPUT_FORMATS: &[&OutputFormat] = &[
+    MP3_22050HZ_32KBPS,
+    MP3_44100HZ_32KBPS,
+    MP3_44100HZ_64KBPS,
+    MP3_44100HZ_96KBPS,
+    MP3_44100HZ_128KBPS,
+    MP3_44100HZ_192KBPS,
+    PCM_8000HZ,
+    PCM_16000HZ,
+    PCM_22050HZ,
+    PCM_24000HZ,
+    PCM_44100HZ,
+    PCM_48000HZ,
+    ULAW_8000HZ,
+    ALAW_8000HZ,
+    OPUS_48000HZ_32KBPS,
+    OPUS_48000HZ_64KBPS,
+    OPUS_48000HZ_96KBPS,
+    OPUS_48000HZ_128KBPS,
+    OPUS_48000HZ_192KBPS,
+];
 
 impl OutputFormat {
     #[allow(dead_code)]
@@ -31,7 +116,200 @@ impl OutputFormat {
         self.2
     }
 
-    pub fn to_string(&self) -> String {
-        format!("{}_{}_{}", self.0.to_str(), self.1, self.2)
+    /// The extension to use for files of this format, after `to_playable` has been applied
+    pub fn get_file_extension(&self) -> &str {
+        match self.0 {
+            MediaFormat::MP3 => "mp3",
+            MediaFormat::Pcm | MediaFormat::ULaw | MediaFormat::ALaw => "wav",
+            MediaFormat::Opus => "opus",
+        }
+    }
+
+    /// A WAV header for raw formats, so Discord clients and songbird know how to play them.
+    /// `data_len` may be None while streaming, when the final length isn't known yet.
+    pub fn get_wav_header(&self, data_len: Option<u32>) -> Option<Vec<u8>> {
+        let format_tag = self.0.wav_format_tag()?;
+        let channels: u16 = 1;
+        let bits_per_sample = self.0.bits_per_sample();
+        let block_align = channels * bits_per_sample / 8;
+        let byte_rate = self.1 as u32 * block_align as u32;
+        // Non-PCM formats need the (empty) extension size field at the end of the fmt chunk
+        let fmt_len: u32 = if format_tag == 1 { 16 } else { 18 };
+        let header_len = 20 + fmt_len;
+        // The maximum length is the conventional way of saying "read until the end" for streamed WAVs
+        let data_len = data_len.unwrap_or(u32::MAX - header_len);
+
+        let mut header = Vec::with_capacity(8 + header_len as usize);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&(header_len + data_len).to_le_bytes());
+        header.extend_from_slice(b"WAVE");
+        header.extend_from_slice(b"fmt ");
+        header.extend_from_slice(&fmt_len.to_le_bytes());
+        header.extend_from_slice(&format_tag.to_le_bytes());
+        header.extend_from_slice(&channels.to_le_bytes());
+        header.extend_from_slice(&(self.1 as u32).to_le_bytes());
+        header.extend_from_slice(&byte_rate.to_le_bytes());
+        header.extend_from_slice(&block_align.to_le_bytes());
+        header.extend_from_slice(&bits_per_sample.to_le_bytes());
+        if fmt_len == 18 {
+            header.extend_from_slice(&0u16.to_le_bytes());
+        }
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&data_len.to_le_bytes());
+        Some(header)
+    }
+
+    /// Turns audio as returned by the API into a self-describing file
+    pub fn to_playable(&self, audio: Vec<u8>) -> Vec<u8> {
+        match self.get_wav_header(Some(audio.len() as u32)) {
+            Some(mut header) => {
+                header.extend(audio);
+                header
+            }
+            None => audio,
+        }
+    }
+}
+
+impl std::fmt::Display for OutputFormat {
+    // The API expects the bitrate in kbps, e.g. "mp3_44100_128", and no bitrate at all for raw formats, e.g. "pcm_16000"
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.0.is_raw() {
+            write!(f, "{}_{}", self.0.to_str(), self.1)
+        } else {
+            write!(f, "{}_{}_{}", self.0.to_str(), self.1, self.2 / 1000)
+        }
+    }
+}
+
+impl std::fmt::Debug for OutputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+pub fn parse_output_format(format: &str) -> Option<&'static OutputFormat> {
+    let format = format.trim().to_lowercase();
+    ALL_OUTPUT_FORMATS
+        .iter()
+        .find(|f| f.to_string() == format)
+        .copied()
+}
+
+/// The file extension for audio with the given MIME type, e.g. from a history download
+pub fn get_file_extension_for_content_type(content_type: &str) -> &'static str {
+    match content_type.split(';').next().unwrap_or("").trim() {
+        "audio/wav" | "audio/x-wav" | "audio/wave" => "wav",
+        "audio/ogg" | "audio/opus" => "opus",
+        "audio/basic" => "ulaw",
+        "application/zip" => "zip",
+        _ => "mp3",
+    }
+}
+
+pub fn get_default_output_format() -> &'static OutputFormat {
+    if let Ok(env_value) = std::env::var("DEFAULT_OUTPUT_FORMAT") {
+        if let Some(format) = parse_output_format(&env_value) {
+            return format;
+        }
+        error!(
+            "Invalid DEFAULT_OUTPUT_FORMAT env variable: {}. Valid values are {}. Falling back to {}",
+            env_value,
+            ALL_OUTPUT_FORMATS
+                .iter()
+                .map(|f| f.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            DEFAULT_OUTPUT_FORMAT
+        );
+    }
+    DEFAULT_OUTPUT_FORMAT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_at(header: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes(header[offset..offset + 2].try_into().unwrap())
+    }
+
+    fn u32_at(header: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn wav_header_for_pcm() {
+        let header = PCM_22050HZ.get_wav_header(Some(1000)).unwrap();
+        assert_eq!(header.len(), 44);
+        assert_eq!(&header[0..4], b"RIFF");
+        assert_eq!(u32_at(&header, 4), 36 + 1000);
+        assert_eq!(&header[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(&header, 16), 16);
+        assert_eq!(u16_at(&header, 20), 1);
+        assert_eq!(u16_at(&header, 22), 1);
+        assert_eq!(u32_at(&header, 24), 22050);
+        assert_eq!(u32_at(&header, 28), 22050 * 2);
+        assert_eq!(u16_at(&header, 32), 2);
+        assert_eq!(u16_at(&header, 34), 16);
+        assert_eq!(&header[36..40], b"data");
+        assert_eq!(u32_at(&header, 40), 1000);
+    }
+
+    #[test]
+    fn wav_header_for_g711() {
+        for (format, tag) in [(ULAW_8000HZ, 7), (ALAW_8000HZ, 6)] {
+            let header = format.get_wav_header(Some(500)).unwrap();
+            // The fmt chunk carries an empty extension, two bytes longer than PCM's
+            assert_eq!(header.len(), 46);
+            assert_eq!(u32_at(&header, 4), 38 + 500);
+            assert_eq!(u32_at(&header, 16), 18);
+            assert_eq!(u16_at(&header, 20), tag);
+            assert_eq!(u32_at(&header, 24), 8000);
+            assert_eq!(u32_at(&header, 28), 8000);
+            assert_eq!(u16_at(&header, 32), 1);
+            assert_eq!(u16_at(&header, 34), 8);
+            assert_eq!(u16_at(&header, 36), 0);
+            assert_eq!(&header[38..42], b"data");
+            assert_eq!(u32_at(&header, 42), 500);
+        }
+    }
+
+    #[test]
+    fn wav_header_while_streaming() {
+        let header = PCM_16000HZ.get_wav_header(None).unwrap();
+        assert_eq!(u32_at(&header, 4), u32::MAX);
+        assert_eq!(u32_at(&header, 40), u32::MAX - 36);
+    }
+
+    #[test]
+    fn only_raw_formats_get_a_header() {
+        for format in ALL_OUTPUT_FORMATS {
+            assert_eq!(
+                format.get_wav_header(Some(0)).is_some(),
+                format.get_format().is_raw(),
+                "{}",
+                format
+            );
+        }
+        assert_eq!(MP3_44100HZ_128KBPS.to_playable(vec![1, 2, 3]), [1, 2, 3]);
+        assert_eq!(PCM_8000HZ.to_playable(vec![1, 2, 3]).len(), 44 + 3);
+    }
+
+    #[test]
+    fn format_strings_round_trip() {
+        for format in ALL_OUTPUT_FORMATS {
+            let parsed = parse_output_format(&format.to_string()).unwrap();
+            assert!(std::ptr::eq(parsed, *format), "{}", format);
+        }
+        assert_eq!(MP3_44100HZ_128KBPS.to_string(), "mp3_44100_128");
+        assert_eq!(PCM_24000HZ.to_string(), "pcm_24000");
+        assert_eq!(ULAW_8000HZ.to_string(), "ulaw_8000");
+        assert!(std::ptr::eq(
+            parse_output_format(" OPUS_48000_64 ").unwrap(),
+            OPUS_48000HZ_64KBPS
+        ));
+        assert!(parse_output_format("mp3_44100").is_none());
+        assert!(parse_output_format("wav_44100").is_none());
     }
 }
@@ -1,23 +1,52 @@
+pub mod error;
 pub mod media;
+pub mod models;
+pub mod pronunciation;
 pub mod requests;
 pub mod responses;
+pub mod retry;
 pub mod types;
+pub mod websocket;
+
+use std::collections::HashMap;
 
 use bytes::Bytes;
 use chrono::DateTime;
-use log::{debug, error, info};
-use media::DEFAULT_OUTPUT_FORMAT;
-use responses::{UserInfo, VoiceList};
+use error::ElevenLabsError;
+use log::{debug, error, info, warn};
+use media::get_default_output_format;
+use responses::{
+    AddVoiceResponse, HistoryPage, SpeechWithTimestampsResponse, StatusResponse, UserInfo,
+    VoiceList,
+};
+use retry::{RequestKind, RetryPolicy, is_retryable, parse_retry_after};
 use serde::Serialize;
-use types::{SpeechModel, VoiceSettings};
+use tokio::sync::RwLock;
+use types::{
+    CharacterStats, DialogueLine, HistoryItem, SpeechOptions, SpeechWithTimestamps, Transcription,
+    TranscriptionOptions, UsageBreakdown, Voice, VoiceListFilter, VoiceSettings,
+};
+
+pub const DEFAULT_API_BASE: &str = "https://api.elevenlabs.io/";
+
+// Identifies each generation, so later requests can be stitched onto it
+const REQUEST_ID_HEADER: &str = "request-id";
 
-type Error = Box<dyn std::error::Error + Send + Sync>;
+// The only speech-to-text model at the moment
+const DEFAULT_TRANSCRIPTION_MODEL: &str = "scribe_v1";
 
-const API_BASE: &str = "https://api.elevenlabs.io/";
+// The largest page size v2/voices allows
+const VOICE_LIST_PAGE_SIZE: &str = "100";
 
 pub struct ElevenLabs {
     api_key: String,
+    api_base: String,
     client: reqwest::Client,
+    read_retry_policy: RetryPolicy,
+    generation_retry_policy: RetryPolicy,
+    write_retry_policy: RetryPolicy,
+    /// Stored settings of each voice we've looked up, by voice ID
+    voice_settings_cache: RwLock<HashMap<String, VoiceSettings>>,
 }
 
 impl serenity::prelude::TypeMapKey for ElevenLabs {
@@ -26,8 +55,45 @@ impl serenity::prelude::TypeMapKey for ElevenLabs {
 
 impl ElevenLabs {
     pub fn new_from_key(api_key: String) -> Self {
+        Self::new_with_base_url(api_key, DEFAULT_API_BASE.to_string())
+    }
+
+    /// Creates a client talking to a different API host, e.g. a local mock server
+    pub fn new_with_base_url(api_key: String, api_base: String) -> Self {
         let client = reqwest::Client::new();
-        Self { api_key, client }
+        // Endpoints are joined onto the base without a separator, so make sure it ends in one
+        let api_base = if api_base.ends_with('/') {
+            api_base
+        } else {
+            format!("{}/", api_base)
+        };
+        Self {
+            api_key,
+            api_base,
+            client,
+            read_retry_policy: RetryPolicy::from_env(RequestKind::Read),
+            generation_retry_policy: RetryPolicy::from_env(RequestKind::Generation),
+            write_retry_policy: RetryPolicy::from_env(RequestKind::Write),
+            voice_settings_cache: RwLock::new(HashMap::new()),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn with_retry_policy(mut self, kind: RequestKind, policy: RetryPolicy) -> Self {
+        match kind {
+            RequestKind::Read => self.read_retry_policy = policy,
+            RequestKind::Generation => self.generation_retry_policy = policy,
+            RequestKind::Write => self.write_retry_policy = policy,
+        }
+        self
+    }
+
+    fn get_retry_policy(&self, kind: RequestKind) -> &RetryPolicy {
+        match kind {
+            RequestKind::Read => &self.read_retry_policy,
+            RequestKind::Generation => &self.generation_retry_policy,
+            RequestKind::Write => &self.write_retry_policy,
+        }
     }
 
     fn get_base_request(
@@ -36,7 +102,7 @@ impl ElevenLabs {
         query: Vec<(&str, &str)>,
     ) -> reqwest::RequestBuilder {
         self.client
-            .get(format!("{}{}", API_BASE, endpoint))
+            .get(format!("{}{}", self.api_base, endpoint))
             .header("xi-api-key", &self.api_key)
             .query(&query)
     }
@@ -47,36 +113,95 @@ impl ElevenLabs {
         query: Vec<(&str, &str)>,
     ) -> reqwest::RequestBuilder {
         self.client
-            .post(format!("{}{}", API_BASE, endpoint))
+            .post(format!("{}{}", self.api_base, endpoint))
             .header("xi-api-key", &self.api_key)
             .query(&query)
     }
 
+    fn delete_base_request(&self, endpoint: &str) -> reqwest::RequestBuilder {
+        self.client
+            .delete(format!("{}{}", self.api_base, endpoint))
+            .header("xi-api-key", &self.api_key)
+    }
+
+    /// Sends the request, retrying rate limited and transient failures according to the policy for `kind`.
+    /// Only returns successful (2xx) responses.
+    async fn send_with_retry(
+        &self,
+        req: reqwest::RequestBuilder,
+        kind: RequestKind,
+    ) -> Result<reqwest::Response, ElevenLabsError> {
+        let policy = self.get_retry_policy(kind);
+        let mut retries: u32 = 0;
+        let mut next_req = Some(req);
+
+        loop {
+            let current_req = next_req
+                .take()
+                .expect("only looping again if the request could be cloned");
+            // Requests with streaming bodies can't be cloned, and therefore can't be retried
+            next_req = current_req.try_clone();
+
+            let built_req = current_req.build()?;
+            debug!(url = built_req.url().as_str(), retries = retries; "Sending request");
+
+            let (err, retry_after) = match self.client.execute(built_req).await {
+                Ok(resp) if resp.status().is_success() => return Ok(resp),
+                Ok(resp) => {
+                    // HTTP error here...
+                    let status = resp.status();
+                    let retry_after = parse_retry_after(resp.headers());
+                    let text = resp.text().await?;
+                    error!(response_body = text.as_str(), status = status.as_str(), retries = retries; "HTTP request failed");
+                    (ElevenLabsError::from_response(status, &text), retry_after)
+                }
+                Err(e) => {
+                    error!(error = e.to_string().as_str(), retries = retries; "HTTP request failed to send");
+                    (e.into(), None)
+                }
+            };
+
+            retries += 1;
+            let delay = match next_req {
+                Some(_) if is_retryable(&err, kind) => policy.get_delay(retries, retry_after),
+                _ => None,
+            };
+            match delay {
+                Some(delay) => {
+                    warn!(
+                        error = err.to_string().as_str(), retries = retries, delay_ms = delay.as_millis() as u64;
+                        "Retrying ElevenLabs request"
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                None => return Err(err),
+            }
+        }
+    }
+
     #[allow(dead_code)]
     async fn execute_request_json_response<ResultType: serde::de::DeserializeOwned>(
         &self,
         req: reqwest::RequestBuilder,
-    ) -> Result<ResultType, Error> {
-        let built_req = req.build()?;
-        debug!(url = built_req.url().as_str(); "Sending request");
-        let resp = self.client.execute(built_req).await?;
-        let status = resp.status();
+        kind: RequestKind,
+    ) -> Result<ResultType, ElevenLabsError> {
+        let resp = self.send_with_retry(req, kind).await?;
+        Self::parse_json_response(resp).await
+    }
+
+    async fn parse_json_response<ResultType: serde::de::DeserializeOwned>(
+        resp: reqwest::Response,
+    ) -> Result<ResultType, ElevenLabsError> {
         let text = resp.text().await?;
-        if status.is_success() {
-            // Successful HTTP request, parse json here...
-            let parsed = serde_json::from_str::<ResultType>(&text);
-            match parsed {
-                Ok(p) => Ok(p),
-                Err(e) => {
-                    // Parsing error
-                    error!(error = e.to_string().as_str(), text = text.as_str(); "Failed to parse response");
-                    Err(e)?
-                }
+        // Successful HTTP request, parse json here...
+        let parsed = serde_json::from_str::<ResultType>(&text);
+        match parsed {
+            Ok(p) => Ok(p),
+            Err(e) => {
+                // Parsing error
+                error!(error = e.to_string().as_str(), text = text.as_str(); "Failed to parse response");
+                Err(e.into())
             }
-        } else {
-            // HTTP error here...
-            error!(response_body = text.as_str(), status = status.as_str(); "HTTP request failed");
-            Err(format!("Error HTTP {}: {}", status.as_str(), text))?
         }
     }
 
@@ -84,19 +209,10 @@ impl ElevenLabs {
     async fn execute_request_cursor_response(
         &self,
         req: reqwest::RequestBuilder,
-    ) -> Result<impl futures_core::Stream<Item = reqwest::Result<Bytes>>, Error> {
-        let built_req = req.build()?;
-        debug!(url = built_req.url().as_str(); "Sending request (getting raw body)");
-        let resp = self.client.execute(built_req).await?;
-        let status = resp.status();
-        if status.is_success() {
-            Ok(resp.bytes_stream())
-        } else {
-            // HTTP error here...
-            let text = resp.text().await?;
-            error!(response_body = text.as_str(), status = status.as_str(); "HTTP request failed");
-            Err(format!("Error HTTP {}: {}", status.as_str(), text))?
-        }
+        kind: RequestKind,
+    ) -> Result<impl futures_core::Stream<Item = reqwest::Result<Bytes>>, ElevenLabsError> {
+        let resp = self.send_with_retry(req, kind).await?;
+        Ok(resp.bytes_stream())
     }
 
     #[allow(dead_code)]
@@ -107,8 +223,9 @@ impl ElevenLabs {
         &self,
         base_req: reqwest::RequestBuilder,
         body: Option<BodyType>,
-    ) -> Result<ResultType, Error> {
-        self.execute_request_json_response(base_req.json(&body))
+        kind: RequestKind,
+    ) -> Result<ResultType, ElevenLabsError> {
+        self.execute_request_json_response(base_req.json(&body), kind)
             .await
     }
 
@@ -116,8 +233,9 @@ impl ElevenLabs {
     async fn run_json_request_no_body<ResultType: serde::de::DeserializeOwned>(
         &self,
         base_req: reqwest::RequestBuilder,
-    ) -> Result<ResultType, Error> {
-        self.execute_request_json_response(base_req).await
+        kind: RequestKind,
+    ) -> Result<ResultType, ElevenLabsError> {
+        self.execute_request_json_response(base_req, kind).await
     }
 
     #[allow(dead_code)]
@@ -125,8 +243,9 @@ impl ElevenLabs {
         &self,
         base_req: reqwest::RequestBuilder,
         body: Option<BodyType>,
-    ) -> Result<impl futures_core::Stream<Item = reqwest::Result<Bytes>>, Error> {
-        self.execute_request_cursor_response(base_req.json(&body))
+        kind: RequestKind,
+    ) -> Result<impl futures_core::Stream<Item = reqwest::Result<Bytes>>, ElevenLabsError> {
+        self.execute_request_cursor_response(base_req.json(&body), kind)
             .await
     }
 
@@ -134,13 +253,19 @@ impl ElevenLabs {
     async fn run_cursor_request_no_body(
         &self,
         base_req: reqwest::RequestBuilder,
-    ) -> Result<impl futures_core::Stream<Item = reqwest::Result<Bytes>>, Error> {
-        self.execute_request_cursor_response(base_req).await
+        kind: RequestKind,
+    ) -> Result<impl futures_core::Stream<Item = reqwest::Result<Bytes>>, ElevenLabsError> {
+        self.execute_request_cursor_response(base_req, kind).await
     }
 
-    pub async fn get_usage(&self) -> Result<(i64, i64, Option<DateTime<chrono::Utc>>), Error> {
+    pub async fn get_usage(
+        &self,
+    ) -> Result<(i64, i64, Option<DateTime<chrono::Utc>>), ElevenLabsError> {
         let user_info = self
-            .run_json_request_no_body::<UserInfo>(self.get_base_request("v1/user", Vec::new()))
+            .run_json_request_no_body::<UserInfo>(
+                self.get_base_request("v1/user", Vec::new()),
+                RequestKind::Read,
+            )
             .await?;
 
         Ok((
@@ -153,48 +278,837 @@ impl ElevenLabs {
         ))
     }
 
-    #[allow(dead_code)]
-    pub async fn get_voice_list(&self) -> Result<VoiceList, Error> {
-        self.run_json_request_no_body(self.get_base_request("v2/voices", Vec::new()))
+    /// Characters used per day between `start` and `end`, optionally split by voice or model
+    pub async fn get_character_stats(
+        &self,
+        start: DateTime<chrono::Utc>,
+        end: DateTime<chrono::Utc>,
+        breakdown: UsageBreakdown,
+    ) -> Result<CharacterStats, ElevenLabsError> {
+        let start_unix = start.timestamp_millis().to_string();
+        let end_unix = end.timestamp_millis().to_string();
+        let breakdown_type = breakdown.get_id();
+        self.run_json_request_no_body(
+            self.get_base_request(
+                "v1/usage/character-stats",
+                vec![
+                    ("start_unix", start_unix.as_str()),
+                    ("end_unix", end_unix.as_str()),
+                    ("breakdown_type", breakdown_type.as_str()),
+                    ("aggregation_interval", "day"),
+                ],
+            ),
+            RequestKind::Read,
+        )
+        .await
+    }
+
+    /// Lists every voice matching the filter, following the API's pagination until there are no more
+    pub async fn get_voice_list(
+        &self,
+        filter: &VoiceListFilter,
+    ) -> Result<Vec<Voice>, ElevenLabsError> {
+        let mut voices = Vec::new();
+        let mut page_token: Option<String> = None;
+
+        loop {
+            let page = self
+                .get_voice_list_page(filter, page_token.as_deref())
+                .await?;
+            debug!(
+                count = page.voices.len(), has_more = page.has_more, total_count = page.total_count;
+                "Fetched page of voices"
+            );
+            voices.extend(page.voices);
+
+            match page.next_page_token {
+                Some(token) if page.has_more => page_token = Some(token),
+                _ => break,
+            }
+        }
+
+        Ok(voices)
+    }
+
+    async fn get_voice_list_page(
+        &self,
+        filter: &VoiceListFilter,
+        page_token: Option<&str>,
+    ) -> Result<VoiceList, ElevenLabsError> {
+        let voice_type = filter.voice_type.map(|t| t.get_id());
+        let category = filter.category.map(|c| c.get_id());
+
+        let mut query = vec![("page_size", VOICE_LIST_PAGE_SIZE)];
+        if let Some(search) = filter.search.as_deref() {
+            query.push(("search", search));
+        }
+        if let Some(voice_type) = voice_type.as_deref() {
+            query.push(("voice_type", voice_type));
+        }
+        if let Some(category) = category.as_deref() {
+            query.push(("category", category));
+        }
+        if let Some(page_token) = page_token {
+            query.push(("next_page_token", page_token));
+        }
+
+        self.run_json_request_no_body(self.get_base_request("v2/voices", query), RequestKind::Read)
             .await
     }
 
+    /// Creates an instant voice clone from recordings of someone speaking, returning the new voice's ID.
+    /// Like other uploads, this isn't retried.
+    pub async fn add_voice(
+        &self,
+        name: String,
+        samples: Vec<(String, Vec<u8>)>,
+        description: Option<String>,
+        labels: HashMap<String, String>,
+        remove_background_noise: bool,
+    ) -> Result<String, ElevenLabsError> {
+        info!(
+            name = name.as_str(), samples = samples.len();
+            "Cloning voice with labels {:?}", labels
+        );
+
+        let mut form = reqwest::multipart::Form::new().text("name", name).text(
+            "remove_background_noise",
+            remove_background_noise.to_string(),
+        );
+        for (filename, data) in samples {
+            form = form.part(
+                "files",
+                reqwest::multipart::Part::bytes(data).file_name(filename),
+            );
+        }
+        if let Some(description) = description {
+            form = form.text("description", description);
+        }
+        if !labels.is_empty() {
+            // Multipart fields are plain strings, so the labels go in as JSON
+            form = form.text("labels", serde_json::to_string(&labels)?);
+        }
+
+        let resp: AddVoiceResponse = self
+            .run_json_request_no_body(
+                self.post_base_request("v1/voices/add", Vec::new())
+                    .multipart(form),
+                RequestKind::Generation,
+            )
+            .await?;
+        if resp.requires_verification {
+            warn!(voice_id = resp.voice_id.as_str(); "New voice needs to be verified before it can be used");
+        }
+        Ok(resp.voice_id)
+    }
+
+    pub async fn delete_voice(&self, voice_id: &str) -> Result<(), ElevenLabsError> {
+        info!(voice_id = voice_id; "Deleting voice");
+        let _: StatusResponse = self
+            .run_json_request_no_body(
+                self.delete_base_request(&format!("v1/voices/{}", voice_id)),
+                RequestKind::Write,
+            )
+            .await?;
+
+        self.voice_settings_cache.write().await.remove(voice_id);
+        Ok(())
+    }
+
+    /// The settings stored for a voice on ElevenLabs, which are used for any setting a request leaves out
+    pub async fn get_voice_settings(
+        &self,
+        voice_id: &str,
+    ) -> Result<VoiceSettings, ElevenLabsError> {
+        let settings: VoiceSettings = self
+            .run_json_request_no_body(
+                self.get_base_request(&format!("v1/voices/{}/settings", voice_id), Vec::new()),
+                RequestKind::Read,
+            )
+            .await?;
+
+        self.voice_settings_cache
+            .write()
+            .await
+            .insert(voice_id.to_string(), settings.clone());
+        Ok(settings)
+    }
+
+    /// Like `get_voice_settings`, but only asks the API the first time each voice is looked up
+    pub async fn get_cached_voice_settings(
+        &self,
+        voice_id: &str,
+    ) -> Result<VoiceSettings, ElevenLabsError> {
+        if let Some(settings) = self.voice_settings_cache.read().await.get(voice_id) {
+            return Ok(settings.clone());
+        }
+        self.get_voice_settings(voice_id).await
+    }
+
+    /// The settings ElevenLabs gives new voices
+    pub async fn get_default_voice_settings(&self) -> Result<VoiceSettings, ElevenLabsError> {
+        self.run_json_request_no_body(
+            self.get_base_request("v1/voices/settings/default", Vec::new()),
+            RequestKind::Read,
+        )
+        .await
+    }
+
+    /// Stores new settings for a voice. Settings left as None are unchanged.
+    pub async fn edit_voice_settings(
+        &self,
+        voice_id: &str,
+        settings: &VoiceSettings,
+    ) -> Result<(), ElevenLabsError> {
+        info!(voice_id = voice_id; "Editing voice settings to {:?}", settings);
+        let _: StatusResponse = self
+            .run_json_request_with_body(
+                self.post_base_request(
+                    &format!("v1/voices/{}/settings/edit", voice_id),
+                    Vec::new(),
+                ),
+                Some(settings),
+                RequestKind::Write,
+            )
+            .await?;
+
+        // Rather than guessing how the API merged the settings, the next lookup reads them back
+        self.voice_settings_cache.write().await.remove(voice_id);
+        Ok(())
+    }
+
+    /// One page of past generations, newest first. Pass the previous page's `last_history_item_id` to get the next one.
+    pub async fn get_history(
+        &self,
+        page_size: u32,
+        start_after: Option<&str>,
+        voice_id: Option<&str>,
+    ) -> Result<HistoryPage, ElevenLabsError> {
+        let page_size = page_size.to_string();
+        let mut query = vec![("page_size", page_size.as_str())];
+        if let Some(start_after) = start_after {
+            query.push(("start_after_history_item_id", start_after));
+        }
+        if let Some(voice_id) = voice_id {
+            query.push(("voice_id", voice_id));
+        }
+
+        self.run_json_request_no_body(
+            self.get_base_request("v1/history", query),
+            RequestKind::Read,
+        )
+        .await
+    }
+
+    /// A past generation's details, like its voice and text
+    pub async fn get_history_item(
+        &self,
+        history_item_id: &str,
+    ) -> Result<HistoryItem, ElevenLabsError> {
+        check_history_item_id(history_item_id)?;
+        self.run_json_request_no_body(
+            self.get_base_request(&format!("v1/history/{}", history_item_id), Vec::new()),
+            RequestKind::Read,
+        )
+        .await
+    }
+
+    /// The audio of a past generation. Doesn't use any characters.
+    pub async fn get_history_item_audio(
+        &self,
+        history_item_id: &str,
+    ) -> Result<impl futures_core::Stream<Item = reqwest::Result<Bytes>>, ElevenLabsError> {
+        check_history_item_id(history_item_id)?;
+        self.run_cursor_request_no_body(
+            self.get_base_request(&format!("v1/history/{}/audio", history_item_id), Vec::new()),
+            RequestKind::Read,
+        )
+        .await
+    }
+
+    pub async fn delete_history_item(&self, history_item_id: &str) -> Result<(), ElevenLabsError> {
+        check_history_item_id(history_item_id)?;
+        info!(history_item_id = history_item_id; "Deleting history item");
+        let _: StatusResponse = self
+            .run_json_request_no_body(
+                self.delete_base_request(&format!("v1/history/{}", history_item_id)),
+                RequestKind::Write,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Downloads past generations along with their content type: the audio file itself for a single item,
+    /// or a zip of all of them otherwise
+    pub async fn download_history_items(
+        &self,
+        history_item_ids: Vec<String>,
+    ) -> Result<(Option<String>, Bytes), ElevenLabsError> {
+        let req = self
+            .post_base_request("v1/history/download", Vec::new())
+            .json(&requests::DownloadHistoryRequest { history_item_ids });
+        let resp = self.send_with_retry(req, RequestKind::Read).await?;
+        let content_type = resp
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string());
+        Ok((content_type, resp.bytes().await?))
+    }
+
+    /// Generates speech, and also returns the request's ID for stitching later requests onto it
     #[allow(dead_code)]
     pub async fn generate_voice(
         &self,
         voice_id: String,
         text: String,
         voice_settings: Option<VoiceSettings>,
-        model_id: Option<SpeechModel>,
+        model_id: Option<String>,
+        media_format: Option<&media::OutputFormat>,
+        options: SpeechOptions,
+    ) -> Result<
+        (
+            Option<String>,
+            impl futures_core::Stream<Item = reqwest::Result<Bytes>>,
+        ),
+        ElevenLabsError,
+    > {
+        self.run_speech_request(
+            format!("v1/text-to-speech/{}", voice_id),
+            voice_id,
+            text,
+            voice_settings,
+            model_id,
+            media_format,
+            options,
+        )
+        .await
+    }
+
+    /// Like `generate_voice`, but uses the streaming endpoint so the first chunks of audio arrive
+    /// while the rest is still being generated
+    pub async fn stream_voice(
+        &self,
+        voice_id: String,
+        text: String,
+        voice_settings: Option<VoiceSettings>,
+        model_id: Option<String>,
+        media_format: Option<&media::OutputFormat>,
+        options: SpeechOptions,
+    ) -> Result<
+        (
+            Option<String>,
+            impl futures_core::Stream<Item = reqwest::Result<Bytes>>,
+        ),
+        ElevenLabsError,
+    > {
+        self.run_speech_request(
+            format!("v1/text-to-speech/{}/stream", voice_id),
+            voice_id,
+            text,
+            voice_settings,
+            model_id,
+            media_format,
+            options,
+        )
+        .await
+    }
+
+    /// Generates speech along with the time at which each character is spoken
+    #[allow(dead_code)]
+    pub async fn generate_voice_with_timestamps(
+        &self,
+        voice_id: String,
+        text: String,
+        voice_settings: Option<VoiceSettings>,
+        model_id: Option<String>,
+        media_format: Option<&media::OutputFormat>,
+        options: SpeechOptions,
+    ) -> Result<SpeechWithTimestamps, ElevenLabsError> {
+        let (req, body) = self.build_speech_request(
+            format!("v1/text-to-speech/{}/with-timestamps", voice_id),
+            voice_id,
+            text,
+            voice_settings,
+            model_id,
+            media_format,
+            options,
+        );
+        let resp = self
+            .send_with_retry(req.json(&body), RequestKind::Generation)
+            .await?;
+        let request_id = get_request_id(&resp);
+        let resp: SpeechWithTimestampsResponse = Self::parse_json_response(resp).await?;
+
+        Ok(SpeechWithTimestamps {
+            request_id,
+            audio: resp.decode_audio()?,
+            alignment: resp.alignment,
+            normalized_alignment: resp.normalized_alignment,
+        })
+    }
+
+    /// Generates a sound effect from a description of it.
+    /// Without a duration, the API picks one that suits the prompt.
+    pub async fn generate_sound_effect(
+        &self,
+        text: String,
+        duration_seconds: Option<f32>,
+        prompt_influence: Option<f32>,
+        media_format: Option<&media::OutputFormat>,
+    ) -> Result<impl futures_core::Stream<Item = reqwest::Result<Bytes>>, ElevenLabsError> {
+        let final_format = match media_format {
+            Some(format) => format,
+            None => get_default_output_format(),
+        };
+        let output_format = final_format.to_string();
+
+        info!(
+            text = text.as_str();
+            "Generating sound effect with duration {:?} and prompt influence {:?}", duration_seconds, prompt_influence
+        );
+        self.run_cursor_request_with_body(
+            self.post_base_request(
+                "v1/sound-generation",
+                vec![("output_format", output_format.as_str())],
+            ),
+            Some(requests::CreateSoundEffectRequest {
+                text,
+                duration_seconds,
+                prompt_influence,
+            }),
+            RequestKind::Generation,
+        )
+        .await
+    }
+
+    /// Generates a conversation between several voices as a single clip
+    pub async fn generate_dialogue(
+        &self,
+        lines: Vec<DialogueLine>,
+        model_id: Option<String>,
+        media_format: Option<&media::OutputFormat>,
+        options: SpeechOptions,
+    ) -> Result<impl futures_core::Stream<Item = reqwest::Result<Bytes>>, ElevenLabsError> {
+        let final_format = match media_format {
+            Some(format) => format,
+            None => get_default_output_format(),
+        };
+        let output_format = final_format.to_string();
+
+        info!(
+            lines = lines.len(),
+            model_id = model_id.as_deref().unwrap_or("default");
+            "Generating dialogue"
+        );
+        self.run_cursor_request_with_body(
+            self.post_base_request(
+                "v1/text-to-dialogue",
+                vec![("output_format", output_format.as_str())],
+            ),
+            Some(requests::CreateDialogueRequest {
+                inputs: lines,
+                model_id,
+                pronunciation_dictionary_locators: options.pronunciation_dictionary_locators,
+            }),
+            RequestKind::Generation,
+        )
+        .await
+    }
+
+    /// Re-voices recorded speech with another voice, keeping its timing and delivery.
+    /// The upload can't be replayed, so unlike other generation requests this isn't retried.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn convert_speech(
+        &self,
+        voice_id: String,
+        audio: Vec<u8>,
+        filename: String,
+        model_id: Option<String>,
+        voice_settings: Option<VoiceSettings>,
+        remove_background_noise: bool,
         media_format: Option<&media::OutputFormat>,
-    ) -> Result<impl futures_core::Stream<Item = reqwest::Result<Bytes>>, Error> {
+    ) -> Result<impl futures_core::Stream<Item = reqwest::Result<Bytes>>, ElevenLabsError> {
+        let final_format = match media_format {
+            Some(format) => format,
+            None => get_default_output_format(),
+        };
+        let output_format = final_format.to_string();
+
+        info!(
+            voice_id = voice_id.as_str(), filename = filename.as_str(), size = audio.len();
+            "Converting speech with model {:?} and options {:?}", model_id, voice_settings
+        );
+
+        let mut form = reqwest::multipart::Form::new()
+            .part(
+                "audio",
+                reqwest::multipart::Part::bytes(audio).file_name(filename),
+            )
+            .text(
+                "remove_background_noise",
+                remove_background_noise.to_string(),
+            );
+        if let Some(model_id) = model_id {
+            form = form.text("model_id", model_id);
+        }
+        if let Some(voice_settings) = voice_settings {
+            // Multipart fields are plain strings, so the settings go in as JSON
+            form = form.text("voice_settings", serde_json::to_string(&voice_settings)?);
+        }
+
+        self.execute_request_cursor_response(
+            self.post_base_request(
+                &format!("v1/speech-to-speech/{}", voice_id),
+                vec![("output_format", output_format.as_str())],
+            )
+            .multipart(form),
+            RequestKind::Generation,
+        )
+        .await
+    }
+
+    /// Turns recorded speech into text. Like `convert_speech`, this isn't retried.
+    pub async fn transcribe(
+        &self,
+        audio: Vec<u8>,
+        filename: String,
+        options: &TranscriptionOptions,
+    ) -> Result<Transcription, ElevenLabsError> {
+        info!(
+            filename = filename.as_str(), size = audio.len();
+            "Transcribing audio with options {:?}", options
+        );
+
+        // Speakers are only reported per word, so diarization needs word timestamps too
+        let granularity = if options.timestamps || options.diarize {
+            "word"
+        } else {
+            "none"
+        };
+        let mut form = reqwest::multipart::Form::new()
+            .part(
+                "file",
+                reqwest::multipart::Part::bytes(audio).file_name(filename),
+            )
+            .text(
+                "model_id",
+                options
+                    .model_id
+                    .clone()
+                    .unwrap_or_else(|| DEFAULT_TRANSCRIPTION_MODEL.to_string()),
+            )
+            .text("diarize", options.diarize.to_string())
+            .text("timestamps_granularity", granularity);
+        if let Some(language_code) = &options.language_code {
+            form = form.text("language_code", language_code.clone());
+        }
+
+        self.run_json_request_no_body(
+            self.post_base_request("v1/speech-to-text", Vec::new())
+                .multipart(form),
+            RequestKind::Generation,
+        )
+        .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn run_speech_request(
+        &self,
+        endpoint: String,
+        voice_id: String,
+        text: String,
+        voice_settings: Option<VoiceSettings>,
+        model_id: Option<String>,
+        media_format: Option<&media::OutputFormat>,
+        options: SpeechOptions,
+    ) -> Result<
+        (
+            Option<String>,
+            impl futures_core::Stream<Item = reqwest::Result<Bytes>>,
+        ),
+        ElevenLabsError,
+    > {
+        let (req, body) = self.build_speech_request(
+            endpoint,
+            voice_id,
+            text,
+            voice_settings,
+            model_id,
+            media_format,
+            options,
+        );
+        let resp = self
+            .send_with_retry(req.json(&body), RequestKind::Generation)
+            .await?;
+        Ok((get_request_id(&resp), resp.bytes_stream()))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn build_speech_request(
+        &self,
+        endpoint: String,
+        voice_id: String,
+        text: String,
+        voice_settings: Option<VoiceSettings>,
+        model_id: Option<String>,
+        media_format: Option<&media::OutputFormat>,
+        options: SpeechOptions,
+    ) -> (reqwest::RequestBuilder, requests::CreateSpeechRequest) {
         let final_format = match media_format {
             Some(format) => format,
             None => {
-                let format = DEFAULT_OUTPUT_FORMAT;
-                info!(
-                    "No media format provided, using default {}",
-                    format.to_string()
-                );
+                let format = get_default_output_format();
+                info!("No media format provided, using default {}", format);
                 format
             }
         };
-        let mut query = Vec::new();
-        query.push(("output_format", final_format.to_string()));
+        let output_format = final_format.to_string();
+        let query = vec![("output_format", output_format.as_str())];
 
         info!(
             voice_id = voice_id.as_str(), text = text.as_str();
             "Generating voice with model {:?} and options {:?}", model_id, voice_settings
         );
 
-        self.run_cursor_request_with_body(
-            self.post_base_request(&format!("v1/text-to-speech/{}", voice_id), Vec::new()),
-            Some(requests::CreateSpeechRequest {
+        (
+            self.post_base_request(&endpoint, query),
+            requests::CreateSpeechRequest {
                 text,
                 voice_settings,
-                model_id: model_id.map(|m| m.get_id()),
-            }),
+                model_id,
+                pronunciation_dictionary_locators: options.pronunciation_dictionary_locators,
+                previous_text: options.previous_text,
+                next_text: options.next_text,
+                previous_request_ids: options.previous_request_ids,
+                next_request_ids: options.next_request_ids,
+                seed: options.seed,
+                language_code: options.language_code,
+                apply_text_normalization: options.apply_text_normalization,
+                apply_language_text_normalization: options.apply_language_text_normalization,
+            },
         )
-        .await
+    }
+}
+
+/// History item IDs come from users, so make sure they can't point the request at another endpoint
+fn check_history_item_id(history_item_id: &str) -> Result<(), ElevenLabsError> {
+    if history_item_id.is_empty() || !history_item_id.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(ElevenLabsError::InvalidId {
+            kind: "history item ID",
+            id: history_item_id.to_string(),
+        });
+    }
+    Ok(())
+}
+
+fn get_request_id(resp: &reqwest::Response) -> Option<String> {
+    resp.headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::streamutil::write_stream_to_vec_u8;
+    use crate::testing::start_mock_server;
+
+    async fn mock_client() -> ElevenLabs {
+        ElevenLabs::new_with_base_url("test-key".to_string(), start_mock_server().await)
+    }
+
+    #[tokio::test]
+    async fn get_usage_reads_subscription() {
+        let (used, limit, reset) = mock_client().await.get_usage().await.unwrap();
+        assert_eq!((used, limit), (1234, 100000));
+        assert!(reset.unwrap() > chrono::Utc::now());
+    }
+
+    #[tokio::test]
+    async fn get_voice_list_filters_and_follows_pages() {
+        let client = mock_client().await;
+        let voices = client
+            .get_voice_list(&VoiceListFilter::default())
+            .await
+            .unwrap();
+        for name in ["Scotty", "June", "UnrealTournament", "Mock Narrator"] {
+            assert!(voices.iter().any(|v| v.name == name), "missing {}", name);
+        }
+
+        let filter = VoiceListFilter {
+            search: Some("narrator".to_string()),
+            ..Default::default()
+        };
+        let voices = client.get_voice_list(&filter).await.unwrap();
+        assert_eq!(voices.len(), 1);
+        assert_eq!(voices[0].voice_id, "mockVoice0000000000A");
+    }
+
+    #[tokio::test]
+    async fn generate_voice_returns_audio_in_requested_format() {
+        let client = mock_client().await;
+        let (request_id, stream) = client
+            .generate_voice(
+                "mockVoice0000000000A".to_string(),
+                "Fifteen letters".to_string(),
+                None,
+                None,
+                Some(media::PCM_16000HZ),
+                SpeechOptions::default(),
+            )
+            .await
+            .unwrap();
+        let audio = write_stream_to_vec_u8(stream).await.unwrap();
+        assert!(request_id.is_some());
+        // The mock speaks 15 characters a second, as 16-bit samples
+        assert_eq!(audio.len(), 16000 * 2);
+    }
+
+    #[tokio::test]
+    async fn rejects_history_item_ids_outside_history() {
+        let client = mock_client().await;
+        for id in ["../voices/mockVoice0000000000A", "abc?x=1", "a/b", ""] {
+            assert!(matches!(
+                client.delete_history_item(id).await,
+                Err(ElevenLabsError::InvalidId { .. })
+            ));
+            assert!(matches!(
+                client.get_history_item_audio(id).await,
+                Err(ElevenLabsError::InvalidId { .. })
+            ));
+            assert!(matches!(
+                client.get_history_item(id).await,
+                Err(ElevenLabsError::InvalidId { .. })
+            ));
+        }
+        let voices = client
+            .get_voice_list(&VoiceListFilter::default())
+            .await
+            .unwrap();
+        assert!(voices.iter().any(|v| v.voice_id == "mockVoice0000000000A"));
+    }
+
+    #[tokio::test]
+    async fn get_history_item_reads_back_a_generation() {
+        let client = mock_client().await;
+        let (request_id, stream) = client
+            .generate_voice(
+                "mockVoice0000000000A".to_string(),
+                "Read me back".to_string(),
+                None,
+                None,
+                None,
+                SpeechOptions::default(),
+            )
+            .await
+            .unwrap();
+        write_stream_to_vec_u8(stream).await.unwrap();
+        let page = client
+            .get_history(100, None, Some("mockVoice0000000000A"))
+            .await
+            .unwrap();
+        let id = page
+            .history
+            .into_iter()
+            .find(|i| i.request_id == request_id)
+            .unwrap()
+            .history_item_id;
+
+        let item = client.get_history_item(&id).await.unwrap();
+        assert_eq!(item.text.as_deref(), Some("Read me back"));
+        assert_eq!(item.voice_id.as_deref(), Some("mockVoice0000000000A"));
+        assert!(matches!(
+            client.get_history_item("mockHistoryMissing").await,
+            Err(ElevenLabsError::Api { detail_status: Some(status), .. })
+                if status == "history_item_not_found"
+        ));
+    }
+
+    async fn transcribe(options: TranscriptionOptions) -> Transcription {
+        mock_client()
+            .await
+            .transcribe(vec![0; 16], "clip.mp3".to_string(), &options)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn transcribe_plain_text() {
+        let transcription = transcribe(TranscriptionOptions::default()).await;
+        assert_eq!(
+            transcription.text,
+            "Welcome back to the show. Thanks, it's great to be here."
+        );
+        assert_eq!(transcription.language_code.as_deref(), Some("en"));
+        assert!(transcription.words.is_empty());
+        assert_eq!(transcription.segments().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn transcribe_with_diarization() {
+        let transcription = transcribe(TranscriptionOptions {
+            diarize: true,
+            ..Default::default()
+        })
+        .await;
+        let segments = transcription
+            .segments()
+            .into_iter()
+            .map(|s| (s.speaker_id.unwrap(), s.text))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            segments,
+            [
+                (
+                    "speaker_0".to_string(),
+                    "Welcome back to the show.".to_string()
+                ),
+                (
+                    "speaker_1".to_string(),
+                    "Thanks, it's great to be here.".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn transcribe_with_timestamps() {
+        let transcription = transcribe(TranscriptionOptions {
+            timestamps: true,
+            language_code: Some("de".to_string()),
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(transcription.language_code.as_deref(), Some("de"));
+        assert!(transcription.words.iter().all(|w| w.start.is_some()));
+        let segments = transcription.segments();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].start_seconds, Some(0.0));
+        // 21 characters at 15 a second, plus a tenth of a second after each of the 5 words
+        let second_start = segments[1].start_seconds.unwrap();
+        assert!((second_start - 1.9).abs() < 0.01, "{}", second_start);
+        assert!(
+            segments
+                .iter()
+                .all(|s| s.speaker_id.as_deref() == Some("speaker_0"))
+        );
+    }
+
+    #[tokio::test]
+    async fn generate_voice_reports_unknown_voice() {
+        let client = mock_client().await;
+        let result = client
+            .generate_voice(
+                "noSuchVoice".to_string(),
+                "Hello".to_string(),
+                None,
+                None,
+                None,
+                SpeechOptions::default(),
+            )
+            .await;
+        assert!(matches!(result, Err(ElevenLabsError::VoiceNotFound { .. })));
     }
 }
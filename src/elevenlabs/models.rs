@@ -0,0 +1,229 @@
+use log::{error, info};
+use serde::Deserialize;
+
+use crate::elevenlabs::{ElevenLabs, error::ElevenLabsError, retry::RequestKind};
+
+// In case no model is provided and all checks fall through, this is what we will use
+const ABSOLUTE_DEFAULT_MODEL: &str = "eleven_multilingual_v2";
+const DEFAULT_VOICE_CONVERSION_MODEL: &str = "eleven_multilingual_sts_v2";
+// Text to dialogue only works with v3, which also can't stitch requests, and the models list doesn't say either
+const DIALOGUE_MODEL: &str = "eleven_v3";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[allow(dead_code)]
+pub struct ModelLanguage {
+    pub language_id: String,
+    pub name: String,
+}
+
+/// A model as described by `v1/models`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[allow(dead_code)]
+pub struct Model {
+    pub model_id: String,
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub can_do_text_to_speech: bool,
+    #[serde(default)]
+    pub can_do_voice_conversion: bool,
+    #[serde(default)]
+    pub can_use_style: bool,
+    #[serde(default)]
+    pub can_use_speaker_boost: bool,
+    /// How many credits each character costs, relative to the standard models
+    pub token_cost_factor: Option<f32>,
+    pub max_characters_request_free_user: Option<usize>,
+    pub max_characters_request_subscribed_user: Option<usize>,
+    pub maximum_text_length_per_request: Option<usize>,
+    #[serde(default)]
+    pub languages: Vec<ModelLanguage>,
+}
+
+impl Model {
+    fn builtin(model_id: &str, name: &str, max_characters: usize) -> Self {
+        Model {
+            model_id: model_id.to_string(),
+            name: name.to_string(),
+            description: None,
+            can_do_text_to_speech: true,
+            can_do_voice_conversion: false,
+            can_use_style: true,
+            can_use_speaker_boost: true,
+            token_cost_factor: Some(1.0),
+            max_characters_request_free_user: None,
+            max_characters_request_subscribed_user: None,
+            maximum_text_length_per_request: Some(max_characters),
+            languages: Vec::new(),
+        }
+    }
+
+    /// The longest text a single request may contain, if the API told us
+    pub fn get_max_characters(&self) -> Option<usize> {
+        self.maximum_text_length_per_request
+            .or(self.max_characters_request_subscribed_user)
+    }
+
+    /// Whether requests with this model can continue on from earlier ones. v3 doesn't support it yet.
+    pub fn supports_request_stitching(&self) -> bool {
+        self.model_id != DIALOGUE_MODEL
+    }
+
+    /// Whether the model can be told to speak the given ISO-639-1 language. Models that didn't list their
+    /// languages get the benefit of the doubt.
+    pub fn supports_language(&self, language_code: &str) -> bool {
+        self.languages.is_empty()
+            || self
+                .languages
+                .iter()
+                .any(|l| l.language_id.eq_ignore_ascii_case(language_code))
+    }
+
+    /// A short summary for choice lists, e.g. "Eleven v3 (29 languages, 1x cost)"
+    pub fn describe(&self) -> String {
+        let mut details = Vec::new();
+        match self.languages.len() {
+            0 => {}
+            1 => details.push(self.languages[0].name.clone()),
+            n => details.push(format!("{} languages", n)),
+        }
+        if let Some(cost) = self.token_cost_factor {
+            details.push(format!("{}x cost", cost));
+        }
+        if details.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{} ({})", self.name, details.join(", "))
+        }
+    }
+}
+
+/// The models the bot can use, normally as reported by the API when the bot started
+#[derive(Debug, Clone)]
+pub struct ModelRegistry {
+    models: Vec<Model>,
+}
+
+impl ModelRegistry {
+    pub fn new(models: Vec<Model>) -> Self {
+        Self { models }
+    }
+
+    /// The models we knew about before they were fetched from the API, for when that fails
+    pub fn builtin() -> Self {
+        Self::new(vec![
+            Model::builtin("eleven_v3", "Eleven v3", 3000),
+            Model::builtin("eleven_multilingual_v2", "Eleven Multilingual v2", 10000),
+            Model::builtin("eleven_turbo_v2", "Eleven Turbo v2", 30000),
+        ])
+    }
+
+    pub fn get(&self, model_id: &str) -> Option<&Model> {
+        let model_id = model_id.trim().to_lowercase();
+        self.models.iter().find(|m| m.model_id == model_id)
+    }
+
+    pub fn speech_models(&self) -> impl Iterator<Item = &Model> {
+        self.models.iter().filter(|m| m.can_do_text_to_speech)
+    }
+
+    /// The model from the `DEFAULT_SPEECH_MODEL` env variable, if it's a valid speech model
+    pub fn get_default_model(&self) -> &Model {
+        if let Ok(env_value) = std::env::var("DEFAULT_SPEECH_MODEL") {
+            match self.get(&env_value) {
+                Some(model) if model.can_do_text_to_speech => return model,
+                _ => error!(
+                    "Invalid DEFAULT_SPEECH_MODEL env variable: {}. Valid values are {}. Falling back to {}",
+                    env_value,
+                    self.speech_models()
+                        .map(|m| m.model_id.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    ABSOLUTE_DEFAULT_MODEL
+                ),
+            }
+        }
+        self.get(ABSOLUTE_DEFAULT_MODEL)
+            .or_else(|| self.speech_models().next())
+            .expect("model registry has at least one speech model")
+    }
+
+    /// The model to re-voice speech with, preferring the multilingual one.
+    /// None if we don't know of any, in which case the API picks.
+    pub fn get_default_voice_conversion_model(&self) -> Option<&Model> {
+        self.get(DEFAULT_VOICE_CONVERSION_MODEL)
+            .or_else(|| self.models.iter().find(|m| m.can_do_voice_conversion))
+    }
+
+    /// The model used for dialogues. None if the account doesn't have it, in which case the API picks.
+    pub fn get_dialogue_model(&self) -> Option<&Model> {
+        self.get(DIALOGUE_MODEL)
+    }
+
+    /// Looks up the requested model (or the default), and checks it can speak `text`.
+    /// The error is meant to be shown to the user.
+    pub fn resolve_speech_model(
+        &self,
+        model_id: Option<&str>,
+        text: &str,
+    ) -> Result<&Model, String> {
+        let model = match model_id {
+            None => self.get_default_model(),
+            Some(id) => match self.get(id) {
+                Some(model) if model.can_do_text_to_speech => model,
+                Some(model) => {
+                    return Err(format!("{} can't be used for text to speech", model.name));
+                }
+                None => {
+                    return Err(format!(
+                        "Unknown model \"{}\". Valid models are: {}",
+                        id,
+                        self.speech_models()
+                            .map(|m| m.model_id.as_str())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ));
+                }
+            },
+        };
+
+        let length = text.chars().count();
+        match model.get_max_characters() {
+            Some(max) if length > max => Err(format!(
+                "That text is {} characters long, but {} can only speak {} at a time",
+                length, model.name, max
+            )),
+            _ => Ok(model),
+        }
+    }
+}
+
+impl ElevenLabs {
+    pub async fn get_models(&self) -> Result<Vec<Model>, ElevenLabsError> {
+        self.run_json_request_no_body(
+            self.get_base_request("v1/models", Vec::new()),
+            RequestKind::Read,
+        )
+        .await
+    }
+
+    /// Builds the model registry from the API, falling back to the built-in models if that fails
+    pub async fn load_model_registry(&self) -> ModelRegistry {
+        match self.get_models().await {
+            Ok(models) if models.iter().any(|m| m.can_do_text_to_speech) => {
+                info!(count = models.len(); "Loaded models from ElevenLabs");
+                ModelRegistry::new(models)
+            }
+            Ok(_) => {
+                error!("ElevenLabs returned no text to speech models, using the built-in ones");
+                ModelRegistry::builtin()
+            }
+            Err(e) => {
+                error!(error = e.to_string().as_str(); "Failed to load models, using the built-in ones");
+                ModelRegistry::builtin()
+            }
+        }
+    }
+}
@@ -0,0 +1,416 @@
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+
+use crate::elevenlabs::{
+    ElevenLabs,
+    error::ElevenLabsError,
+    requests::{
+        AddPronunciationRulesRequest, CreatePronunciationDictionaryRequest,
+        RemovePronunciationRulesRequest,
+    },
+    responses::{PronunciationDictionaryList, PronunciationDictionaryVersion},
+    retry::RequestKind,
+};
+
+// The largest page size v1/pronunciation-dictionaries allows
+const DICTIONARY_LIST_PAGE_SIZE: &str = "100";
+
+/// How to say a word or phrase
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PronunciationRule {
+    /// Speak something else instead, e.g. "GG" as "good game"
+    Alias {
+        string_to_replace: String,
+        alias: String,
+    },
+    /// Speak the exact sounds. Only some models support these.
+    Phoneme {
+        string_to_replace: String,
+        phoneme: String,
+        alphabet: PhonemeAlphabet,
+    },
+}
+
+impl PronunciationRule {
+    pub fn get_string_to_replace(&self) -> &str {
+        match self {
+            PronunciationRule::Alias {
+                string_to_replace, ..
+            }
+            | PronunciationRule::Phoneme {
+                string_to_replace, ..
+            } => string_to_replace,
+        }
+    }
+}
+
+#[derive(poise::ChoiceParameter, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PhonemeAlphabet {
+    #[name = "IPA"]
+    #[serde(rename = "ipa")]
+    Ipa,
+    #[name = "CMU Arpabet"]
+    #[serde(rename = "cmu-arpabet")]
+    CmuArpabet,
+}
+
+/// Points a speech request at a specific version of a dictionary
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct PronunciationDictionaryLocator {
+    pub pronunciation_dictionary_id: String,
+    pub version_id: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[allow(dead_code)]
+pub struct PronunciationDictionary {
+    pub id: String,
+    pub name: String,
+    pub latest_version_id: String,
+    pub latest_version_rules_num: Option<u32>,
+    pub description: Option<String>,
+}
+
+impl PronunciationDictionary {
+    pub fn get_locator(&self) -> PronunciationDictionaryLocator {
+        PronunciationDictionaryLocator {
+            pronunciation_dictionary_id: self.id.clone(),
+            version_id: self.latest_version_id.clone(),
+        }
+    }
+}
+
+impl ElevenLabs {
+    /// Lists every dictionary in the account, following the API's pagination until there are no more
+    pub async fn get_pronunciation_dictionaries(
+        &self,
+    ) -> Result<Vec<PronunciationDictionary>, ElevenLabsError> {
+        let mut dictionaries = Vec::new();
+        let mut cursor: Option<String> = None;
+
+        loop {
+            let mut query = vec![("page_size", DICTIONARY_LIST_PAGE_SIZE)];
+            if let Some(cursor) = cursor.as_deref() {
+                query.push(("cursor", cursor));
+            }
+            let page: PronunciationDictionaryList = self
+                .run_json_request_no_body(
+                    self.get_base_request("v1/pronunciation-dictionaries", query),
+                    RequestKind::Read,
+                )
+                .await?;
+            debug!(
+                count = page.pronunciation_dictionaries.len(), has_more = page.has_more;
+                "Fetched page of pronunciation dictionaries"
+            );
+            dictionaries.extend(page.pronunciation_dictionaries);
+
+            match page.next_cursor {
+                Some(next) if page.has_more => cursor = Some(next),
+                _ => break,
+            }
+        }
+
+        Ok(dictionaries)
+    }
+
+    pub async fn create_pronunciation_dictionary(
+        &self,
+        name: String,
+        rules: Vec<PronunciationRule>,
+        description: Option<String>,
+    ) -> Result<PronunciationDictionaryLocator, ElevenLabsError> {
+        info!(name = name.as_str(), rules = rules.len(); "Creating pronunciation dictionary");
+        let resp: PronunciationDictionaryVersion = self
+            .run_json_request_with_body(
+                self.post_base_request("v1/pronunciation-dictionaries/add-from-rules", Vec::new()),
+                Some(CreatePronunciationDictionaryRequest {
+                    name,
+                    rules,
+                    description,
+                }),
+                RequestKind::Write,
+            )
+            .await?;
+        Ok(resp.into_locator())
+    }
+
+    /// Adds rules to a dictionary, replacing any existing rules for the same strings.
+    /// Returns the locator of the new version.
+    pub async fn add_pronunciation_rules(
+        &self,
+        dictionary_id: &str,
+        rules: Vec<PronunciationRule>,
+    ) -> Result<PronunciationDictionaryLocator, ElevenLabsError> {
+        info!(dictionary_id = dictionary_id, rules = rules.len(); "Adding pronunciation rules");
+        let resp: PronunciationDictionaryVersion = self
+            .run_json_request_with_body(
+                self.post_base_request(
+                    &format!("v1/pronunciation-dictionaries/{}/add-rules", dictionary_id),
+                    Vec::new(),
+                ),
+                Some(AddPronunciationRulesRequest { rules }),
+                RequestKind::Write,
+            )
+            .await?;
+        Ok(resp.into_locator())
+    }
+
+    /// Removes the rules for the given strings from a dictionary. Returns the locator of the new version.
+    pub async fn remove_pronunciation_rules(
+        &self,
+        dictionary_id: &str,
+        rule_strings: Vec<String>,
+    ) -> Result<PronunciationDictionaryLocator, ElevenLabsError> {
+        info!(dictionary_id = dictionary_id; "Removing pronunciation rules for {:?}", rule_strings);
+        let resp: PronunciationDictionaryVersion = self
+            .run_json_request_with_body(
+                self.post_base_request(
+                    &format!(
+                        "v1/pronunciation-dictionaries/{}/remove-rules",
+                        dictionary_id
+                    ),
+                    Vec::new(),
+                ),
+                Some(RemovePronunciationRulesRequest { rule_strings }),
+                RequestKind::Write,
+            )
+            .await?;
+        Ok(resp.into_locator())
+    }
+
+    /// The rules in a version of a dictionary. The API only hands these out as a PLS (XML) file.
+    pub async fn get_pronunciation_rules(
+        &self,
+        locator: &PronunciationDictionaryLocator,
+    ) -> Result<Vec<PronunciationRule>, ElevenLabsError> {
+        let resp = self
+            .send_with_retry(
+                self.get_base_request(
+                    &format!(
+                        "v1/pronunciation-dictionaries/{}/{}/download",
+                        locator.pronunciation_dictionary_id, locator.version_id
+                    ),
+                    Vec::new(),
+                ),
+                RequestKind::Read,
+            )
+            .await?;
+        Ok(parse_pls(&resp.text().await?))
+    }
+}
+
+/// Just enough of the Pronunciation Lexicon Specification to read back what ElevenLabs exports
+fn parse_pls(pls: &str) -> Vec<PronunciationRule> {
+    let lexicon_alphabet = get_attribute(pls, "lexicon", "alphabet");
+
+    let mut rules = Vec::new();
+    let mut rest = pls;
+    while let Some(start) = rest.find("<lexeme") {
+        let Some(end) = rest[start..].find("</lexeme>") else {
+            break;
+        };
+        let lexeme = &rest[start..start + end];
+        rest = &rest[start + end..];
+
+        let Some(grapheme) = get_element_text(lexeme, "grapheme") else {
+            continue;
+        };
+        if let Some(alias) = get_element_text(lexeme, "alias") {
+            rules.push(PronunciationRule::Alias {
+                string_to_replace: grapheme,
+                alias,
+            });
+        } else if let Some(phoneme) = get_element_text(lexeme, "phoneme") {
+            let alphabet =
+                get_attribute(lexeme, "phoneme", "alphabet").or(lexicon_alphabet.clone());
+            rules.push(PronunciationRule::Phoneme {
+                string_to_replace: grapheme,
+                phoneme,
+                alphabet: match alphabet.as_deref() {
+                    Some("x-cmu-arpabet" | "cmu-arpabet") => PhonemeAlphabet::CmuArpabet,
+                    _ => PhonemeAlphabet::Ipa,
+                },
+            });
+        }
+    }
+    rules
+}
+
+fn get_element_text(xml: &str, tag: &str) -> Option<String> {
+    let open = xml.find(&format!("<{}", tag))?;
+    let content_start = open + xml[open..].find('>')? + 1;
+    let content_end = content_start + xml[content_start..].find(&format!("</{}>", tag))?;
+    Some(unescape_xml(xml[content_start..content_end].trim()))
+}
+
+fn get_attribute(xml: &str, tag: &str, attribute: &str) -> Option<String> {
+    let open = xml.find(&format!("<{}", tag))?;
+    let element = &xml[open..open + xml[open..].find('>')?];
+    let value_start = element.find(&format!("{}=\"", attribute))? + attribute.len() + 2;
+    let value_end = value_start + element[value_start..].find('"')?;
+    Some(unescape_xml(&element[value_start..value_end]))
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::start_mock_server;
+
+    fn lexicon(alphabet: &str, lexemes: &str) -> String {
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<lexicon version=\"1.0\" xmlns=\"http://www.w3.org/2005/01/pronunciation-lexicon\" alphabet=\"{}\" xml:lang=\"en-US\">\n{}</lexicon>\n",
+            alphabet, lexemes
+        )
+    }
+
+    fn phoneme(word: &str, phoneme: &str, alphabet: PhonemeAlphabet) -> PronunciationRule {
+        PronunciationRule::Phoneme {
+            string_to_replace: word.to_string(),
+            phoneme: phoneme.to_string(),
+            alphabet,
+        }
+    }
+
+    #[test]
+    fn reads_aliases_and_phonemes() {
+        let pls = lexicon(
+            "ipa",
+            "  <lexeme>\n    <grapheme>GG</grapheme>\n    <alias>good game</alias>\n  </lexeme>\n  <lexeme>\n    <grapheme>chainsaw</grapheme>\n    <phoneme>ˈtʃeɪnsɔː</phoneme>\n  </lexeme>\n",
+        );
+        assert_eq!(
+            parse_pls(&pls),
+            vec![
+                PronunciationRule::Alias {
+                    string_to_replace: "GG".to_string(),
+                    alias: "good game".to_string(),
+                },
+                phoneme("chainsaw", "ˈtʃeɪnsɔː", PhonemeAlphabet::Ipa),
+            ]
+        );
+    }
+
+    #[test]
+    fn phoneme_alphabet_overrides_the_lexicons() {
+        let lexemes = "<lexeme><grapheme>tomato</grapheme><phoneme>T AH0 M EY1 T OW2</phoneme></lexeme>\
+            <lexeme><grapheme>potato</grapheme><phoneme alphabet=\"ipa\">pəˈteɪtoʊ</phoneme></lexeme>\
+            <lexeme><grapheme>basil</grapheme><phoneme alphabet=\"cmu-arpabet\">B EY1 Z AH0 L</phoneme></lexeme>";
+        assert_eq!(
+            parse_pls(&lexicon("x-cmu-arpabet", lexemes)),
+            vec![
+                phoneme("tomato", "T AH0 M EY1 T OW2", PhonemeAlphabet::CmuArpabet),
+                phoneme("potato", "pəˈteɪtoʊ", PhonemeAlphabet::Ipa),
+                phoneme("basil", "B EY1 Z AH0 L", PhonemeAlphabet::CmuArpabet),
+            ]
+        );
+        assert_eq!(
+            parse_pls(&lexicon("ipa", lexemes))[0],
+            phoneme("tomato", "T AH0 M EY1 T OW2", PhonemeAlphabet::Ipa)
+        );
+    }
+
+    #[test]
+    fn unescapes_entities_once() {
+        let pls = lexicon(
+            "ipa",
+            "<lexeme><grapheme>&amp;lt;3 &amp; co</grapheme><alias>&quot;love&quot; &lt;3 &apos;n&apos; co</alias></lexeme>",
+        );
+        assert_eq!(
+            parse_pls(&pls),
+            vec![PronunciationRule::Alias {
+                string_to_replace: "&lt;3 & co".to_string(),
+                alias: "\"love\" <3 'n' co".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn skips_lexemes_without_a_grapheme() {
+        let pls = lexicon(
+            "ipa",
+            "<lexeme><alias>nothing to replace</alias></lexeme>\
+            <lexeme><grapheme>GG</grapheme></lexeme>\
+            <lexeme><grapheme>WP</grapheme><alias>well played</alias></lexeme>",
+        );
+        assert_eq!(
+            parse_pls(&pls),
+            vec![PronunciationRule::Alias {
+                string_to_replace: "WP".to_string(),
+                alias: "well played".to_string(),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_back_rules_from_the_mock() {
+        let client =
+            ElevenLabs::new_with_base_url("test-key".to_string(), start_mock_server().await);
+        let created = client
+            .create_pronunciation_dictionary(
+                "discord-guild-1".to_string(),
+                vec![PronunciationRule::Alias {
+                    string_to_replace: "R&D".to_string(),
+                    alias: "research and development".to_string(),
+                }],
+                None,
+            )
+            .await
+            .unwrap();
+        let updated = client
+            .add_pronunciation_rules(
+                &created.pronunciation_dictionary_id,
+                vec![phoneme(
+                    "tomato",
+                    "T AH0 M EY1 T OW2",
+                    PhonemeAlphabet::CmuArpabet,
+                )],
+            )
+            .await
+            .unwrap();
+        assert_ne!(updated.version_id, created.version_id);
+
+        assert_eq!(
+            client.get_pronunciation_rules(&updated).await.unwrap(),
+            vec![
+                PronunciationRule::Alias {
+                    string_to_replace: "R&D".to_string(),
+                    alias: "research and development".to_string(),
+                },
+                phoneme("tomato", "T AH0 M EY1 T OW2", PhonemeAlphabet::CmuArpabet),
+            ]
+        );
+        // Earlier versions stay as they were
+        assert_eq!(
+            client
+                .get_pronunciation_rules(&created)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        let removed = client
+            .remove_pronunciation_rules(&updated.pronunciation_dictionary_id, vec!["R&D".into()])
+            .await
+            .unwrap();
+        assert_eq!(
+            client.get_pronunciation_rules(&removed).await.unwrap(),
+            vec![phoneme(
+                "tomato",
+                "T AH0 M EY1 T OW2",
+                PhonemeAlphabet::CmuArpabet
+            )]
+        );
+    }
+}
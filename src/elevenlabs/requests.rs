@@ -1,3 +1,4 @@
+use crate::elevenlabs::pronunciation::{PronunciationDictionaryLocator, PronunciationRule};
 use crate::elevenlabs::types;
 use serde::Serialize;
 
@@ -8,4 +9,82 @@ pub struct CreateSpeechRequest {
     pub text: String,
     pub model_id: Option<String>,
     pub voice_settings: Option<types::VoiceSettings>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pronunciation_dictionary_locators: Vec<PronunciationDictionaryLocator>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous_text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_text: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub previous_request_ids: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub next_request_ids: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language_code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub apply_text_normalization: Option<types::TextNormalization>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub apply_language_text_normalization: Option<bool>,
+}
+
+/// Messages we send over the `stream-input` WebSocket
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct StreamInputMessage {
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub voice_settings: Option<types::VoiceSettings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub try_trigger_generation: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flush: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct DownloadHistoryRequest {
+    pub history_item_ids: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct CreateSoundEffectRequest {
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_seconds: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_influence: Option<f32>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct CreateDialogueRequest {
+    pub inputs: Vec<types::DialogueLine>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model_id: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pronunciation_dictionary_locators: Vec<PronunciationDictionaryLocator>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct CreatePronunciationDictionaryRequest {
+    pub name: String,
+    pub rules: Vec<PronunciationRule>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct AddPronunciationRulesRequest {
+    pub rules: Vec<PronunciationRule>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct RemovePronunciationRulesRequest {
+    pub rule_strings: Vec<String>,
 }
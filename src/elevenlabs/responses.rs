@@ -1,4 +1,5 @@
-use crate::elevenlabs::types;
+use crate::elevenlabs::{error::ElevenLabsError, pronunciation, types};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
 use serde::Deserialize;
 
 #[derive(Debug, Deserialize)]
@@ -6,6 +7,62 @@ use serde::Deserialize;
 #[allow(dead_code)]
 pub struct VoiceList {
     pub voices: Vec<types::Voice>,
+    #[serde(default)]
+    pub has_more: bool,
+    pub total_count: Option<i64>,
+    pub next_page_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[allow(dead_code)]
+pub struct HistoryPage {
+    pub history: Vec<types::HistoryItem>,
+    pub last_history_item_id: Option<String>,
+    #[serde(default)]
+    pub has_more: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[allow(dead_code)]
+pub struct AddVoiceResponse {
+    pub voice_id: String,
+    #[serde(default)]
+    pub requires_verification: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[allow(dead_code)]
+pub struct StatusResponse {
+    pub status: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct PronunciationDictionaryList {
+    pub pronunciation_dictionaries: Vec<pronunciation::PronunciationDictionary>,
+    #[serde(default)]
+    pub has_more: bool,
+    pub next_cursor: Option<String>,
+}
+
+/// Every change to a dictionary makes a new version
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct PronunciationDictionaryVersion {
+    pub id: String,
+    pub version_id: String,
+}
+
+impl PronunciationDictionaryVersion {
+    pub fn into_locator(self) -> pronunciation::PronunciationDictionaryLocator {
+        pronunciation::PronunciationDictionaryLocator {
+            pronunciation_dictionary_id: self.id,
+            version_id: self.version_id,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -24,3 +81,41 @@ pub struct Subscription {
     pub character_limit: i64,
     pub next_character_count_reset_unix: i64,
 }
+
+/// Messages we receive over the `stream-input` WebSocket.
+/// Either an audio chunk, or an error (in which case only `message`/`error` are set).
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[allow(dead_code)]
+pub struct StreamOutputMessage {
+    pub audio: Option<String>,
+    pub is_final: Option<bool>,
+    pub alignment: Option<StreamAlignment>,
+    pub normalized_alignment: Option<StreamAlignment>,
+    pub message: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Which characters the accompanying audio chunk covers, with times relative to the start of the chunk
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[allow(dead_code)]
+pub struct StreamAlignment {
+    pub chars: Vec<String>,
+    pub char_start_times_ms: Vec<i64>,
+    pub chars_durations_ms: Vec<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct SpeechWithTimestampsResponse {
+    pub audio_base64: String,
+    pub alignment: Option<types::Alignment>,
+    pub normalized_alignment: Option<types::Alignment>,
+}
+
+impl SpeechWithTimestampsResponse {
+    pub fn decode_audio(&self) -> Result<Vec<u8>, ElevenLabsError> {
+        Ok(BASE64.decode(&self.audio_base64)?)
+    }
+}
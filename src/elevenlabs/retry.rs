@@ -0,0 +1,245 @@
+use std::time::Duration;
+
+use log::error;
+use rand::Rng;
+
+use crate::elevenlabs::error::ElevenLabsError;
+
+/// The kinds of requests we send, each of which can have its own retry policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestKind {
+    /// Cheap metadata lookups (usage, voice lists, ...)
+    Read,
+    /// Anything that generates audio: slow, and someone in Discord is waiting on it. Like writes, these are only
+    /// retried when they can't have gone through, since a timed out request may still have been charged for.
+    Generation,
+    /// Deletes and edits. A timeout or server error doesn't mean these didn't go through, so only rate limits and
+    /// failed connections are retried.
+    Write,
+}
+
+impl RequestKind {
+    pub fn get_env_name(&self) -> String {
+        match self {
+            RequestKind::Read => "READ",
+            RequestKind::Generation => "GENERATION",
+            RequestKind::Write => "WRITE",
+        }
+        .to_string()
+    }
+
+    fn default_retry_policy(&self) -> RetryPolicy {
+        match self {
+            RequestKind::Read | RequestKind::Write => RetryPolicy {
+                max_retries: 3,
+                base_delay: Duration::from_millis(250),
+                max_delay: Duration::from_secs(5),
+            },
+            RequestKind::Generation => RetryPolicy {
+                max_retries: 2,
+                base_delay: Duration::from_millis(500),
+                max_delay: Duration::from_secs(10),
+            },
+        }
+    }
+}
+
+/// Exponential backoff with jitter for rate limited (429) and transient (5xx) failures
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    /// Upper bound for a single wait. If the API asks us (via `Retry-After`) to wait longer than this, we give up instead.
+    pub max_delay: Duration,
+}
+
+fn get_env_u64(var: &impl Fn(&str) -> Option<String>, env_name: &str) -> Option<u64> {
+    if let Some(env_value) = var(env_name) {
+        if let Ok(value) = env_value.parse::<u64>() {
+            return Some(value);
+        }
+        error!(
+            "Failed to parse env variable {}={} as u64",
+            env_name, env_value
+        );
+    }
+    None
+}
+
+impl RetryPolicy {
+    /// The policy for the given kind of request, with overrides from
+    /// `ELEVENLABS_RETRY_{READ,GENERATION,WRITE}_{MAX_RETRIES,BASE_DELAY_MS,MAX_DELAY_MS}`
+    pub fn from_env(kind: RequestKind) -> Self {
+        Self::from_vars(kind, |name| std::env::var(name).ok())
+    }
+
+    /// Like `from_env`, but looks the variables up with `var`
+    fn from_vars(kind: RequestKind, var: impl Fn(&str) -> Option<String>) -> Self {
+        let default = kind.default_retry_policy();
+        let prefix = format!("ELEVENLABS_RETRY_{}", kind.get_env_name());
+        RetryPolicy {
+            max_retries: get_env_u64(&var, &format!("{}_MAX_RETRIES", prefix))
+                .map(|v| v as u32)
+                .unwrap_or(default.max_retries),
+            base_delay: get_env_u64(&var, &format!("{}_BASE_DELAY_MS", prefix))
+                .map(Duration::from_millis)
+                .unwrap_or(default.base_delay),
+            max_delay: get_env_u64(&var, &format!("{}_MAX_DELAY_MS", prefix))
+                .map(Duration::from_millis)
+                .unwrap_or(default.max_delay),
+        }
+    }
+
+    /// How long to wait before the given retry (starting at 1), or None if we shouldn't retry at all
+    pub fn get_delay(&self, retry: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        if retry > self.max_retries {
+            return None;
+        }
+        if let Some(retry_after) = retry_after {
+            return (retry_after <= self.max_delay).then_some(retry_after);
+        }
+
+        // "Equal jitter": wait at least half of the exponential delay, plus a random amount up to the other half
+        let exponential = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry - 1))
+            .min(self.max_delay);
+        let half = exponential / 2;
+        let jitter = rand::rng().random_range(0..=half.as_millis() as u64);
+        Some(half + Duration::from_millis(jitter))
+    }
+}
+
+/// Whether an error is worth trying again: rate limits, server errors and connection problems.
+/// Writes and generations are only retried when they can't have gone through.
+pub fn is_retryable(error: &ElevenLabsError, kind: RequestKind) -> bool {
+    let idempotent = kind == RequestKind::Read;
+    match error {
+        ElevenLabsError::RateLimited { .. } => true,
+        ElevenLabsError::Api { status, .. } => idempotent && status.is_server_error(),
+        ElevenLabsError::Transport(e) => e.is_connect() || (idempotent && e.is_timeout()),
+        _ => false,
+    }
+}
+
+/// Parses a `Retry-After` header given in seconds. (The HTTP-date form isn't used by ElevenLabs.)
+pub fn parse_retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    headers
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: RetryPolicy = RetryPolicy {
+        max_retries: 5,
+        base_delay: Duration::from_millis(100),
+        max_delay: Duration::from_millis(1000),
+    };
+
+    #[test]
+    fn delay_has_equal_jitter() {
+        for (retry, exponential) in [(1, 100), (2, 200), (3, 400), (4, 800)] {
+            for _ in 0..100 {
+                let delay = POLICY.get_delay(retry, None).unwrap().as_millis();
+                assert!(
+                    (exponential / 2..=exponential).contains(&delay),
+                    "retry {} waited {}ms",
+                    retry,
+                    delay
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn delay_is_capped() {
+        let policy = RetryPolicy {
+            max_retries: 40,
+            ..POLICY
+        };
+        for retry in [5, 20, 40] {
+            let delay = policy.get_delay(retry, None).unwrap();
+            assert!(delay >= POLICY.max_delay / 2 && delay <= POLICY.max_delay);
+        }
+    }
+
+    #[test]
+    fn gives_up_after_max_retries() {
+        assert!(POLICY.get_delay(5, None).is_some());
+        assert!(POLICY.get_delay(6, None).is_none());
+        assert!(POLICY.get_delay(6, Some(Duration::ZERO)).is_none());
+    }
+
+    #[test]
+    fn honours_retry_after() {
+        assert_eq!(
+            POLICY.get_delay(1, Some(Duration::from_secs(1))),
+            Some(Duration::from_secs(1))
+        );
+        // Longer than we're willing to wait
+        assert_eq!(POLICY.get_delay(1, Some(Duration::from_secs(2))), None);
+    }
+
+    #[test]
+    fn parses_retry_after() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        assert_eq!(parse_retry_after(&headers), None);
+        headers.insert(reqwest::header::RETRY_AFTER, " 3 ".parse().unwrap());
+        assert_eq!(parse_retry_after(&headers), Some(Duration::from_secs(3)));
+        headers.insert(
+            reqwest::header::RETRY_AFTER,
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(parse_retry_after(&headers), None);
+    }
+
+    #[test]
+    fn env_overrides_defaults() {
+        let policy = RetryPolicy::from_vars(RequestKind::Generation, |name| match name {
+            "ELEVENLABS_RETRY_GENERATION_MAX_RETRIES" => Some("7".to_string()),
+            "ELEVENLABS_RETRY_GENERATION_MAX_DELAY_MS" => Some("2500".to_string()),
+            // Unparsable values fall back to the default
+            "ELEVENLABS_RETRY_GENERATION_BASE_DELAY_MS" => Some("soon".to_string()),
+            _ => None,
+        });
+        assert_eq!(policy.max_retries, 7);
+        assert_eq!(policy.base_delay, Duration::from_millis(500));
+        assert_eq!(policy.max_delay, Duration::from_millis(2500));
+
+        let policy = RetryPolicy::from_vars(RequestKind::Read, |_| None);
+        assert_eq!(policy.max_retries, 3);
+        assert_eq!(policy.base_delay, Duration::from_millis(250));
+        assert_eq!(policy.max_delay, Duration::from_secs(5));
+    }
+
+    #[test]
+    fn writes_and_generations_only_retry_when_not_carried_out() {
+        let rate_limited = ElevenLabsError::RateLimited {
+            status: reqwest::StatusCode::TOO_MANY_REQUESTS,
+            message: String::new(),
+        };
+        let server_error = ElevenLabsError::Api {
+            status: reqwest::StatusCode::BAD_GATEWAY,
+            detail_status: None,
+            message: String::new(),
+        };
+        for kind in [
+            RequestKind::Read,
+            RequestKind::Generation,
+            RequestKind::Write,
+        ] {
+            assert!(is_retryable(&rate_limited, kind));
+        }
+        assert!(is_retryable(&server_error, RequestKind::Read));
+        assert!(!is_retryable(&server_error, RequestKind::Generation));
+        assert!(!is_retryable(&server_error, RequestKind::Write));
+    }
+}
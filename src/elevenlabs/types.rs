@@ -1,6 +1,10 @@
+use std::collections::HashMap;
+
 use log::{error, kv::ToValue};
 use serde::{Deserialize, Serialize};
 
+use crate::elevenlabs::pronunciation::PronunciationDictionaryLocator;
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "snake_case")]
 #[allow(dead_code)]
@@ -8,16 +12,139 @@ pub struct Voice {
     pub voice_id: String,
     pub name: String,
     pub description: Option<String>,
+    pub category: Option<String>,
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    pub preview_url: Option<String>,
+}
+
+/// Whose voices to list
+#[derive(poise::ChoiceParameter, Debug, Clone, Copy)]
+pub enum VoiceType {
+    Personal,
+    Community,
+    Default,
+    Workspace,
+    NonDefault,
+}
+
+impl VoiceType {
+    pub fn get_id(&self) -> String {
+        match self {
+            VoiceType::Personal => "personal",
+            VoiceType::Community => "community",
+            VoiceType::Default => "default",
+            VoiceType::Workspace => "workspace",
+            VoiceType::NonDefault => "non-default",
+        }
+        .to_string()
+    }
+}
+
+/// How a voice was created
+#[derive(poise::ChoiceParameter, Debug, Clone, Copy)]
+pub enum VoiceCategory {
+    Premade,
+    Cloned,
+    Generated,
+    Professional,
+}
+
+impl VoiceCategory {
+    pub fn get_id(&self) -> String {
+        match self {
+            VoiceCategory::Premade => "premade",
+            VoiceCategory::Cloned => "cloned",
+            VoiceCategory::Generated => "generated",
+            VoiceCategory::Professional => "professional",
+        }
+        .to_string()
+    }
+}
+
+/// What to split character usage by
+#[derive(poise::ChoiceParameter, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsageBreakdown {
+    #[name = "Total"]
+    None,
+    Voice,
+    Model,
+}
+
+impl UsageBreakdown {
+    pub fn get_id(&self) -> String {
+        match self {
+            UsageBreakdown::None => "none",
+            UsageBreakdown::Voice => "voice",
+            UsageBreakdown::Model => "model",
+        }
+        .to_string()
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// Characters used in each period, split by the requested breakdown
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct CharacterStats {
+    /// Start of each period, as unix milliseconds
+    pub time: Vec<i64>,
+    /// Characters used in each period by voice or model ID, or under "All" without a breakdown
+    pub usage: HashMap<String, Vec<f64>>,
+}
+
+impl CharacterStats {
+    /// Each period's start date and the characters used in it across the whole breakdown
+    pub fn get_daily_totals(&self) -> Vec<(chrono::NaiveDate, u64)> {
+        self.time
+            .iter()
+            .enumerate()
+            .filter_map(|(i, time)| {
+                let date = chrono::DateTime::from_timestamp_millis(*time)?.date_naive();
+                let total = self
+                    .usage
+                    .values()
+                    .filter_map(|series| series.get(i))
+                    .sum::<f64>();
+                Some((date, total.round() as u64))
+            })
+            .collect()
+    }
+
+    /// The characters used by each voice or model over the whole time span, most used first
+    pub fn get_totals_by_key(&self) -> Vec<(String, u64)> {
+        let mut totals = self
+            .usage
+            .iter()
+            .map(|(key, series)| (key.clone(), series.iter().sum::<f64>().round() as u64))
+            .collect::<Vec<_>>();
+        totals.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        totals
+    }
+}
+
+/// Narrows down which voices `get_voice_list` returns. All filters are optional.
+#[derive(Debug, Default, Clone)]
+pub struct VoiceListFilter {
+    /// Matched against the voice's name, description, labels and category
+    pub search: Option<String>,
+    pub voice_type: Option<VoiceType>,
+    pub category: Option<VoiceCategory>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 #[allow(dead_code)]
 pub struct VoiceSettings {
+    // Unset fields are left out entirely, so the API falls back to the voice's stored settings for them
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub stability: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub similarity_boost: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub style: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub use_speaker_boost: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub speed: Option<f32>,
 }
 
@@ -40,7 +167,7 @@ impl SpeechSpeed {
 }
 
 impl ToValue for SpeechSpeed {
-    fn to_value(&self) -> log::kv::Value {
+    fn to_value(&self) -> log::kv::Value<'_> {
         match self {
             SpeechSpeed::Slow => "Slow".to_value(),
             SpeechSpeed::Normal => "Normal".to_value(),
@@ -49,59 +176,21 @@ impl ToValue for SpeechSpeed {
     }
 }
 
-// In case no model is provided and all checks fall through, this is what we will use
-const ABSOLUTE_DEFAULT_MODEL: SpeechModel = SpeechModel::ElevenMultilingualV2;
-
-#[derive(poise::ChoiceParameter, Debug, Clone, Copy)]
-pub enum SpeechModel {
-    ElevenV3,
-    ElevenMultilingualV2,
-    ElevenTurboV2,
-}
-
-impl SpeechModel {
-    pub fn get_id(&self) -> String {
-        match self {
-            SpeechModel::ElevenV3 => "eleven_v3",
-            SpeechModel::ElevenMultilingualV2 => "eleven_multilingual_v2",
-            SpeechModel::ElevenTurboV2 => "eleven_turbo_v2",
-        }
-        .to_string()
-    }
-}
-
-pub fn parse_speech_model(model: &str) -> Option<SpeechModel> {
-    match model.to_lowercase().as_str() {
-        "eleven_v3" => Some(SpeechModel::ElevenV3),
-        "eleven_multilingual_v2" => Some(SpeechModel::ElevenMultilingualV2),
-        "eleven_turbo_v2" => Some(SpeechModel::ElevenTurboV2),
-        _ => None,
-    }
-}
-
-pub fn get_default_speech_model() -> Option<SpeechModel> {
-    if let Ok(env_value) = std::env::var("DEFAULT_SPEECH_MODEL") {
-        if let Some(model) = parse_speech_model(&env_value) {
-            return Some(model);
-        }
-        error!(
-            "Invalid DEFAULT_SPEECH_MODEL env variable: {}. Valid values are eleven_v3, eleven_multilingual_v2, eleven_turbo_v2. Falling back to {}",
-            env_value,
-            ABSOLUTE_DEFAULT_MODEL.get_id()
-        );
-    }
-    return Some(ABSOLUTE_DEFAULT_MODEL);
-}
-
-#[derive(poise::ChoiceParameter, Debug)]
+#[derive(poise::ChoiceParameter, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum KnownVoice {
     Scotty,
     June,
     UnrealTournament,
 }
 
+pub const ALL_KNOWN_VOICES: &[KnownVoice] = &[
+    KnownVoice::Scotty,
+    KnownVoice::June,
+    KnownVoice::UnrealTournament,
+];
+
 impl ToValue for KnownVoice {
-    fn to_value(&self) -> log::kv::Value {
+    fn to_value(&self) -> log::kv::Value<'_> {
         match self {
             KnownVoice::Scotty => "Scotty".to_value(),
             KnownVoice::June => "June".to_value(),
@@ -179,7 +268,7 @@ impl KnownVoice {
             speed.get_env_name(),
             self.get_env_name(),
         )) {
-            if override_speed < 0.7 || override_speed > 1.2 {
+            if !(0.7..=1.2).contains(&override_speed) {
                 error!(
                     "Invalid speed override for {}: {}",
                     self.get_env_name(),
@@ -193,7 +282,7 @@ impl KnownVoice {
         if let Some(override_speed) =
             get_env_f32(&format!("VOICE_SPEED_OVERRIDE_{}_ALL", self.get_env_name(),))
         {
-            if override_speed < 0.7 || override_speed > 1.2 {
+            if !(0.7..=1.2).contains(&override_speed) {
                 error!(
                     "Invalid speed override for {}: {}",
                     self.get_env_name(),
@@ -235,3 +324,246 @@ impl KnownVoice {
         }
     }
 }
+
+/// A past generation, as kept in the account's history
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[allow(dead_code)]
+pub struct HistoryItem {
+    pub history_item_id: String,
+    pub request_id: Option<String>,
+    pub voice_id: Option<String>,
+    pub voice_name: Option<String>,
+    pub model_id: Option<String>,
+    pub text: Option<String>,
+    pub date_unix: i64,
+    pub character_count_change_from: Option<i64>,
+    pub character_count_change_to: Option<i64>,
+    pub content_type: Option<String>,
+    pub state: Option<String>,
+}
+
+impl HistoryItem {
+    pub fn get_date(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        chrono::DateTime::from_timestamp(self.date_unix, 0)
+    }
+}
+
+/// When each character of the spoken text starts and ends, in seconds from the start of the audio
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Alignment {
+    pub characters: Vec<String>,
+    pub character_start_times_seconds: Vec<f64>,
+    pub character_end_times_seconds: Vec<f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct WordTiming {
+    pub word: String,
+    pub start_seconds: f64,
+    pub end_seconds: f64,
+}
+
+impl Alignment {
+    /// Groups the characters into whitespace separated words
+    pub fn words(&self) -> Vec<WordTiming> {
+        let mut words = Vec::new();
+        let mut current: Option<WordTiming> = None;
+
+        let timed_chars = self.characters.iter().zip(
+            self.character_start_times_seconds
+                .iter()
+                .zip(self.character_end_times_seconds.iter()),
+        );
+        for (character, (start, end)) in timed_chars {
+            if character.trim().is_empty() {
+                words.extend(current.take());
+                continue;
+            }
+            match current.as_mut() {
+                Some(word) => {
+                    word.word.push_str(character);
+                    word.end_seconds = *end;
+                }
+                None => {
+                    current = Some(WordTiming {
+                        word: character.clone(),
+                        start_seconds: *start,
+                        end_seconds: *end,
+                    })
+                }
+            }
+        }
+        words.extend(current);
+
+        words
+    }
+
+    /// Renders the words as SubRip captions, a few words per caption
+    pub fn to_srt(&self, words_per_caption: usize) -> String {
+        fn timestamp(seconds: f64) -> String {
+            let millis = (seconds * 1000.0).round() as i64;
+            format!(
+                "{:02}:{:02}:{:02},{:03}",
+                millis / 3_600_000,
+                (millis / 60_000) % 60,
+                (millis / 1000) % 60,
+                millis % 1000
+            )
+        }
+
+        self.words()
+            .chunks(words_per_caption.max(1))
+            .enumerate()
+            .map(|(i, chunk)| {
+                format!(
+                    "{}\n{} --> {}\n{}\n",
+                    i + 1,
+                    timestamp(chunk[0].start_seconds),
+                    timestamp(chunk[chunk.len() - 1].end_seconds),
+                    chunk
+                        .iter()
+                        .map(|w| w.word.as_str())
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[derive(Debug)]
+#[allow(dead_code)]
+pub struct SpeechWithTimestamps {
+    pub request_id: Option<String>,
+    pub audio: Vec<u8>,
+    pub alignment: Option<Alignment>,
+    pub normalized_alignment: Option<Alignment>,
+}
+
+/// Extra options for speech requests. Everything is left to the API by default.
+#[derive(Debug, Default, Clone)]
+pub struct SpeechOptions {
+    /// Applied in order, the API allows up to 3
+    pub pronunciation_dictionary_locators: Vec<PronunciationDictionaryLocator>,
+    /// What was said just before, to carry its delivery over. Ignored if `previous_request_ids` is set.
+    pub previous_text: Option<String>,
+    /// What will be said right after. Ignored if `next_request_ids` is set.
+    pub next_text: Option<String>,
+    /// Requests this one continues on from, oldest first. The API allows up to 3.
+    pub previous_request_ids: Vec<String>,
+    /// Requests that will follow this one. The API allows up to 3.
+    pub next_request_ids: Vec<String>,
+    /// Makes generation repeatable, as far as the API can manage
+    pub seed: Option<u32>,
+    /// ISO-639-1 code of the language to speak, for models that support it
+    pub language_code: Option<String>,
+    /// Whether numbers, dates and the like are spelled out before speaking
+    pub apply_text_normalization: Option<TextNormalization>,
+    /// Language-specific normalization, which is slower. Only some languages (e.g. Japanese) have it.
+    pub apply_language_text_normalization: Option<bool>,
+}
+
+/// Whether to spell out numbers, dates and the like before speaking them
+#[derive(poise::ChoiceParameter, Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextNormalization {
+    /// Left to the model
+    Auto,
+    On,
+    Off,
+}
+
+/// Options for `transcribe`. Everything is off (or left to the API) by default.
+#[derive(Debug, Default, Clone)]
+pub struct TranscriptionOptions {
+    pub model_id: Option<String>,
+    /// ISO-639 code of the spoken language, detected automatically if not set
+    pub language_code: Option<String>,
+    /// Tell apart who's speaking when
+    pub diarize: bool,
+    /// Time each word
+    pub timestamps: bool,
+}
+
+/// Text recognised from speech
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[allow(dead_code)]
+pub struct Transcription {
+    pub language_code: Option<String>,
+    pub language_probability: Option<f64>,
+    pub text: String,
+    #[serde(default)]
+    pub words: Vec<TranscribedWord>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[allow(dead_code)]
+pub struct TranscribedWord {
+    pub text: String,
+    pub start: Option<f64>,
+    pub end: Option<f64>,
+    /// "word", "spacing" or "audio_event" (e.g. "(laughter)")
+    #[serde(rename = "type")]
+    pub word_type: Option<String>,
+    pub speaker_id: Option<String>,
+}
+
+/// A sentence, or a run of speech by the same speaker if there's no punctuation
+#[derive(Debug, Clone)]
+pub struct TranscriptSegment {
+    pub speaker_id: Option<String>,
+    pub start_seconds: Option<f64>,
+    pub text: String,
+}
+
+impl Transcription {
+    /// Splits the transcript into sentences, and wherever the speaker changes.
+    /// Without word details, it's all one segment.
+    pub fn segments(&self) -> Vec<TranscriptSegment> {
+        if self.words.is_empty() {
+            return vec![TranscriptSegment {
+                speaker_id: None,
+                start_seconds: None,
+                text: self.text.clone(),
+            }];
+        }
+
+        let mut segments: Vec<TranscriptSegment> = Vec::new();
+        for word in &self.words {
+            match segments.last_mut() {
+                // Spacing has no speaker of its own, it belongs to whoever is talking
+                Some(segment)
+                    if word.word_type.as_deref() == Some("spacing")
+                        || (segment.speaker_id == word.speaker_id
+                            && !segment.text.trim_end().ends_with(['.', '?', '!'])) =>
+                {
+                    segment.text.push_str(&word.text);
+                }
+                _ => segments.push(TranscriptSegment {
+                    speaker_id: word.speaker_id.clone(),
+                    start_seconds: word.start,
+                    text: word.text.clone(),
+                }),
+            }
+        }
+        for segment in segments.iter_mut() {
+            segment.text = segment.text.trim().to_string();
+        }
+        segments.retain(|s| !s.text.is_empty());
+
+        segments
+    }
+}
+
+/// One speaker's turn in a dialogue
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct DialogueLine {
+    pub voice_id: String,
+    pub text: String,
+}
@@ -0,0 +1,258 @@
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use futures::{SinkExt, StreamExt, stream::SplitSink};
+use log::{debug, error, info};
+use reqwest::Url;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{
+    MaybeTlsStream, WebSocketStream,
+    tungstenite::{Message, client::IntoClientRequest, http::HeaderValue},
+};
+
+use crate::elevenlabs::{
+    ElevenLabs,
+    error::ElevenLabsError,
+    media::{OutputFormat, get_default_output_format},
+    requests::StreamInputMessage,
+    responses::{StreamAlignment, StreamOutputMessage},
+    types::VoiceSettings,
+};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// A decoded piece of audio from an input stream
+#[derive(Debug)]
+#[allow(dead_code)]
+pub struct AudioChunk {
+    pub audio: Vec<u8>,
+    pub alignment: Option<StreamAlignment>,
+    pub normalized_alignment: Option<StreamAlignment>,
+    pub is_final: bool,
+}
+
+/// The sending half of an input stream: text goes in here, audio comes out of the stream returned alongside it
+pub struct TextInputSender {
+    sink: SplitSink<Socket, Message>,
+}
+
+#[allow(dead_code)]
+impl TextInputSender {
+    async fn send_message(&mut self, message: StreamInputMessage) -> Result<(), ElevenLabsError> {
+        let json = serde_json::to_string(&message)?;
+        self.sink.send(Message::text(json)).await?;
+        Ok(())
+    }
+
+    /// Queues more text. ElevenLabs buffers it until it has enough to generate natural sounding speech,
+    /// so this can be called with fragments as small as single words (each ending in a space).
+    pub async fn send_text(&mut self, text: &str) -> Result<(), ElevenLabsError> {
+        self.send_message(StreamInputMessage {
+            text: text.to_string(),
+            voice_settings: None,
+            try_trigger_generation: Some(true),
+            flush: None,
+        })
+        .await
+    }
+
+    /// Forces generation of everything sent so far, e.g. at the end of a sentence when more may follow later
+    pub async fn flush(&mut self) -> Result<(), ElevenLabsError> {
+        self.send_message(StreamInputMessage {
+            text: " ".to_string(),
+            voice_settings: None,
+            try_trigger_generation: None,
+            flush: Some(true),
+        })
+        .await
+    }
+
+    /// Tells ElevenLabs there's no more text coming. The audio stream ends once the remaining audio has been sent.
+    pub async fn finish(mut self) -> Result<(), ElevenLabsError> {
+        // An empty string is the protocol's "end of input" marker
+        self.send_message(StreamInputMessage {
+            text: String::new(),
+            voice_settings: None,
+            try_trigger_generation: None,
+            flush: None,
+        })
+        .await
+    }
+}
+
+fn decode_message(text: &str) -> Option<Result<AudioChunk, ElevenLabsError>> {
+    let message = match serde_json::from_str::<StreamOutputMessage>(text) {
+        Ok(m) => m,
+        Err(e) => {
+            error!(error = e.to_string().as_str(), text = text; "Failed to parse stream message");
+            return Some(Err(e.into()));
+        }
+    };
+
+    if let Some(err) = message.error {
+        error!(error = err.as_str(), message = message.message.as_deref().unwrap_or(""); "Input stream failed");
+        return Some(Err(ElevenLabsError::Stream {
+            message: message.message.unwrap_or(err),
+        }));
+    }
+
+    let is_final = message.is_final.unwrap_or(false);
+    let audio = match message.audio {
+        Some(audio) => match BASE64.decode(audio) {
+            Ok(a) => a,
+            Err(e) => return Some(Err(e.into())),
+        },
+        // The final message usually carries no audio, and there's nothing else to report for it
+        None if !is_final => return None,
+        None => Vec::new(),
+    };
+
+    Some(Ok(AudioChunk {
+        audio,
+        alignment: message.alignment,
+        normalized_alignment: message.normalized_alignment,
+        is_final,
+    }))
+}
+
+impl ElevenLabs {
+    fn get_stream_input_url(
+        &self,
+        voice_id: &str,
+        model_id: Option<&str>,
+        media_format: Option<&OutputFormat>,
+    ) -> Result<Url, ElevenLabsError> {
+        let final_format = match media_format {
+            Some(format) => format,
+            None => get_default_output_format(),
+        };
+        // Same host as the HTTP API, just with the WebSocket scheme
+        let ws_base = match self.api_base.split_once("://") {
+            Some(("http", rest)) => format!("ws://{}", rest),
+            Some((_, rest)) => format!("wss://{}", rest),
+            None => format!("wss://{}", self.api_base),
+        };
+        let mut url = Url::parse(&format!(
+            "{}v1/text-to-speech/{}/stream-input",
+            ws_base, voice_id
+        ))
+        .map_err(|e| ElevenLabsError::Stream {
+            message: format!("Invalid input stream URL: {}", e),
+        })?;
+        url.query_pairs_mut()
+            .append_pair("output_format", &final_format.to_string());
+        if let Some(model_id) = model_id {
+            url.query_pairs_mut().append_pair("model_id", model_id);
+        }
+        Ok(url)
+    }
+
+    /// Opens a `stream-input` WebSocket, which generates speech from text that's sent in pieces over time.
+    ///
+    /// Returns the sender for the text, and a stream of audio chunks that ends when all audio has been received.
+    #[allow(dead_code)]
+    pub async fn stream_input(
+        &self,
+        voice_id: String,
+        voice_settings: Option<VoiceSettings>,
+        model_id: Option<String>,
+        media_format: Option<&OutputFormat>,
+    ) -> Result<
+        (
+            TextInputSender,
+            impl futures_core::Stream<Item = Result<AudioChunk, ElevenLabsError>> + use<>,
+        ),
+        ElevenLabsError,
+    > {
+        let url = self.get_stream_input_url(&voice_id, model_id.as_deref(), media_format)?;
+        let mut request = url.as_str().into_client_request()?;
+        request.headers_mut().insert(
+            "xi-api-key",
+            HeaderValue::from_str(&self.api_key).map_err(|e| ElevenLabsError::Stream {
+                message: format!("Invalid API key header: {}", e),
+            })?,
+        );
+
+        info!(voice_id = voice_id.as_str(); "Opening input stream with model {:?} and options {:?}", model_id, voice_settings);
+        let (socket, _) = tokio_tungstenite::connect_async(request).await?;
+        let (sink, source) = socket.split();
+
+        let mut sender = TextInputSender { sink };
+        // The first message has to be a single space, and is the only place voice settings can go
+        sender
+            .send_message(StreamInputMessage {
+                text: " ".to_string(),
+                voice_settings,
+                try_trigger_generation: None,
+                flush: None,
+            })
+            .await?;
+
+        let chunks = source.filter_map(|message| async move {
+            match message {
+                Ok(Message::Text(text)) => decode_message(text.as_str()),
+                Ok(Message::Close(frame)) => {
+                    debug!("Input stream closed: {:?}", frame);
+                    None
+                }
+                Ok(_) => None,
+                Err(e) => Some(Err(e.into())),
+            }
+        });
+
+        Ok((sender, chunks))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elevenlabs::media::PCM_16000HZ;
+    use crate::testing::start_mock_server;
+
+    #[test]
+    fn encodes_stream_input_query() {
+        let client = ElevenLabs::new_with_base_url(
+            "test-key".to_string(),
+            "https://api.example.com".to_string(),
+        );
+        let url = client
+            .get_stream_input_url("voice1", Some("model&x=1"), Some(PCM_16000HZ))
+            .unwrap();
+        assert_eq!(
+            url.as_str(),
+            "wss://api.example.com/v1/text-to-speech/voice1/stream-input?output_format=pcm_16000&model_id=model%26x%3D1"
+        );
+    }
+
+    #[tokio::test]
+    async fn streams_audio_for_text_sent_in_pieces() {
+        let client =
+            ElevenLabs::new_with_base_url("test-key".to_string(), start_mock_server().await);
+        let (mut sender, chunks) = client
+            .stream_input(
+                "mockVoice0000000000A".to_string(),
+                None,
+                Some("eleven_flash_v2_5".to_string()),
+                None,
+            )
+            .await
+            .unwrap();
+        sender.send_text("Hello ").await.unwrap();
+        sender.send_text("world ").await.unwrap();
+        sender.finish().await.unwrap();
+
+        let chunks = chunks
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let (last, audio) = chunks.split_last().unwrap();
+        assert!(last.is_final);
+        assert!(audio.iter().all(|c| !c.audio.is_empty() && !c.is_final));
+        let spoken = audio
+            .iter()
+            .flat_map(|c| c.alignment.as_ref().unwrap().chars.clone())
+            .collect::<String>();
+        assert_eq!(spoken, "Hello world ");
+    }
+}
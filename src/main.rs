@@ -1,16 +1,43 @@
 mod commands;
 mod elevenlabs;
+mod pronunciation_registry;
+mod soundboard;
+mod speech_cache;
+mod speech_stitching;
 mod streamutil;
+#[cfg(test)]
+mod testing;
+mod tts;
 mod types;
+mod voice_registry;
 
 use crate::commands::{
+    clip::{clip, save_clip},
+    clone_voice::{clone_voice, delete_voice},
+    dialogue::dialogue,
+    history::history,
     join_leave::{join_voice, leave_voice},
+    pronunciation::pronunciation,
+    sfx::sfx,
     speak::{speak, speak_vs},
+    transcribe::{transcribe, transcribe_message},
     usage::show_usage,
+    voice_changer::voice_changer,
+    voice_settings::voice_settings,
+    voices::voices,
+};
+use crate::pronunciation_registry::PronunciationRegistry;
+use crate::soundboard::{RecentClips, Soundboard};
+use crate::speech_cache::SpeechCache;
+use crate::speech_stitching::SpeechStitcher;
+use crate::tts::{
+    ProviderRegistry, elevenlabs::BackupElevenLabs, fallback::FallbackChain,
+    offline::OfflineProvider, openai::OpenAiProvider,
 };
 use crate::types::{Data, Error, HttpKey};
+use crate::voice_registry::VoiceRegistry;
 
-use ::log::{error, info};
+use ::log::{error, info, warn};
 use ::poise::serenity_prelude as serenity;
 
 // This trait adds the `register_songbird` and `register_songbird_with` methods
@@ -18,14 +45,9 @@ use ::poise::serenity_prelude as serenity;
 // The voice client can be retrieved in any command using `songbird::get(ctx).await`.
 use ::songbird::SerenityInit;
 
-fn parse_env() -> Result<(String, String), Box<dyn std::error::Error + Send + Sync>> {
-    let discord_token: String;
-    let elevenlabs_token: String;
-
-    match std::env::var(crate::types::DISCORD_TOKEN_ENV) {
-        Ok(token) => {
-            discord_token = token;
-        }
+fn parse_env() -> Result<(String, Option<String>, Option<String>), Error> {
+    let discord_token = match std::env::var(crate::types::DISCORD_TOKEN_ENV) {
+        Ok(token) => token,
         Err(_) => {
             return Err(format!(
                 "Please set the {} environment variable",
@@ -33,32 +55,47 @@ fn parse_env() -> Result<(String, String), Box<dyn std::error::Error + Send + Sy
             )
             .into());
         }
-    }
+    };
 
-    match std::env::var(crate::types::ELEVENLABS_TOKEN_ENV) {
-        Ok(token) => {
-            elevenlabs_token = token;
-        }
-        Err(_) => {
-            return Err(format!(
-                "Please set the {} environment variable",
-                crate::types::ELEVENLABS_TOKEN_ENV
-            )
-            .into());
-        }
-    }
+    // Checked once we know whether there's an offline engine to fall back on
+    let elevenlabs_token = std::env::var(crate::types::ELEVENLABS_TOKEN_ENV).ok();
 
-    Ok((discord_token, elevenlabs_token))
+    // Optional: lets us point the bot at a local mock server instead of the real API
+    let elevenlabs_api_base = std::env::var(crate::types::ELEVENLABS_API_BASE_ENV).ok();
+
+    Ok((discord_token, elevenlabs_token, elevenlabs_api_base))
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     env_logger::init();
 
-    let (discord_token, elevenlabs_token) = parse_env().map_err(|e| {
+    let (discord_token, elevenlabs_token, elevenlabs_api_base) = parse_env().inspect_err(|e| {
         error!(error = e.to_string().as_str(); "Error parsing environment variables");
-        e
     })?;
+    // Optional: a second account to fall back on when the first runs out of quota
+    let elevenlabs_backup_token = std::env::var(crate::types::ELEVENLABS_BACKUP_TOKEN_ENV).ok();
+    let offline = OfflineProvider::from_env();
+    let openai = OpenAiProvider::from_env();
+    let elevenlabs_token = match (elevenlabs_token, offline.is_some() || openai.is_some()) {
+        (Some(token), _) => token,
+        // Enough to develop with, or to run on the other providers alone
+        (None, true) => {
+            warn!(
+                "{} isn't set, only voices from other providers will work",
+                crate::types::ELEVENLABS_TOKEN_ENV
+            );
+            String::new()
+        }
+        (None, false) => {
+            let e = format!(
+                "Please set the {} environment variable, or set up another speech provider",
+                crate::types::ELEVENLABS_TOKEN_ENV
+            );
+            error!(error = e.as_str(); "Error parsing environment variables");
+            return Err(e.into());
+        }
+    };
 
     let intents = serenity::GatewayIntents::non_privileged();
 
@@ -67,18 +104,72 @@ async fn main() -> Result<(), Error> {
             commands: vec![
                 speak(),
                 speak_vs(),
+                dialogue(),
                 join_voice(),
                 leave_voice(),
                 show_usage(),
+                voices(),
+                voice_settings(),
+                history(),
+                sfx(),
+                voice_changer(),
+                transcribe(),
+                transcribe_message(),
+                clone_voice(),
+                delete_voice(),
+                pronunciation(),
+                clip(),
+                save_clip(),
             ],
             ..Default::default()
         })
         .setup(|ctx, _ready, framework| {
             Box::pin(async move {
-                let el_client = elevenlabs::ElevenLabs::new_from_key(elevenlabs_token.to_string());
+                if let Some(api_base) = &elevenlabs_api_base {
+                    info!(api_base = api_base.as_str(); "Using custom ElevenLabs API base URL");
+                }
+                let new_el_client = |api_key| match &elevenlabs_api_base {
+                    Some(api_base) => {
+                        elevenlabs::ElevenLabs::new_with_base_url(api_key, api_base.clone())
+                    }
+                    None => elevenlabs::ElevenLabs::new_from_key(api_key),
+                };
+                let el_client = std::sync::Arc::new(new_el_client(elevenlabs_token));
+                let models = el_client.load_model_registry().await;
+                let mut voices = VoiceRegistry::load(&el_client).await;
+                let mut providers = ProviderRegistry::default();
+                providers.add(el_client.clone());
+                if let Some(backup_token) = elevenlabs_backup_token {
+                    info!("Using a backup ElevenLabs API key");
+                    providers.add(std::sync::Arc::new(BackupElevenLabs(new_el_client(
+                        backup_token,
+                    ))));
+                }
+                if let Some(offline) = offline {
+                    voices = voices.with_provider_voices(offline.get_configured_voices().await);
+                    providers.add(std::sync::Arc::new(offline));
+                }
+                if let Some(openai) = openai {
+                    voices = voices.with_provider_voices(openai.get_configured_voices());
+                    providers.add(std::sync::Arc::new(openai));
+                }
+                let pronunciations = PronunciationRegistry::load(&el_client).await;
                 poise::builtins::register_globally(ctx, &framework.options().commands).await?;
 
-                Ok(Data { client: el_client })
+                let fallback = FallbackChain::from_env(&providers);
+
+                Ok(Data {
+                    client: el_client,
+                    providers,
+                    fallback,
+                    models,
+                    voices: tokio::sync::RwLock::new(voices),
+                    pronunciations: tokio::sync::RwLock::new(pronunciations),
+                    stitcher: SpeechStitcher::from_env(),
+                    cache: SpeechCache::load().await,
+                    soundboard: Soundboard::load().await,
+                    recent_clips: RecentClips::default(),
+                })
             })
         })
         .build();
@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use log::{error, info};
+use serenity::all::GuildId;
+
+use crate::elevenlabs::{ElevenLabs, pronunciation::PronunciationDictionaryLocator};
+
+// Each server's dictionary is named after it, so they can be found again after a restart
+const DICTIONARY_NAME_PREFIX: &str = "discord-guild-";
+
+/// The latest version of each server's pronunciation dictionary, kept on ElevenLabs
+#[derive(Debug, Default)]
+pub struct PronunciationRegistry {
+    guilds: HashMap<GuildId, PronunciationDictionaryLocator>,
+    /// Held while a server's dictionary is being changed, so edits made at once can't overwrite each other's
+    /// versions or both create a dictionary
+    edit_locks: Mutex<HashMap<GuildId, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl PronunciationRegistry {
+    /// Finds the servers' dictionaries in the account. If that fails, nobody gets their pronunciations until
+    /// they add a rule, which creates a new dictionary.
+    pub async fn load(client: &ElevenLabs) -> Self {
+        match client.get_pronunciation_dictionaries().await {
+            Ok(dictionaries) => {
+                let guilds = dictionaries
+                    .iter()
+                    .filter_map(|d| {
+                        let guild_id = d
+                            .name
+                            .strip_prefix(DICTIONARY_NAME_PREFIX)?
+                            .parse::<u64>()
+                            .ok()?;
+                        Some((GuildId::new(guild_id), d.get_locator()))
+                    })
+                    .collect::<HashMap<_, _>>();
+                info!(count = guilds.len(); "Loaded pronunciation dictionaries");
+                Self {
+                    guilds,
+                    ..Default::default()
+                }
+            }
+            Err(e) => {
+                error!(error = e.to_string().as_str(); "Failed to load pronunciation dictionaries");
+                Self::default()
+            }
+        }
+    }
+
+    pub fn get_dictionary_name(guild_id: GuildId) -> String {
+        format!("{}{}", DICTIONARY_NAME_PREFIX, guild_id)
+    }
+
+    pub fn get(&self, guild_id: GuildId) -> Option<&PronunciationDictionaryLocator> {
+        self.guilds.get(&guild_id)
+    }
+
+    /// The lock to hold while changing the server's dictionary. Taking it doesn't block anyone reading the
+    /// registry, so speech keeps using the current version in the meantime.
+    pub fn get_edit_lock(&self, guild_id: GuildId) -> Arc<tokio::sync::Mutex<()>> {
+        self.edit_locks
+            .lock()
+            .unwrap()
+            .entry(guild_id)
+            .or_default()
+            .clone()
+    }
+
+    pub fn set(&mut self, guild_id: GuildId, locator: PronunciationDictionaryLocator) {
+        self.guilds.insert(guild_id, locator);
+    }
+}
@@ -0,0 +1,374 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use serenity::all::{GuildId, MessageId};
+use tokio::sync::Mutex;
+
+const SOUNDBOARD_DIR_ENV: &str = "SOUNDBOARD_DIR";
+const DEFAULT_SOUNDBOARD_DIR: &str = "/data/soundboard";
+const INDEX_FILE: &str = "clips.json";
+// Keeps names readable in autocomplete, which cuts choices off at 100 characters
+pub const MAX_CLIP_NAME_LEN: usize = 50;
+// Plenty for a night's callouts, without letting one server fill the disk
+const MAX_CLIPS_PER_GUILD: usize = 200;
+// A good while of busy chat, for a few hundred bytes each
+const MAX_RECENT_CLIPS: usize = 1000;
+
+/// What a clip says, and who had it generated
+#[derive(Debug, Clone)]
+pub struct ClipSource {
+    /// Name of the voice that spoke it, or the voices in a dialogue
+    pub voice: String,
+    pub text: String,
+    pub creator_id: u64,
+    pub creator_name: String,
+}
+
+/// A saved clip. The audio lives next to the server's index, in `file`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Clip {
+    pub name: String,
+    /// Name of the voice that spoke it
+    pub voice: String,
+    pub text: String,
+    pub creator_id: u64,
+    pub creator_name: String,
+    pub created_at: DateTime<Utc>,
+    file: String,
+}
+
+impl Clip {
+    pub fn new(name: String, source: ClipSource) -> Self {
+        Self {
+            name,
+            voice: source.voice,
+            text: source.text,
+            creator_id: source.creator_id,
+            creator_name: source.creator_name,
+            created_at: Utc::now(),
+            file: String::new(),
+        }
+    }
+}
+
+/// The trimmed clip name, or why it can't be used. The error is meant to be shown to the user.
+pub fn check_clip_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_CLIP_NAME_LEN {
+        return Err(format!(
+            "Clip names have to be between 1 and {} characters",
+            MAX_CLIP_NAME_LEN
+        ));
+    }
+    Ok(name.to_string())
+}
+
+/// What the bot's latest clips say, by the message they were posted in, so they can be saved to the soundboard
+/// as they are. Only kept in memory, so clips from before a restart can't be saved.
+#[derive(Default)]
+pub struct RecentClips {
+    messages: std::sync::Mutex<VecDeque<(MessageId, ClipSource)>>,
+}
+
+impl RecentClips {
+    pub fn record(&self, message_id: MessageId, source: ClipSource) {
+        let mut messages = self.messages.lock().unwrap();
+        if messages.len() >= MAX_RECENT_CLIPS {
+            messages.pop_front();
+        }
+        messages.push_back((message_id, source));
+    }
+
+    pub fn get(&self, message_id: MessageId) -> Option<ClipSource> {
+        self.messages
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(id, _)| *id == message_id)
+            .map(|(_, source)| source.clone())
+    }
+}
+
+/// Each server's saved clips, kept on disk as `<dir>/<guild ID>/clips.json` plus one audio file per clip
+pub struct Soundboard {
+    dir: PathBuf,
+    /// Held across disk writes, so two saves can't clobber each other's index
+    guilds: Mutex<HashMap<GuildId, Vec<Clip>>>,
+}
+
+impl Soundboard {
+    /// Reads every server's clips from `SOUNDBOARD_DIR`
+    pub async fn load() -> Self {
+        Self::open(PathBuf::from(
+            std::env::var(SOUNDBOARD_DIR_ENV)
+                .unwrap_or_else(|_| DEFAULT_SOUNDBOARD_DIR.to_string()),
+        ))
+        .await
+    }
+
+    /// Reads every server's clips from the directory. Servers whose index can't be read start out empty.
+    pub async fn open(dir: PathBuf) -> Self {
+        let mut guilds = HashMap::new();
+        if let Ok(mut read_dir) = tokio::fs::read_dir(&dir).await {
+            while let Ok(Some(entry)) = read_dir.next_entry().await {
+                let Some(guild_id) = entry
+                    .file_name()
+                    .to_str()
+                    .and_then(|n| n.parse::<u64>().ok())
+                    .filter(|id| *id != 0)
+                else {
+                    continue;
+                };
+                let index = entry.path().join(INDEX_FILE);
+                let clips = match tokio::fs::read(&index).await {
+                    Ok(bytes) => {
+                        serde_json::from_slice::<Vec<Clip>>(&bytes).map_err(|e| e.to_string())
+                    }
+                    Err(e) => Err(e.to_string()),
+                };
+                match clips {
+                    Ok(clips) => {
+                        guilds.insert(GuildId::new(guild_id), clips);
+                    }
+                    Err(e) => {
+                        error!(guild_id = guild_id, error = e.as_str(); "Failed to read soundboard index")
+                    }
+                }
+            }
+        }
+        info!(
+            dir = dir.to_string_lossy().as_ref(), guilds = guilds.len(),
+            clips = guilds.values().map(Vec::len).sum::<usize>();
+            "Loaded soundboard"
+        );
+
+        Self {
+            dir,
+            guilds: Mutex::new(guilds),
+        }
+    }
+
+    /// The server's clips, sorted by name
+    pub async fn list(&self, guild_id: GuildId) -> Vec<Clip> {
+        let mut clips = self
+            .guilds
+            .lock()
+            .await
+            .get(&guild_id)
+            .cloned()
+            .unwrap_or_default();
+        clips.sort_by_key(|c| c.name.to_lowercase());
+        clips
+    }
+
+    pub async fn find(&self, guild_id: GuildId, name: &str) -> Option<Clip> {
+        self.guilds
+            .lock()
+            .await
+            .get(&guild_id)?
+            .iter()
+            .find(|c| c.name.eq_ignore_ascii_case(name.trim()))
+            .cloned()
+    }
+
+    /// Saves a clip's audio, which has to be playable as-is (e.g. raw PCM needs its WAV header), with the given
+    /// file extension. A clip with the same name is replaced, but only if the same person made it.
+    /// The error is meant to be shown to the user.
+    pub async fn save(
+        &self,
+        guild_id: GuildId,
+        mut clip: Clip,
+        audio: &[u8],
+        extension: &str,
+    ) -> Result<(), String> {
+        let mut guilds = self.guilds.lock().await;
+        let clips = guilds.entry(guild_id).or_default();
+        let existing = clips
+            .iter()
+            .position(|c| c.name.eq_ignore_ascii_case(&clip.name));
+        match existing.map(|i| &clips[i]) {
+            Some(other) if other.creator_id != clip.creator_id => {
+                return Err(format!(
+                    "There's already a clip called \"{}\", saved by {}",
+                    other.name, other.creator_name
+                ));
+            }
+            None if clips.len() >= MAX_CLIPS_PER_GUILD => {
+                return Err(format!(
+                    "This server already has {} clips, which is as many as it can have",
+                    MAX_CLIPS_PER_GUILD
+                ));
+            }
+            _ => {}
+        }
+
+        let guild_dir = self.dir.join(guild_id.to_string());
+        // Named by hash, so any clip name makes a safe file name
+        let digest = openssl::sha::sha256(clip.name.to_lowercase().as_bytes());
+        let stem = digest[..8]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+        clip.file = format!("{}.{}", stem, extension);
+
+        let mut updated = clips.clone();
+        let replaced = match existing {
+            Some(i) => Some(std::mem::replace(&mut updated[i], clip.clone())),
+            None => {
+                updated.push(clip.clone());
+                None
+            }
+        };
+        let written = async {
+            tokio::fs::create_dir_all(&guild_dir).await?;
+            if let Some(replaced) = &replaced
+                && replaced.file != clip.file
+            {
+                let _ = tokio::fs::remove_file(guild_dir.join(&replaced.file)).await;
+            }
+            tokio::fs::write(guild_dir.join(&clip.file), audio).await?;
+            let index = serde_json::to_vec_pretty(&updated).map_err(std::io::Error::other)?;
+            tokio::fs::write(guild_dir.join(INDEX_FILE), index).await
+        };
+        if let Err(e) = written.await {
+            error!(guild_id = guild_id.get(), clip = clip.name.as_str(), error = e.to_string().as_str(); "Failed to save clip");
+            return Err(format!("Failed to save the clip: {}", e));
+        }
+
+        info!(guild_id = guild_id.get(), clip = clip.name.as_str(), replaced = replaced.is_some(); "Saved clip");
+        *clips = updated;
+        Ok(())
+    }
+
+    /// The clip's audio, ready to play or post
+    pub async fn read_audio(&self, guild_id: GuildId, clip: &Clip) -> std::io::Result<Vec<u8>> {
+        tokio::fs::read(self.dir.join(guild_id.to_string()).join(&clip.file)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUILD: GuildId = GuildId::new(1);
+
+    fn source(creator_id: u64) -> ClipSource {
+        ClipSource {
+            voice: "June".to_string(),
+            text: "Cashout in progress".to_string(),
+            creator_id,
+            creator_name: format!("user{}", creator_id),
+        }
+    }
+
+    fn clip(name: &str, creator_id: u64) -> Clip {
+        Clip::new(name.to_string(), source(creator_id))
+    }
+
+    #[test]
+    fn checks_clip_names() {
+        assert_eq!(check_clip_name("  Cashout  "), Ok("Cashout".to_string()));
+        assert!(check_clip_name("   ").is_err());
+        assert!(check_clip_name(&"é".repeat(MAX_CLIP_NAME_LEN)).is_ok());
+        assert!(check_clip_name(&"a".repeat(MAX_CLIP_NAME_LEN + 1)).is_err());
+    }
+
+    #[tokio::test]
+    async fn keeps_clips_across_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let soundboard = Soundboard::open(dir.path().to_path_buf()).await;
+        soundboard
+            .save(GUILD, clip("Cashout", 7), b"audio", "mp3")
+            .await
+            .unwrap();
+
+        let soundboard = Soundboard::open(dir.path().to_path_buf()).await;
+        let found = soundboard.find(GUILD, " cashout ").await.unwrap();
+        assert_eq!(found.name, "Cashout");
+        assert_eq!(found.voice, "June");
+        assert_eq!(found.text, "Cashout in progress");
+        assert_eq!(found.creator_name, "user7");
+        assert_eq!(
+            soundboard.read_audio(GUILD, &found).await.unwrap(),
+            b"audio"
+        );
+        assert!(soundboard.list(GuildId::new(2)).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn only_lets_the_creator_replace_a_clip() {
+        let dir = tempfile::tempdir().unwrap();
+        let soundboard = Soundboard::open(dir.path().to_path_buf()).await;
+        soundboard
+            .save(GUILD, clip("Cashout", 7), b"first", "mp3")
+            .await
+            .unwrap();
+
+        let taken = soundboard
+            .save(GUILD, clip("CASHOUT", 8), b"other", "mp3")
+            .await;
+        assert_eq!(
+            taken,
+            Err("There's already a clip called \"Cashout\", saved by user7".to_string())
+        );
+
+        soundboard
+            .save(GUILD, clip("CASHOUT", 7), b"second", "wav")
+            .await
+            .unwrap();
+        let clips = soundboard.list(GUILD).await;
+        assert_eq!(clips.len(), 1);
+        assert_eq!(
+            soundboard.read_audio(GUILD, &clips[0]).await.unwrap(),
+            b"second"
+        );
+        // The old recording doesn't linger next to the new one
+        let files = std::fs::read_dir(dir.path().join("1")).unwrap().count();
+        assert_eq!(files, 2);
+    }
+
+    #[tokio::test]
+    async fn caps_the_clips_per_server() {
+        let dir = tempfile::tempdir().unwrap();
+        let soundboard = Soundboard::open(dir.path().to_path_buf()).await;
+        for i in 0..MAX_CLIPS_PER_GUILD {
+            soundboard
+                .save(GUILD, clip(&format!("Clip {}", i), 7), b"", "mp3")
+                .await
+                .unwrap();
+        }
+
+        assert!(
+            soundboard
+                .save(GUILD, clip("One too many", 7), b"", "mp3")
+                .await
+                .is_err()
+        );
+        // Replacing one is still fine, and so are other servers
+        assert!(
+            soundboard
+                .save(GUILD, clip("Clip 0", 7), b"", "mp3")
+                .await
+                .is_ok()
+        );
+        assert!(
+            soundboard
+                .save(GuildId::new(2), clip("One too many", 7), b"", "mp3")
+                .await
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn remembers_only_the_latest_clips() {
+        let recent = RecentClips::default();
+        for i in 1..=MAX_RECENT_CLIPS as u64 + 1 {
+            recent.record(MessageId::new(i), source(i));
+        }
+        assert!(recent.get(MessageId::new(1)).is_none());
+        assert_eq!(recent.get(MessageId::new(2)).unwrap().creator_id, 2);
+    }
+}
@@ -0,0 +1,513 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::elevenlabs::media::{OutputFormat, parse_output_format};
+use crate::tts::SpeechRequest;
+
+const CACHE_DIR_ENV: &str = "SPEECH_CACHE_DIR";
+const CACHE_MAX_MB_ENV: &str = "SPEECH_CACHE_MAX_MB";
+// The Docker image keeps everything in its /data volume
+const DATA_DIR: &str = "/data";
+const DEFAULT_CACHE_DIR: &str = "/data/speech_cache";
+// Outside of Docker, under the user's cache directory
+const USER_CACHE_DIR: &str = "discord-finals-tts/speech_cache";
+// Plenty for a few thousand callouts
+const DEFAULT_CACHE_MAX_MB: u64 = 256;
+
+const AUDIO_EXTENSION: &str = "audio";
+const METADATA_EXTENSION: &str = "json";
+
+/// Stored next to each clip. The voice and text are only there to make the directory easier to poke through.
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheMetadata {
+    format: String,
+    seed: Option<u32>,
+    voice_id: String,
+    text: String,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    size: u64,
+    last_used: SystemTime,
+}
+
+/// A clip from the cache
+pub struct CachedSpeech {
+    pub format: &'static OutputFormat,
+    /// The seed it was generated with, if any
+    pub seed: Option<u32>,
+    pub audio: Vec<u8>,
+}
+
+/// Generated clips on disk, keyed by a hash of everything that went into them, so repeated lines don't cost
+/// characters again. The least recently used clips are dropped once the cache grows past its size limit.
+pub struct SpeechCache {
+    /// None if caching is turned off
+    dir: Option<PathBuf>,
+    max_bytes: u64,
+    entries: Mutex<HashMap<String, CacheEntry>>,
+}
+
+impl SpeechCache {
+    /// Caches in `SPEECH_CACHE_DIR`, up to `SPEECH_CACHE_MAX_MB` megabytes. 0 turns caching off.
+    /// Whatever is already in the directory is picked up again.
+    pub async fn load() -> Self {
+        let max_mb = match std::env::var(CACHE_MAX_MB_ENV) {
+            Ok(env_value) => env_value.parse::<u64>().unwrap_or_else(|_| {
+                error!(
+                    "Failed to parse env variable {}={} as u64, using {}",
+                    CACHE_MAX_MB_ENV, env_value, DEFAULT_CACHE_MAX_MB
+                );
+                DEFAULT_CACHE_MAX_MB
+            }),
+            Err(_) => DEFAULT_CACHE_MAX_MB,
+        };
+        if max_mb == 0 {
+            info!("Speech cache is turned off");
+            return Self::disabled();
+        }
+
+        let dir = match std::env::var(CACHE_DIR_ENV) {
+            Ok(dir) => PathBuf::from(dir),
+            Err(_) => {
+                let dir = get_default_cache_dir(Path::new(DATA_DIR).is_dir(), |name| {
+                    std::env::var(name).ok()
+                });
+                info!(
+                    dir = dir.to_string_lossy().as_ref();
+                    "Caching speech in the default directory, set {} to move it", CACHE_DIR_ENV
+                );
+                dir
+            }
+        };
+        Self::open(dir, max_mb * 1024 * 1024).await
+    }
+
+    /// Caches in the given directory, up to `max_bytes`, picking up whatever is already there
+    pub async fn open(dir: PathBuf, max_bytes: u64) -> Self {
+        let disabled = Self::disabled();
+        if let Err(e) = tokio::fs::create_dir_all(&dir).await {
+            warn!(
+                dir = dir.to_string_lossy().as_ref(), error = e.to_string().as_str();
+                "Failed to create speech cache directory, caching is turned off. Set {} to a writable directory to turn it back on",
+                CACHE_DIR_ENV
+            );
+            return disabled;
+        }
+        let entries = match load_entries(&dir).await {
+            Ok(entries) => entries,
+            Err(e) => {
+                error!(
+                    dir = dir.to_string_lossy().as_ref(), error = e.to_string().as_str();
+                    "Failed to read speech cache directory, caching is turned off"
+                );
+                return disabled;
+            }
+        };
+        info!(
+            dir = dir.to_string_lossy().as_ref(), clips = entries.len(),
+            bytes = entries.values().map(|e| e.size).sum::<u64>(), max_bytes = max_bytes;
+            "Loaded speech cache"
+        );
+
+        Self {
+            dir: Some(dir),
+            max_bytes,
+            entries: Mutex::new(entries),
+        }
+    }
+
+    fn disabled() -> Self {
+        Self {
+            dir: None,
+            max_bytes: 0,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The key for a request to the given provider. None if it shouldn't be cached, because it's stitched onto
+    /// other lines and so depends on what was said around it.
+    ///
+    /// Meant to be taken before a random seed is picked: a request without a seed takes any take of the line, so
+    /// it gets whichever one was cached for it, and that take's seed is kept to show where it came from.
+    pub fn get_key(provider_id: &str, request: &SpeechRequest) -> Option<String> {
+        let options = &request.options;
+        if !options.previous_request_ids.is_empty()
+            || !options.next_request_ids.is_empty()
+            || options.previous_text.is_some()
+            || options.next_text.is_some()
+        {
+            return None;
+        }
+        // Object keys are sorted, so the same request always comes out the same
+        let described = serde_json::json!({
+            "provider": provider_id,
+            "voice_id": request.voice_id,
+            "text": request.text,
+            "model_id": request.model_id,
+            "voice_settings": request.voice_settings,
+            "format": request.format.to_string(),
+            "seed": options.seed,
+            "language_code": options.language_code,
+            "apply_text_normalization": options.apply_text_normalization,
+            "apply_language_text_normalization": options.apply_language_text_normalization,
+            "pronunciation_dictionary_locators": options.pronunciation_dictionary_locators,
+        });
+        let digest = openssl::sha::sha256(described.to_string().as_bytes());
+        Some(digest.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+
+    pub async fn get(&self, key: &str) -> Option<CachedSpeech> {
+        let dir = self.dir.as_ref()?;
+        if !self.entries.lock().unwrap().contains_key(key) {
+            return None;
+        }
+
+        let audio_path = dir.join(format!("{}.{}", key, AUDIO_EXTENSION));
+        let read = async {
+            let metadata = tokio::fs::read(dir.join(format!("{}.{}", key, METADATA_EXTENSION)))
+                .await
+                .map_err(|e| e.to_string())?;
+            let metadata: CacheMetadata =
+                serde_json::from_slice(&metadata).map_err(|e| e.to_string())?;
+            let format = parse_output_format(&metadata.format)
+                .ok_or_else(|| format!("Unknown format {}", metadata.format))?;
+            let audio = tokio::fs::read(&audio_path)
+                .await
+                .map_err(|e| e.to_string())?;
+            Ok::<_, String>(CachedSpeech {
+                format,
+                seed: metadata.seed,
+                audio,
+            })
+        };
+        let cached = match read.await {
+            Ok(cached) => cached,
+            Err(e) => {
+                error!(key = key, error = e.as_str(); "Failed to read cached speech, dropping it");
+                self.remove(key).await;
+                return None;
+            }
+        };
+
+        let now = SystemTime::now();
+        if let Some(entry) = self.entries.lock().unwrap().get_mut(key) {
+            entry.last_used = now;
+        }
+        // Keeps the order of use across restarts, where it's read back from the modification times
+        if let Ok(file) = tokio::fs::OpenOptions::new()
+            .append(true)
+            .open(&audio_path)
+            .await
+        {
+            let _ = file.into_std().await.set_modified(now);
+        }
+        debug!(key = key; "Speech cache hit");
+        Some(cached)
+    }
+
+    /// Stores the clip generated for a request, then drops the least recently used ones until the cache fits in
+    /// its limit again
+    pub async fn insert(
+        &self,
+        key: &str,
+        request: &SpeechRequest,
+        format: &'static OutputFormat,
+        audio: &[u8],
+    ) {
+        let Some(dir) = self.dir.as_ref() else {
+            return;
+        };
+        if audio.len() as u64 > self.max_bytes {
+            return;
+        }
+
+        let metadata = CacheMetadata {
+            format: format.to_string(),
+            seed: request.options.seed,
+            voice_id: request.voice_id.clone(),
+            text: request.text.clone(),
+        };
+        let metadata = match serde_json::to_vec(&metadata) {
+            Ok(m) => m,
+            Err(e) => {
+                error!(key = key, error = e.to_string().as_str(); "Failed to serialize cache metadata");
+                return;
+            }
+        };
+        // The metadata goes last, since clips without it are ignored when loading
+        let written = async {
+            tokio::fs::write(dir.join(format!("{}.{}", key, AUDIO_EXTENSION)), audio).await?;
+            tokio::fs::write(
+                dir.join(format!("{}.{}", key, METADATA_EXTENSION)),
+                &metadata,
+            )
+            .await
+        };
+        if let Err(e) = written.await {
+            error!(key = key, error = e.to_string().as_str(); "Failed to write speech to cache");
+            self.remove(key).await;
+            return;
+        }
+
+        let evicted = {
+            let mut entries = self.entries.lock().unwrap();
+            entries.insert(
+                key.to_string(),
+                CacheEntry {
+                    size: (audio.len() + metadata.len()) as u64,
+                    last_used: SystemTime::now(),
+                },
+            );
+            let mut total: u64 = entries.values().map(|e| e.size).sum();
+            let mut by_age = entries
+                .iter()
+                .map(|(k, e)| (e.last_used, k.clone()))
+                .collect::<Vec<_>>();
+            by_age.sort();
+            let mut evicted = Vec::new();
+            for (_, old_key) in by_age {
+                if total <= self.max_bytes {
+                    break;
+                }
+                if let Some(entry) = entries.remove(&old_key) {
+                    total -= entry.size;
+                    evicted.push(old_key);
+                }
+            }
+            evicted
+        };
+        for old_key in &evicted {
+            self.remove(old_key).await;
+        }
+        debug!(key = key, evicted = evicted.len(); "Cached speech");
+    }
+
+    async fn remove(&self, key: &str) {
+        self.entries.lock().unwrap().remove(key);
+        if let Some(dir) = self.dir.as_ref() {
+            for extension in [AUDIO_EXTENSION, METADATA_EXTENSION] {
+                let _ = tokio::fs::remove_file(dir.join(format!("{}.{}", key, extension))).await;
+            }
+        }
+    }
+}
+
+/// Finds the complete clips in the directory, using the audio's modification time as when it was last used
+/// Picks the /data volume when running in Docker, otherwise the user's cache directory
+/// ($XDG_CACHE_HOME, or ~/.cache), or failing that one next to the bot
+fn get_default_cache_dir(
+    data_dir_exists: bool,
+    get_var: impl Fn(&str) -> Option<String>,
+) -> PathBuf {
+    if data_dir_exists {
+        return PathBuf::from(DEFAULT_CACHE_DIR);
+    }
+    let non_empty = |name| get_var(name).filter(|v| !v.is_empty());
+    match non_empty("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| non_empty("HOME").map(|home| Path::new(&home).join(".cache")))
+    {
+        Some(cache_home) => cache_home.join(USER_CACHE_DIR),
+        None => PathBuf::from("speech_cache"),
+    }
+}
+
+async fn load_entries(dir: &Path) -> std::io::Result<HashMap<String, CacheEntry>> {
+    let mut entries = HashMap::new();
+    let mut read_dir = tokio::fs::read_dir(dir).await?;
+    while let Some(file) = read_dir.next_entry().await? {
+        let path = file.path();
+        if path.extension().and_then(|e| e.to_str()) != Some(METADATA_EXTENSION) {
+            continue;
+        }
+        let Some(key) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        let Ok(audio) = tokio::fs::metadata(dir.join(format!("{}.{}", key, AUDIO_EXTENSION))).await
+        else {
+            continue;
+        };
+        let metadata_size = file.metadata().await.map(|m| m.len()).unwrap_or(0);
+        entries.insert(
+            key.to_string(),
+            CacheEntry {
+                size: audio.len() + metadata_size,
+                last_used: audio.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            },
+        );
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elevenlabs::media::{MP3_44100HZ_128KBPS, PCM_16000HZ};
+    use crate::elevenlabs::types::{SpeechOptions, VoiceSettings};
+
+    fn request(text: &str) -> SpeechRequest {
+        SpeechRequest {
+            voice_id: "voice".to_string(),
+            text: text.to_string(),
+            voice_settings: Some(VoiceSettings {
+                stability: Some(0.5),
+                ..Default::default()
+            }),
+            model_id: Some("eleven_flash_v2_5".to_string()),
+            format: PCM_16000HZ,
+            options: SpeechOptions::default(),
+        }
+    }
+
+    fn key(request: &SpeechRequest) -> String {
+        SpeechCache::get_key("elevenlabs", request).unwrap()
+    }
+
+    #[test]
+    fn keys_are_stable() {
+        assert_eq!(key(&request("Hello")), key(&request("Hello")));
+        assert_eq!(key(&request("Hello")).len(), 64);
+        assert_ne!(key(&request("Hello")), key(&request("Goodbye")));
+        assert_ne!(
+            key(&request("Hello")),
+            SpeechCache::get_key("openai", &request("Hello")).unwrap()
+        );
+    }
+
+    #[test]
+    fn keys_depend_on_seed_format_and_settings() {
+        let plain = key(&request("Hello"));
+
+        let mut seeded = request("Hello");
+        seeded.options.seed = Some(42);
+        assert_ne!(key(&seeded), plain);
+
+        let mut mp3 = request("Hello");
+        mp3.format = MP3_44100HZ_128KBPS;
+        assert_ne!(key(&mp3), plain);
+
+        let mut faster = request("Hello");
+        faster.voice_settings.as_mut().unwrap().speed = Some(1.2);
+        assert_ne!(key(&faster), plain);
+    }
+
+    #[test]
+    fn stitched_requests_are_not_cached() {
+        let mut stitched = request("Hello");
+        stitched.options.previous_text = Some("Before".to_string());
+        assert_eq!(SpeechCache::get_key("elevenlabs", &stitched), None);
+    }
+
+    #[tokio::test]
+    async fn unseeded_requests_reuse_the_cached_take_and_its_seed() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = SpeechCache::open(dir.path().to_path_buf(), 1024 * 1024).await;
+
+        // Keyed before the random seed is picked, as speak does
+        let mut request = request("Hello");
+        let unseeded_key = key(&request);
+        request.options.seed = Some(42);
+        cache
+            .insert(&unseeded_key, &request, PCM_16000HZ, b"take")
+            .await;
+
+        let cached = cache.get(&unseeded_key).await.unwrap();
+        assert_eq!(cached.audio, b"take");
+        assert_eq!(cached.seed, Some(42));
+        // Asking for a seed is asking for that exact take, which has a key of its own
+        assert!(cache.get(&key(&request)).await.is_none());
+    }
+
+    #[test]
+    fn falls_back_to_the_user_cache_outside_docker() {
+        let vars = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(n, _)| *n == name)
+                    .map(|(_, v)| v.to_string())
+            }
+        };
+        let both = vars(&[("XDG_CACHE_HOME", "/xdg"), ("HOME", "/home/bot")]);
+
+        assert_eq!(
+            get_default_cache_dir(true, both),
+            PathBuf::from("/data/speech_cache")
+        );
+        assert_eq!(
+            get_default_cache_dir(false, both),
+            PathBuf::from("/xdg/discord-finals-tts/speech_cache")
+        );
+        assert_eq!(
+            get_default_cache_dir(
+                false,
+                vars(&[("XDG_CACHE_HOME", ""), ("HOME", "/home/bot")])
+            ),
+            PathBuf::from("/home/bot/.cache/discord-finals-tts/speech_cache")
+        );
+        assert_eq!(
+            get_default_cache_dir(false, vars(&[])),
+            PathBuf::from("speech_cache")
+        );
+    }
+
+    #[tokio::test]
+    async fn evicts_the_least_recently_used() {
+        let dir = tempfile::tempdir().unwrap();
+        // Room for two clips with their metadata, but not three
+        let cache = SpeechCache::open(dir.path().to_path_buf(), 2500).await;
+        let audio = vec![0; 1000];
+        let keys = ["first", "second", "third"].map(|text| (text, key(&request(text))));
+
+        for (text, key) in &keys[..2] {
+            cache.insert(key, &request(text), PCM_16000HZ, &audio).await;
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert!(cache.get(&keys[0].1).await.is_some());
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        let (text, key) = &keys[2];
+        cache.insert(key, &request(text), PCM_16000HZ, &audio).await;
+
+        assert!(cache.get(&keys[0].1).await.is_some());
+        assert!(cache.get(&keys[1].1).await.is_none());
+        assert!(cache.get(&keys[2].1).await.is_some());
+        assert!(!dir.path().join(format!("{}.audio", keys[1].1)).exists());
+    }
+
+    #[tokio::test]
+    async fn skips_clips_bigger_than_the_whole_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = SpeechCache::open(dir.path().to_path_buf(), 100).await;
+        let key = key(&request("Hello"));
+        cache
+            .insert(&key, &request("Hello"), PCM_16000HZ, &[0; 101])
+            .await;
+        assert!(cache.get(&key).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn picks_up_clips_after_a_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = key(&request("Hello"));
+        {
+            let cache = SpeechCache::open(dir.path().to_path_buf(), 1024 * 1024).await;
+            cache
+                .insert(&key, &request("Hello"), MP3_44100HZ_128KBPS, b"audio")
+                .await;
+        }
+        // Audio without metadata is a clip that was never finished
+        std::fs::write(dir.path().join("unfinished.audio"), b"partial").unwrap();
+
+        let cache = SpeechCache::open(dir.path().to_path_buf(), 1024 * 1024).await;
+        assert_eq!(cache.entries.lock().unwrap().len(), 1);
+        let cached = cache.get(&key).await.unwrap();
+        assert_eq!(cached.audio, b"audio");
+        assert_eq!(cached.format.to_string(), "mp3_44100_128");
+    }
+}
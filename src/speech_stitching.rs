@@ -0,0 +1,124 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use log::{debug, error};
+use serenity::all::GuildId;
+
+use crate::elevenlabs::types::SpeechOptions;
+
+const STITCH_WINDOW_ENV: &str = "SPEECH_STITCH_WINDOW_SECS";
+// Long enough to type the next part of an announcement
+const DEFAULT_STITCH_WINDOW_SECS: u64 = 60;
+// The most previous request IDs the API accepts
+const MAX_STITCHED_REQUESTS: usize = 3;
+
+/// The lines most recently spoken in a server, as long as they're all by the same voice
+struct SpokenChain {
+    voice_id: String,
+    /// Request ID (if the API sent one) and text of each line, oldest first
+    lines: VecDeque<(Option<String>, String)>,
+    last_spoken: Instant,
+}
+
+/// Remembers what was just said in each server, so a voice's next line can carry on from its previous ones
+pub struct SpeechStitcher {
+    /// None if stitching is turned off
+    window: Option<Duration>,
+    chains: Mutex<HashMap<GuildId, SpokenChain>>,
+}
+
+impl SpeechStitcher {
+    /// Lines within `SPEECH_STITCH_WINDOW_SECS` of each other are stitched together. 0 turns stitching off.
+    pub fn from_env() -> Self {
+        let window_secs = match std::env::var(STITCH_WINDOW_ENV) {
+            Ok(env_value) => env_value.parse::<u64>().unwrap_or_else(|_| {
+                error!(
+                    "Failed to parse env variable {}={} as u64, using {}",
+                    STITCH_WINDOW_ENV, env_value, DEFAULT_STITCH_WINDOW_SECS
+                );
+                DEFAULT_STITCH_WINDOW_SECS
+            }),
+            Err(_) => DEFAULT_STITCH_WINDOW_SECS,
+        };
+        Self {
+            window: (window_secs > 0).then(|| Duration::from_secs(window_secs)),
+            chains: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Points the options at the server's last lines, if they were by the same voice and recent enough
+    pub fn continue_from_previous(
+        &self,
+        guild_id: GuildId,
+        voice_id: &str,
+        options: &mut SpeechOptions,
+    ) {
+        let Some(window) = self.window else {
+            return;
+        };
+        let chains = self.chains.lock().unwrap();
+        let Some(chain) = chains
+            .get(&guild_id)
+            .filter(|c| c.voice_id == voice_id && c.last_spoken.elapsed() <= window)
+        else {
+            return;
+        };
+
+        options.previous_request_ids = chain
+            .lines
+            .iter()
+            .filter_map(|(request_id, _)| request_id.clone())
+            .collect();
+        options.previous_text = Some(
+            chain
+                .lines
+                .iter()
+                .map(|(_, text)| text.as_str())
+                .collect::<Vec<_>>()
+                .join(" "),
+        );
+        debug!(
+            guild_id = guild_id.get(), voice_id = voice_id, previous_requests = options.previous_request_ids.len();
+            "Stitching speech onto previous lines"
+        );
+    }
+
+    /// Records a line, starting a new chain if it's by a different voice or the last one was too long ago
+    pub fn record(
+        &self,
+        guild_id: GuildId,
+        voice_id: &str,
+        request_id: Option<String>,
+        text: &str,
+    ) {
+        let Some(window) = self.window else {
+            return;
+        };
+        let mut chains = self.chains.lock().unwrap();
+        let chain = chains
+            .entry(guild_id)
+            .and_modify(|c| {
+                if c.voice_id != voice_id || c.last_spoken.elapsed() > window {
+                    c.voice_id = voice_id.to_string();
+                    c.lines.clear();
+                }
+            })
+            .or_insert_with(|| SpokenChain {
+                voice_id: voice_id.to_string(),
+                lines: VecDeque::new(),
+                last_spoken: Instant::now(),
+            });
+
+        chain.lines.push_back((request_id, text.to_string()));
+        while chain.lines.len() > MAX_STITCHED_REQUESTS {
+            chain.lines.pop_front();
+        }
+        chain.last_spoken = Instant::now();
+    }
+
+    /// Forgets the server's chain, so the next line starts fresh
+    pub fn reset(&self, guild_id: GuildId) {
+        self.chains.lock().unwrap().remove(&guild_id);
+    }
+}
@@ -1,5 +1,10 @@
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::{Mutex, mpsc};
+
+use ::bytes::{Buf, Bytes};
 use ::futures::{StreamExt, pin_mut};
 use ::log::error;
+use ::symphonia::core::io::MediaSource;
 use ::tokio::{fs::File, io::AsyncWriteExt};
 
 type Error = Box<dyn std::error::Error + Send + Sync>;
@@ -14,7 +19,7 @@ pub async fn write_stream_to_file(
     while let Some(item) = stream.next().await {
         match item {
             Ok(ref bytes) => {
-                file.write_all(&bytes).await?;
+                file.write_all(bytes).await?;
             }
             Err(e) => {
                 error!(error = e.to_string().as_str(); "Error writing stream to file");
@@ -42,8 +47,8 @@ pub async fn write_stream_to_filename(
 }
 
 #[allow(dead_code)]
-pub async fn write_stream_to_vec_u8(
-    stream: impl futures::Stream<Item = reqwest::Result<bytes::Bytes>>,
+pub async fn write_stream_to_vec_u8<E: std::error::Error + Send + Sync + 'static>(
+    stream: impl futures::Stream<Item = Result<bytes::Bytes, E>>,
 ) -> Result<Vec<u8>, Error> {
     pin_mut!(stream);
 
@@ -51,7 +56,7 @@ pub async fn write_stream_to_vec_u8(
     while let Some(item) = stream.next().await {
         match item {
             Ok(ref new_bytes) => {
-                let thing: Vec<u8> = new_bytes.iter().map(|b| *b).collect::<Vec<u8>>();
+                let thing: Vec<u8> = new_bytes.iter().copied().collect::<Vec<u8>>();
                 bytes.extend(thing);
             }
             Err(e) => {
@@ -62,3 +67,94 @@ pub async fn write_stream_to_vec_u8(
     }
     Ok(bytes)
 }
+
+/// A `MediaSource` fed from an async byte stream, so songbird can start decoding audio before it's fully downloaded.
+///
+/// Reads block until the next chunk arrives, which is fine since songbird reads its inputs on a blocking thread.
+/// The stream ends once the matching `StreamingSourceSender` is dropped.
+pub struct StreamingSource {
+    receiver: Mutex<mpsc::Receiver<Bytes>>,
+    current: Bytes,
+}
+
+pub struct StreamingSourceSender(mpsc::Sender<Bytes>);
+
+impl StreamingSource {
+    pub fn new() -> (Self, StreamingSourceSender) {
+        let (sender, receiver) = mpsc::channel();
+        (
+            Self {
+                receiver: Mutex::new(receiver),
+                current: Bytes::new(),
+            },
+            StreamingSourceSender(sender),
+        )
+    }
+
+    /// Plays the given bytes before anything from the stream, e.g. a header the stream itself lacks
+    pub fn with_header(mut self, header: Vec<u8>) -> Self {
+        self.current = Bytes::from(header);
+        self
+    }
+}
+
+impl Read for StreamingSource {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while !self.current.has_remaining() {
+            // Only ever locked here, and we have `&mut self`, so a poisoned lock is still fine to use
+            let receiver = self.receiver.get_mut().unwrap_or_else(|e| e.into_inner());
+            match receiver.recv() {
+                Ok(next) => self.current = next,
+                // Sender is gone, so there's no more data coming
+                Err(_) => return Ok(0),
+            }
+        }
+
+        let len = buf.len().min(self.current.remaining());
+        self.current.copy_to_slice(&mut buf[..len]);
+        Ok(len)
+    }
+}
+
+impl Seek for StreamingSource {
+    fn seek(&mut self, _pos: SeekFrom) -> std::io::Result<u64> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "streaming sources can't seek",
+        ))
+    }
+}
+
+impl MediaSource for StreamingSource {
+    fn is_seekable(&self) -> bool {
+        false
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Forwards every chunk of the stream to a `StreamingSource` as it arrives, while also collecting the whole thing.
+pub async fn tee_stream_to_vec_u8<E: std::error::Error + Send + Sync + 'static>(
+    stream: impl futures::Stream<Item = Result<bytes::Bytes, E>>,
+    sender: StreamingSourceSender,
+) -> Result<Vec<u8>, Error> {
+    pin_mut!(stream);
+
+    let mut bytes = Vec::<u8>::new();
+    while let Some(item) = stream.next().await {
+        match item {
+            Ok(new_bytes) => {
+                bytes.extend_from_slice(&new_bytes);
+                // If playback was stopped nobody is listening anymore, but we still want the full clip
+                let _ = sender.0.send(new_bytes);
+            }
+            Err(e) => {
+                error!(error = e.to_string().as_str(); "Error teeing stream");
+                return Err(Box::new(e));
+            }
+        }
+    }
+    Ok(bytes)
+}
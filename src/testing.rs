@@ -0,0 +1,16 @@
+//! Helpers shared by the tests
+
+#[path = "../examples/mock_elevenlabs/server.rs"]
+mod mock_elevenlabs;
+
+/// Starts the mock ElevenLabs server on a free port, returning its base URL
+pub async fn start_mock_server() -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("a free port to bind to");
+    let addr = listener
+        .local_addr()
+        .expect("a bound listener has an address");
+    tokio::spawn(async move { axum::serve(listener, mock_elevenlabs::router()).await });
+    format!("http://{}/", addr)
+}
@@ -0,0 +1,131 @@
+use futures::StreamExt;
+use serenity::async_trait;
+
+use crate::elevenlabs::ElevenLabs;
+use crate::elevenlabs::error::ElevenLabsError;
+use crate::elevenlabs::types::VoiceListFilter;
+use crate::tts::{
+    ProviderUsage, ProviderVoice, Speech, SpeechRequest, SpeechStream, TtsError, TtsProvider,
+};
+
+pub const ELEVENLABS_PROVIDER_ID: &str = "elevenlabs";
+pub const ELEVENLABS_BACKUP_PROVIDER_ID: &str = "elevenlabs_backup";
+
+/// Whether the provider is ElevenLabs on any key, so the same voice IDs work with it
+pub fn is_elevenlabs_provider(provider_id: &str) -> bool {
+    provider_id == ELEVENLABS_PROVIDER_ID || provider_id == ELEVENLABS_BACKUP_PROVIDER_ID
+}
+
+#[async_trait]
+impl TtsProvider for ElevenLabs {
+    fn id(&self) -> &str {
+        ELEVENLABS_PROVIDER_ID
+    }
+
+    fn name(&self) -> &str {
+        "ElevenLabs"
+    }
+
+    async fn generate(&self, request: SpeechRequest) -> Result<Speech, TtsError> {
+        let (request_id, stream) = self
+            .generate_voice(
+                request.voice_id,
+                request.text,
+                request.voice_settings,
+                request.model_id,
+                Some(request.format),
+                request.options,
+            )
+            .await?;
+        futures::pin_mut!(stream);
+        let mut audio = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            audio.extend_from_slice(&chunk.map_err(ElevenLabsError::from)?);
+        }
+        Ok(Speech {
+            request_id,
+            format: request.format,
+            audio,
+        })
+    }
+
+    async fn stream(&self, request: SpeechRequest) -> Result<SpeechStream<'_>, TtsError> {
+        let (request_id, stream) = self
+            .stream_voice(
+                request.voice_id,
+                request.text,
+                request.voice_settings,
+                request.model_id,
+                Some(request.format),
+                request.options,
+            )
+            .await?;
+        Ok(SpeechStream {
+            request_id,
+            format: request.format,
+            audio: Box::pin(stream.map(|chunk| chunk.map_err(|e| ElevenLabsError::from(e).into()))),
+        })
+    }
+
+    async fn list_voices(&self) -> Result<Vec<ProviderVoice>, TtsError> {
+        Ok(self
+            .get_voice_list(&VoiceListFilter::default())
+            .await?
+            .into_iter()
+            .map(|v| ProviderVoice {
+                provider_id: ELEVENLABS_PROVIDER_ID.to_string(),
+                voice_id: v.voice_id,
+                name: v.name,
+                description: v.description,
+            })
+            .collect())
+    }
+
+    async fn get_usage(&self) -> Result<Option<ProviderUsage>, TtsError> {
+        let (used, limit, resets_at) = ElevenLabs::get_usage(self).await?;
+        Ok(Some(ProviderUsage {
+            used_characters: used.max(0) as u64,
+            character_limit: Some(limit.max(0) as u64),
+            resets_at,
+        }))
+    }
+}
+
+/// ElevenLabs on a second API key, to fall back on when the main one runs out of quota
+pub struct BackupElevenLabs(pub ElevenLabs);
+
+#[async_trait]
+impl TtsProvider for BackupElevenLabs {
+    fn id(&self) -> &str {
+        ELEVENLABS_BACKUP_PROVIDER_ID
+    }
+
+    fn name(&self) -> &str {
+        "ElevenLabs (backup key)"
+    }
+
+    async fn generate(&self, request: SpeechRequest) -> Result<Speech, TtsError> {
+        self.0.generate(request).await
+    }
+
+    async fn stream(&self, request: SpeechRequest) -> Result<SpeechStream<'_>, TtsError> {
+        self.0.stream(request).await
+    }
+
+    async fn list_voices(&self) -> Result<Vec<ProviderVoice>, TtsError> {
+        Ok(self
+            .0
+            .list_voices()
+            .await?
+            .into_iter()
+            .map(|v| ProviderVoice {
+                provider_id: ELEVENLABS_BACKUP_PROVIDER_ID.to_string(),
+                ..v
+            })
+            .collect())
+    }
+
+    async fn get_usage(&self) -> Result<Option<ProviderUsage>, TtsError> {
+        TtsProvider::get_usage(&self.0).await
+    }
+}
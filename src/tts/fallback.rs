@@ -0,0 +1,455 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+
+use log::{error, info, warn};
+
+use crate::tts::elevenlabs::is_elevenlabs_provider;
+use crate::tts::{ProviderRegistry, SpeechRequest, TtsError, TtsProvider};
+use crate::voice_registry::BotVoice;
+
+const CHAIN_ENV: &str = "TTS_FALLBACK_CHAIN";
+const VOICES_ENV: &str = "TTS_FALLBACK_VOICES";
+// Voice mappings under this name apply to every voice without one of its own
+const ANY_VOICE: &str = "*";
+
+/// Which provider ended up producing a clip
+#[derive(Debug)]
+pub struct Producer {
+    pub provider_id: String,
+    pub provider_name: String,
+    /// The voice's own provider and why it couldn't, if another one had to step in
+    pub fell_back_from: Option<(String, TtsError)>,
+}
+
+impl Producer {
+    /// E.g. "with ElevenLabs", or "with eSpeak NG because ElevenLabs failed: ..."
+    pub fn describe(&self) -> String {
+        match &self.fell_back_from {
+            None => format!("with {}", self.provider_name),
+            Some((failed_name, e)) => format!(
+                "with {} because {} failed: {}",
+                self.provider_name, failed_name, e
+            ),
+        }
+    }
+}
+
+/// The order providers are tried in when a voice's own provider is out of quota or down, and which of their
+/// voices stand in for ours
+pub struct FallbackChain {
+    provider_ids: Vec<String>,
+    /// (lowercased voice name, provider ID) to the provider's voice ID
+    voices: HashMap<(String, String), String>,
+}
+
+impl FallbackChain {
+    /// Reads the chain from `TTS_FALLBACK_CHAIN` as comma separated provider IDs, by default every set up provider
+    /// in the order they were added. `TTS_FALLBACK_VOICES` maps voices onto other providers as comma separated
+    /// "Voice name=provider:voice", with "*" as the name for a default.
+    ///
+    /// ElevenLabs keys stand in for each other with the same voice ID, anything else needs a mapping.
+    pub fn from_env(providers: &ProviderRegistry) -> Self {
+        let provider_ids: Vec<String> = match std::env::var(CHAIN_ENV) {
+            Ok(env_value) => env_value
+                .split(',')
+                .map(str::trim)
+                .filter(|id| !id.is_empty())
+                .filter(|id| {
+                    let known = providers.get(id).is_ok();
+                    if !known {
+                        warn!(provider_id = id; "Ignoring unknown provider in {}", CHAIN_ENV);
+                    }
+                    known
+                })
+                .map(str::to_string)
+                .collect(),
+            Err(_) => providers.all().map(|p| p.id().to_string()).collect(),
+        };
+
+        let mut voices = HashMap::new();
+        if let Ok(env_value) = std::env::var(VOICES_ENV) {
+            for entry in env_value
+                .split(',')
+                .map(str::trim)
+                .filter(|e| !e.is_empty())
+            {
+                let Some((name, (provider_id, voice_id))) = entry
+                    .split_once('=')
+                    .and_then(|(name, target)| Some((name, target.split_once(':')?)))
+                else {
+                    error!(
+                        "Failed to parse \"{}\" in env variable {}, expected \"Voice name=provider:voice\"",
+                        entry, VOICES_ENV
+                    );
+                    continue;
+                };
+                voices.insert(
+                    (name.trim().to_lowercase(), provider_id.trim().to_string()),
+                    voice_id.trim().to_string(),
+                );
+            }
+        }
+
+        Self::new(provider_ids, voices)
+    }
+
+    pub fn new(provider_ids: Vec<String>, voices: HashMap<(String, String), String>) -> Self {
+        info!(chain = provider_ids.join(" -> ").as_str(), mapped_voices = voices.len(); "Set up speech fallback chain");
+        Self {
+            provider_ids,
+            voices,
+        }
+    }
+
+    /// The providers and voice IDs to try for a voice in order, starting with its own
+    fn get_candidates(&self, voice: &BotVoice) -> Vec<(String, String)> {
+        let own_provider = voice.get_provider_id();
+        let name = voice.get_name().to_lowercase();
+        let mut candidates = vec![(own_provider.to_string(), voice.get_id())];
+        for provider_id in self.provider_ids.iter().filter(|id| *id != own_provider) {
+            let mapped = self
+                .voices
+                .get(&(name.clone(), provider_id.clone()))
+                .or_else(|| {
+                    self.voices
+                        .get(&(ANY_VOICE.to_string(), provider_id.clone()))
+                });
+            match mapped {
+                Some(voice_id) => candidates.push((provider_id.clone(), voice_id.clone())),
+                None if is_elevenlabs_provider(own_provider)
+                    && is_elevenlabs_provider(provider_id) =>
+                {
+                    candidates.push((provider_id.clone(), voice.get_id()))
+                }
+                None => {}
+            }
+        }
+        candidates
+    }
+
+    /// Runs `attempt` with the voice's own provider, moving down the chain while providers are out of quota or
+    /// down. If every one fails, the first error is returned.
+    pub async fn run<'a, T, F, Fut>(
+        &self,
+        providers: &'a ProviderRegistry,
+        voice: &BotVoice,
+        request: SpeechRequest,
+        attempt: F,
+    ) -> Result<(T, Producer), TtsError>
+    where
+        F: Fn(&'a Arc<dyn TtsProvider>, SpeechRequest) -> Fut,
+        Fut: Future<Output = Result<T, TtsError>>,
+    {
+        let mut candidates = self.get_candidates(voice).into_iter();
+        let (own_provider_id, _) = candidates
+            .next()
+            .expect("a voice's own provider always comes first");
+        let own_provider_name = providers
+            .get(&own_provider_id)
+            .map(|p| p.name().to_string())
+            .unwrap_or_else(|_| own_provider_id.clone());
+        let own_result = match providers.get(&own_provider_id) {
+            Ok(provider) => attempt(provider, request.clone()).await,
+            Err(e) => Err(e),
+        };
+        let first_error = match own_result {
+            Ok(result) => {
+                return Ok((
+                    result,
+                    Producer {
+                        provider_id: own_provider_id,
+                        provider_name: own_provider_name,
+                        fell_back_from: None,
+                    },
+                ));
+            }
+            Err(e) if !e.is_outage() => return Err(e),
+            Err(e) => e,
+        };
+
+        for (provider_id, voice_id) in candidates {
+            let Ok(provider) = providers.get(&provider_id) else {
+                continue;
+            };
+            warn!(
+                voice = voice, failed_provider = own_provider_id.as_str(), provider = provider_id.as_str(), voice_id = voice_id.as_str(),
+                error = first_error.to_string().as_str();
+                "Falling back to another speech provider"
+            );
+            match attempt(provider, fallback_request(&request, voice_id)).await {
+                Ok(result) => {
+                    return Ok((
+                        result,
+                        Producer {
+                            provider_id,
+                            provider_name: provider.name().to_string(),
+                            fell_back_from: Some((own_provider_name, first_error)),
+                        },
+                    ));
+                }
+                Err(e) => {
+                    error!(
+                        provider = provider_id.as_str(), error = e.to_string().as_str();
+                        "Fallback speech provider failed too"
+                    );
+                }
+            }
+        }
+        Err(first_error)
+    }
+}
+
+/// The request for another provider's voice. Dictionaries and earlier request IDs belong to the original
+/// account, so they're left out.
+fn fallback_request(request: &SpeechRequest, voice_id: String) -> SpeechRequest {
+    let mut request = request.clone();
+    request.voice_id = voice_id;
+    request.options.pronunciation_dictionary_locators.clear();
+    request.options.previous_request_ids.clear();
+    request.options.next_request_ids.clear();
+    request
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use futures::StreamExt;
+    use serenity::async_trait;
+
+    use super::*;
+    use crate::elevenlabs::error::ElevenLabsError;
+    use crate::elevenlabs::media::PCM_16000HZ;
+    use crate::elevenlabs::types::SpeechOptions;
+    use crate::tts::{ProviderUsage, ProviderVoice, Speech, SpeechStream};
+
+    fn outage() -> TtsError {
+        TtsError::Api {
+            provider: "first".to_string(),
+            status: Some(reqwest::StatusCode::SERVICE_UNAVAILABLE),
+            message: "Down".to_string(),
+        }
+    }
+
+    fn out_of_quota() -> TtsError {
+        TtsError::ElevenLabs(ElevenLabsError::QuotaExceeded {
+            message: "Out of credits".to_string(),
+        })
+    }
+
+    fn bad_request() -> TtsError {
+        TtsError::Api {
+            provider: "first".to_string(),
+            status: Some(reqwest::StatusCode::BAD_REQUEST),
+            message: "Bad".to_string(),
+        }
+    }
+
+    /// What a stub fails with, if it does
+    type Failure = fn() -> TtsError;
+
+    const OUTAGE: Option<Failure> = Some(outage);
+    const OUT_OF_QUOTA: Option<Failure> = Some(out_of_quota);
+    const BAD_REQUEST: Option<Failure> = Some(bad_request);
+
+    /// Answers every request the same way, and remembers which voices it was asked for
+    struct StubProvider {
+        id: &'static str,
+        fails_with: Option<Failure>,
+        voice_ids: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl TtsProvider for StubProvider {
+        fn id(&self) -> &str {
+            self.id
+        }
+
+        fn name(&self) -> &str {
+            self.id
+        }
+
+        async fn generate(&self, request: SpeechRequest) -> Result<Speech, TtsError> {
+            self.voice_ids.lock().unwrap().push(request.voice_id);
+            match self.fails_with {
+                Some(error) => Err(error()),
+                None => Ok(Speech {
+                    request_id: None,
+                    format: request.format,
+                    audio: self.id.as_bytes().to_vec(),
+                }),
+            }
+        }
+
+        /// The whole clip in one chunk
+        async fn stream(&self, request: SpeechRequest) -> Result<SpeechStream<'_>, TtsError> {
+            let speech = self.generate(request).await?;
+            let audio = bytes::Bytes::from(speech.audio);
+            Ok(SpeechStream {
+                request_id: speech.request_id,
+                format: speech.format,
+                audio: Box::pin(futures::stream::once(async move { Ok(audio) })),
+            })
+        }
+
+        async fn list_voices(&self) -> Result<Vec<ProviderVoice>, TtsError> {
+            Ok(Vec::new())
+        }
+
+        async fn get_usage(&self) -> Result<Option<ProviderUsage>, TtsError> {
+            Ok(None)
+        }
+    }
+
+    /// The registry, and the stubs in it to check what they were asked for
+    fn providers(
+        stubs: &[(&'static str, Option<Failure>)],
+    ) -> (ProviderRegistry, Vec<Arc<StubProvider>>) {
+        let stubs = stubs
+            .iter()
+            .map(|(id, fails_with)| {
+                Arc::new(StubProvider {
+                    id,
+                    fails_with: *fails_with,
+                    voice_ids: Mutex::new(Vec::new()),
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut providers = ProviderRegistry::default();
+        for stub in &stubs {
+            providers.add(stub.clone());
+        }
+        (providers, stubs)
+    }
+
+    fn asked_for(stub: &StubProvider) -> Vec<String> {
+        stub.voice_ids.lock().unwrap().clone()
+    }
+
+    fn chain(voices: &[(&str, &str, &str)]) -> FallbackChain {
+        FallbackChain::new(
+            vec![
+                "first".to_string(),
+                "second".to_string(),
+                "third".to_string(),
+            ],
+            voices
+                .iter()
+                .map(|(name, provider_id, voice_id)| {
+                    (
+                        (name.to_string(), provider_id.to_string()),
+                        voice_id.to_string(),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    fn voice() -> BotVoice {
+        BotVoice::Provider(ProviderVoice {
+            provider_id: "first".to_string(),
+            voice_id: "own".to_string(),
+            name: "Announcer".to_string(),
+            description: None,
+        })
+    }
+
+    fn request() -> SpeechRequest {
+        SpeechRequest {
+            voice_id: "own".to_string(),
+            text: "Hello".to_string(),
+            voice_settings: None,
+            model_id: None,
+            format: PCM_16000HZ,
+            options: SpeechOptions::default(),
+        }
+    }
+
+    async fn run(
+        chain: &FallbackChain,
+        providers: &ProviderRegistry,
+    ) -> Result<(Vec<u8>, Producer), TtsError> {
+        chain
+            .run(providers, &voice(), request(), |provider, request| async move {
+                provider.generate(request).await.map(|s| s.audio)
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn uses_the_voices_own_provider_first() {
+        let (providers, stubs) = providers(&[("first", None), ("second", None)]);
+        let (audio, producer) = run(&chain(&[("*", "second", "other")]), &providers)
+            .await
+            .unwrap();
+        assert_eq!(audio, b"first");
+        assert_eq!(producer.describe(), "with first");
+        assert!(asked_for(&stubs[1]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn falls_through_outages_to_mapped_voices() {
+        let (providers, stubs) =
+            providers(&[("first", OUT_OF_QUOTA), ("second", OUTAGE), ("third", None)]);
+        let chain = chain(&[("announcer", "second", "mapped"), ("*", "third", "anyone")]);
+        let (audio, producer) = run(&chain, &providers).await.unwrap();
+        assert_eq!(audio, b"third");
+        assert_eq!(producer.provider_id, "third");
+        assert_eq!(
+            producer.describe(),
+            "with third because first failed: ElevenLabs character quota exceeded: Out of credits"
+        );
+        assert_eq!(asked_for(&stubs[0]), vec!["own"]);
+        assert_eq!(asked_for(&stubs[1]), vec!["mapped"]);
+        assert_eq!(asked_for(&stubs[2]), vec!["anyone"]);
+    }
+
+    #[tokio::test]
+    async fn streams_through_the_chain_too() {
+        let (providers, _) = providers(&[("first", OUTAGE), ("second", None)]);
+        let (mut audio, producer) = chain(&[("*", "second", "other")])
+            .run(&providers, &voice(), request(), |provider, request| {
+                provider.stream(request)
+            })
+            .await
+            .map(|(speech, producer)| (speech.audio, producer))
+            .unwrap();
+        assert_eq!(producer.provider_id, "second");
+        let mut bytes = Vec::new();
+        while let Some(chunk) = audio.next().await {
+            bytes.extend_from_slice(&chunk.unwrap());
+        }
+        assert_eq!(bytes, b"second");
+    }
+
+    #[tokio::test]
+    async fn skips_providers_without_a_voice_to_stand_in() {
+        let (providers, stubs) = providers(&[("first", OUTAGE), ("second", None), ("third", None)]);
+        let (audio, _) = run(&chain(&[("*", "third", "anyone")]), &providers)
+            .await
+            .unwrap();
+        assert_eq!(audio, b"third");
+        assert!(asked_for(&stubs[1]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn stops_at_errors_in_the_request_itself() {
+        let (providers, stubs) = providers(&[("first", BAD_REQUEST), ("second", None)]);
+        let Err(e) = run(&chain(&[("*", "second", "other")]), &providers).await else {
+            panic!("a bad request shouldn't fall back");
+        };
+        assert_eq!(e.to_string(), "first returned HTTP 400: Bad");
+        assert!(asked_for(&stubs[1]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn returns_the_first_error_when_everything_fails() {
+        let (providers, stubs) = providers(&[("first", OUTAGE), ("second", BAD_REQUEST)]);
+        let Err(e) = run(&chain(&[("*", "second", "other")]), &providers).await else {
+            panic!("every provider failed");
+        };
+        assert_eq!(e.to_string(), "first returned HTTP 503: Down");
+        assert_eq!(asked_for(&stubs[1]), vec!["other"]);
+    }
+}
@@ -0,0 +1,217 @@
+pub mod elevenlabs;
+pub mod fallback;
+pub mod offline;
+pub mod openai;
+
+use std::pin::Pin;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::{Stream, StreamExt};
+use serenity::async_trait;
+
+use crate::elevenlabs::error::ElevenLabsError;
+use crate::elevenlabs::media::OutputFormat;
+use crate::elevenlabs::types::{SpeechOptions, VoiceSettings};
+
+pub type AudioStream<'a> = Pin<Box<dyn Stream<Item = Result<Bytes, TtsError>> + Send + 'a>>;
+
+/// What to say and how. Providers ignore whatever they have no equivalent for, e.g. models or stitching.
+#[derive(Debug, Clone)]
+pub struct SpeechRequest {
+    pub voice_id: String,
+    pub text: String,
+    pub voice_settings: Option<VoiceSettings>,
+    pub model_id: Option<String>,
+    /// The format we'd like. Providers that can't produce it pick the closest one they can.
+    pub format: &'static OutputFormat,
+    pub options: SpeechOptions,
+}
+
+/// A fully generated clip
+#[allow(dead_code)]
+pub struct Speech {
+    /// Only set by providers that support stitching requests together
+    pub request_id: Option<String>,
+    /// The format the audio is actually in
+    pub format: &'static OutputFormat,
+    pub audio: Vec<u8>,
+}
+
+/// A clip that's still being generated
+pub struct SpeechStream<'a> {
+    pub request_id: Option<String>,
+    pub format: &'static OutputFormat,
+    pub audio: AudioStream<'a>,
+}
+
+/// A voice as listed by a provider
+#[derive(Debug, Clone)]
+pub struct ProviderVoice {
+    pub provider_id: String,
+    pub voice_id: String,
+    pub name: String,
+    pub description: Option<String>,
+}
+
+/// How much of a provider's quota has been used
+#[derive(Debug, Clone)]
+pub struct ProviderUsage {
+    pub used_characters: u64,
+    pub character_limit: Option<u64>,
+    pub resets_at: Option<DateTime<Utc>>,
+}
+
+/// Something that can turn text into speech
+#[async_trait]
+pub trait TtsProvider: Send + Sync {
+    /// Stable ID that voices use to refer to the provider
+    fn id(&self) -> &str;
+
+    /// Name to show to users
+    fn name(&self) -> &str;
+
+    /// Generates the whole clip. By default this just collects `stream`.
+    async fn generate(&self, request: SpeechRequest) -> Result<Speech, TtsError> {
+        let speech = self.stream(request).await?;
+        let mut audio = Vec::new();
+        let mut chunks = speech.audio;
+        while let Some(chunk) = chunks.next().await {
+            audio.extend_from_slice(&chunk?);
+        }
+        Ok(Speech {
+            request_id: speech.request_id,
+            format: speech.format,
+            audio,
+        })
+    }
+
+    /// Starts generating, handing out the audio as it's produced
+    async fn stream(&self, request: SpeechRequest) -> Result<SpeechStream<'_>, TtsError>;
+
+    async fn list_voices(&self) -> Result<Vec<ProviderVoice>, TtsError>;
+
+    /// None if the provider has no quota to speak of
+    async fn get_usage(&self) -> Result<Option<ProviderUsage>, TtsError>;
+}
+
+/// Everything that can go wrong generating speech, whichever provider does it.
+///
+/// Like `ElevenLabsError`, the `Display` output is meant to be shown to Discord users as-is.
+#[derive(Debug)]
+pub enum TtsError {
+    ElevenLabs(ElevenLabsError),
+    /// A local engine couldn't be run, or didn't produce usable audio
+    Engine {
+        engine: String,
+        message: String,
+    },
+    /// Another provider's HTTP API failed. Without a status, it couldn't be reached at all.
+    Api {
+        provider: String,
+        status: Option<reqwest::StatusCode>,
+        message: String,
+    },
+    /// No provider with this ID is set up
+    UnknownProvider {
+        provider_id: String,
+    },
+}
+
+impl std::fmt::Display for TtsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ElevenLabs(e) => write!(f, "{}", e),
+            Self::Engine { engine, message } => write!(f, "{} failed: {}", engine, message),
+            Self::Api {
+                provider,
+                status: Some(status),
+                message,
+            } => write!(
+                f,
+                "{} returned HTTP {}: {}",
+                provider,
+                status.as_u16(),
+                message
+            ),
+            Self::Api {
+                provider,
+                status: None,
+                message,
+            } => write!(f, "Couldn't reach {}: {}", provider, message),
+            Self::UnknownProvider { provider_id } => {
+                write!(f, "The \"{}\" speech provider isn't set up", provider_id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TtsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::ElevenLabs(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl TtsError {
+    /// Whether the provider itself is the problem (quota, outage, rate limits, a bad key), rather than the
+    /// request, so another provider might manage it
+    pub fn is_outage(&self) -> bool {
+        match self {
+            Self::ElevenLabs(e) => match e {
+                ElevenLabsError::Transport(_)
+                | ElevenLabsError::WebSocket(_)
+                | ElevenLabsError::InvalidApiKey { .. }
+                | ElevenLabsError::QuotaExceeded { .. }
+                | ElevenLabsError::RateLimited { .. } => true,
+                ElevenLabsError::Api { status, .. } => status.is_server_error(),
+                _ => false,
+            },
+            Self::Engine { .. } | Self::UnknownProvider { .. } => true,
+            Self::Api { status: None, .. } => true,
+            Self::Api {
+                status: Some(status),
+                ..
+            } => {
+                status.is_server_error()
+                    || *status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                    || *status == reqwest::StatusCode::UNAUTHORIZED
+            }
+        }
+    }
+}
+
+impl From<ElevenLabsError> for TtsError {
+    fn from(e: ElevenLabsError) -> Self {
+        Self::ElevenLabs(e)
+    }
+}
+
+/// Every provider the bot can speak with
+#[derive(Default)]
+pub struct ProviderRegistry {
+    providers: Vec<Arc<dyn TtsProvider>>,
+}
+
+impl ProviderRegistry {
+    pub fn add(&mut self, provider: Arc<dyn TtsProvider>) {
+        self.providers.retain(|p| p.id() != provider.id());
+        self.providers.push(provider);
+    }
+
+    pub fn all(&self) -> impl Iterator<Item = &Arc<dyn TtsProvider>> {
+        self.providers.iter()
+    }
+
+    pub fn get(&self, provider_id: &str) -> Result<&Arc<dyn TtsProvider>, TtsError> {
+        self.providers
+            .iter()
+            .find(|p| p.id() == provider_id)
+            .ok_or_else(|| TtsError::UnknownProvider {
+                provider_id: provider_id.to_string(),
+            })
+    }
+}
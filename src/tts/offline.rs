@@ -0,0 +1,531 @@
+use std::path::PathBuf;
+use std::process::Stdio;
+
+use bytes::Bytes;
+use futures::StreamExt;
+use log::{debug, error, info};
+use serenity::async_trait;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::{Child, ChildStdout, Command};
+use tokio::task::JoinHandle;
+
+use crate::elevenlabs::media::{ALL_OUTPUT_FORMATS, MediaFormat, OutputFormat};
+use crate::tts::{
+    ProviderUsage, ProviderVoice, SpeechRequest, SpeechStream, TtsError, TtsProvider,
+};
+
+pub const OFFLINE_PROVIDER_ID: &str = "offline";
+
+const ENGINE_ENV: &str = "OFFLINE_TTS_ENGINE";
+const COMMAND_ENV: &str = "OFFLINE_TTS_COMMAND";
+const MODEL_DIR_ENV: &str = "OFFLINE_TTS_MODEL_DIR";
+const VOICES_ENV: &str = "OFFLINE_TTS_VOICES";
+
+const ESPEAK_DEFAULT_VOICE: &str = "en-us";
+// espeak-ng's default speaking rate, in words per minute
+const ESPEAK_DEFAULT_WPM: f32 = 175.0;
+// What piper models are trained at unless their config says otherwise
+const PIPER_DEFAULT_SAMPLE_RATE: i32 = 22050;
+// Plenty for a WAV header, even with extra chunks before the samples
+const MAX_WAV_HEADER_LEN: usize = 4096;
+const READ_CHUNK_SIZE: usize = 16 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OfflineEngine {
+    /// Robotic, but tiny and speaks almost any language
+    EspeakNg,
+    /// Neural voices that sound far more natural, one model file per voice
+    Piper,
+}
+
+impl OfflineEngine {
+    fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "espeak-ng" | "espeak" => Some(OfflineEngine::EspeakNg),
+            "piper" => Some(OfflineEngine::Piper),
+            _ => None,
+        }
+    }
+
+    fn get_default_command(&self) -> &'static str {
+        match self {
+            OfflineEngine::EspeakNg => "espeak-ng",
+            OfflineEngine::Piper => "piper",
+        }
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            OfflineEngine::EspeakNg => "eSpeak NG",
+            OfflineEngine::Piper => "Piper",
+        }
+    }
+}
+
+/// Speaks with a TTS engine installed next to the bot, so it keeps working without an API key or credits
+pub struct OfflineProvider {
+    engine: OfflineEngine,
+    command: String,
+    /// Where piper's voices are, as `<voice>.onnx` models with a `<voice>.onnx.json` config next to each
+    model_dir: PathBuf,
+}
+
+impl OfflineProvider {
+    /// Set up from `OFFLINE_TTS_ENGINE` (espeak-ng or piper), `OFFLINE_TTS_COMMAND` to run something other than
+    /// the engine's usual binary, and `OFFLINE_TTS_MODEL_DIR` for piper. None if there's no engine configured.
+    pub fn from_env() -> Option<Self> {
+        let engine_name = std::env::var(ENGINE_ENV).ok()?;
+        let Some(engine) = OfflineEngine::parse(&engine_name) else {
+            error!(
+                "Unknown offline TTS engine {}={}, valid engines are espeak-ng and piper",
+                ENGINE_ENV, engine_name
+            );
+            return None;
+        };
+        let command =
+            std::env::var(COMMAND_ENV).unwrap_or_else(|_| engine.get_default_command().to_string());
+        let model_dir = PathBuf::from(std::env::var(MODEL_DIR_ENV).unwrap_or_else(|_| ".".into()));
+        info!(engine = engine.get_name(), command = command.as_str(); "Using offline TTS engine");
+        Some(Self {
+            engine,
+            command,
+            model_dir,
+        })
+    }
+
+    /// The voices to offer in commands, from `OFFLINE_TTS_VOICES` as comma separated "Name=voice" or just "voice".
+    /// Without it, espeak-ng offers American English and piper every model it has.
+    pub async fn get_configured_voices(&self) -> Vec<ProviderVoice> {
+        if let Ok(env_value) = std::env::var(VOICES_ENV) {
+            return env_value
+                .split(',')
+                .map(str::trim)
+                .filter(|entry| !entry.is_empty())
+                .map(|entry| {
+                    let (name, voice_id) = entry.split_once('=').unwrap_or((entry, entry));
+                    make_voice(voice_id.trim(), name.trim())
+                })
+                .collect();
+        }
+        match self.engine {
+            OfflineEngine::EspeakNg => vec![make_voice(ESPEAK_DEFAULT_VOICE, "eSpeak")],
+            OfflineEngine::Piper => self.list_voices().await.unwrap_or_else(|e| {
+                error!(error = e.to_string().as_str(); "Failed to list piper voices");
+                Vec::new()
+            }),
+        }
+    }
+
+    fn build_command(&self, request: &SpeechRequest) -> Command {
+        let speed = request
+            .voice_settings
+            .as_ref()
+            .and_then(|s| s.speed)
+            .unwrap_or(1.0);
+        let mut command = Command::new(&self.command);
+        match self.engine {
+            OfflineEngine::EspeakNg => {
+                // Reading the text from stdin means it can never be mistaken for an option
+                command
+                    .args(["-v", &request.voice_id])
+                    .args([
+                        "-s",
+                        &((ESPEAK_DEFAULT_WPM * speed).round() as u32).to_string(),
+                    ])
+                    .args(["-b", "1", "--stdout", "--stdin"]);
+            }
+            OfflineEngine::Piper => {
+                command
+                    .arg("--model")
+                    .arg(self.get_model_path(&request.voice_id))
+                    .args(["--length_scale", &format!("{:.3}", 1.0 / speed)])
+                    .arg("--output-raw");
+            }
+        }
+        command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        command
+    }
+
+    fn spawn(&self, request: &SpeechRequest) -> Result<EngineProcess, TtsError> {
+        let mut child = self.build_command(request).spawn().map_err(|e| {
+            self.error(match e.kind() {
+                std::io::ErrorKind::NotFound => {
+                    format!("couldn't find \"{}\", is it installed?", self.command)
+                }
+                _ => format!("couldn't run \"{}\": {}", self.command, e),
+            })
+        })?;
+
+        // Piper speaks each line separately, with an awkward pause in between
+        let text = match self.engine {
+            OfflineEngine::EspeakNg => request.text.clone(),
+            OfflineEngine::Piper => request.text.replace('\n', " "),
+        };
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        tokio::spawn(async move {
+            // Dropping stdin once it's all written tells the engine there's no more text
+            if let Err(e) = stdin.write_all(text.as_bytes()).await {
+                error!(error = e.to_string().as_str(); "Failed to send text to offline TTS engine");
+            }
+        });
+        let mut stderr = child.stderr.take().expect("stderr is piped");
+        let stderr = tokio::spawn(async move {
+            let mut output = Vec::new();
+            let _ = stderr.read_to_end(&mut output).await;
+            String::from_utf8_lossy(&output).into_owned()
+        });
+        let stdout = child.stdout.take().expect("stdout is piped");
+
+        Ok(EngineProcess {
+            engine: self.engine,
+            child,
+            stdout,
+            stderr,
+        })
+    }
+
+    fn get_model_path(&self, voice_id: &str) -> PathBuf {
+        self.model_dir.join(format!("{}.onnx", voice_id))
+    }
+
+    async fn get_model_sample_rate(&self, voice_id: &str) -> i32 {
+        let mut config_path = self.get_model_path(voice_id).into_os_string();
+        config_path.push(".json");
+        let config = match tokio::fs::read_to_string(&config_path).await {
+            Ok(c) => c,
+            Err(e) => {
+                error!(
+                    voice_id = voice_id, error = e.to_string().as_str();
+                    "Failed to read piper model config, assuming {}Hz", PIPER_DEFAULT_SAMPLE_RATE
+                );
+                return PIPER_DEFAULT_SAMPLE_RATE;
+            }
+        };
+        serde_json::from_str::<serde_json::Value>(&config)
+            .ok()
+            .and_then(|c| c["audio"]["sample_rate"].as_i64())
+            .map(|r| r as i32)
+            .unwrap_or(PIPER_DEFAULT_SAMPLE_RATE)
+    }
+
+    fn error(&self, message: impl Into<String>) -> TtsError {
+        engine_error(self.engine, message)
+    }
+}
+
+#[async_trait]
+impl TtsProvider for OfflineProvider {
+    fn id(&self) -> &str {
+        OFFLINE_PROVIDER_ID
+    }
+
+    fn name(&self) -> &str {
+        self.engine.get_name()
+    }
+
+    async fn stream(&self, request: SpeechRequest) -> Result<SpeechStream<'_>, TtsError> {
+        debug!(
+            voice_id = request.voice_id.as_str(), text = request.text.as_str();
+            "Generating speech with {}", self.engine.get_name()
+        );
+        let mut process = self.spawn(&request)?;
+
+        // espeak-ng only writes WAV, so the samples have to be dug out of it. Piper writes them as they are.
+        let (sample_rate, first_samples) = match self.engine {
+            OfflineEngine::EspeakNg => match read_wav_header(&mut process.stdout).await {
+                Ok(header) => header,
+                // If the engine failed, what it said about it is more useful than the missing header
+                Err(message) => {
+                    process.finish().await?;
+                    return Err(self.error(message));
+                }
+            },
+            OfflineEngine::Piper => (
+                self.get_model_sample_rate(&request.voice_id).await,
+                Vec::new(),
+            ),
+        };
+        let format = get_pcm_format(sample_rate).ok_or_else(|| {
+            self.error(format!(
+                "it speaks at {}Hz, which isn't supported",
+                sample_rate
+            ))
+        })?;
+
+        let first = futures::stream::iter(
+            (!first_samples.is_empty()).then(|| Ok(Bytes::from(first_samples))),
+        );
+        let rest = futures::stream::unfold(Some(process), |process| async move {
+            let mut process = process?;
+            let mut buf = vec![0; READ_CHUNK_SIZE];
+            match process.stdout.read(&mut buf).await {
+                Ok(0) => process.finish().await.err().map(|e| (Err(e), None)),
+                Ok(len) => {
+                    buf.truncate(len);
+                    Some((Ok(Bytes::from(buf)), Some(process)))
+                }
+                Err(e) => Some((
+                    Err(engine_error(
+                        process.engine,
+                        format!("couldn't read its audio: {}", e),
+                    )),
+                    None,
+                )),
+            }
+        });
+
+        Ok(SpeechStream {
+            request_id: None,
+            format,
+            audio: Box::pin(first.chain(rest)),
+        })
+    }
+
+    async fn list_voices(&self) -> Result<Vec<ProviderVoice>, TtsError> {
+        match self.engine {
+            OfflineEngine::EspeakNg => {
+                let output = Command::new(&self.command)
+                    .arg("--voices")
+                    .output()
+                    .await
+                    .map_err(|e| self.error(format!("couldn't run \"{}\": {}", self.command, e)))?;
+                // Columns are: Pty Language Age/Gender VoiceName File Other Languages
+                Ok(String::from_utf8_lossy(&output.stdout)
+                    .lines()
+                    .skip(1)
+                    .filter_map(|line| {
+                        let columns = line.split_whitespace().collect::<Vec<_>>();
+                        let (language, name) = (columns.get(1)?, columns.get(3)?);
+                        Some(make_voice(language, &name.replace('_', " ")))
+                    })
+                    .collect())
+            }
+            OfflineEngine::Piper => {
+                let mut entries = tokio::fs::read_dir(&self.model_dir).await.map_err(|e| {
+                    self.error(format!(
+                        "couldn't read the models in {}: {}",
+                        self.model_dir.display(),
+                        e
+                    ))
+                })?;
+                let mut voices = Vec::new();
+                while let Ok(Some(entry)) = entries.next_entry().await {
+                    let path = entry.path();
+                    if path.extension().is_some_and(|e| e == "onnx")
+                        && let Some(stem) = path.file_stem().and_then(|s| s.to_str())
+                    {
+                        voices.push(make_voice(stem, stem));
+                    }
+                }
+                voices.sort_by(|a, b| a.voice_id.cmp(&b.voice_id));
+                Ok(voices)
+            }
+        }
+    }
+
+    async fn get_usage(&self) -> Result<Option<ProviderUsage>, TtsError> {
+        Ok(None)
+    }
+}
+
+/// A running engine. What it prints about errors is collected on the side, so that can't fill up and stall it.
+struct EngineProcess {
+    engine: OfflineEngine,
+    child: Child,
+    stdout: ChildStdout,
+    stderr: JoinHandle<String>,
+}
+
+impl EngineProcess {
+    /// Waits for the engine to exit, turning a failure into an error with its last words
+    async fn finish(mut self) -> Result<(), TtsError> {
+        let status = self.child.wait().await.map_err(|e| {
+            engine_error(
+                self.engine,
+                format!("couldn't wait for it to finish: {}", e),
+            )
+        })?;
+        if status.success() {
+            return Ok(());
+        }
+        let stderr = self.stderr.await.unwrap_or_default();
+        let reason = stderr
+            .lines()
+            .map(str::trim)
+            .rfind(|l| !l.is_empty())
+            .map(|l| l.to_string())
+            .unwrap_or_else(|| status.to_string());
+        Err(engine_error(self.engine, reason))
+    }
+}
+
+fn engine_error(engine: OfflineEngine, message: impl Into<String>) -> TtsError {
+    TtsError::Engine {
+        engine: engine.get_name().to_string(),
+        message: message.into(),
+    }
+}
+
+fn make_voice(voice_id: &str, name: &str) -> ProviderVoice {
+    ProviderVoice {
+        provider_id: OFFLINE_PROVIDER_ID.to_string(),
+        voice_id: voice_id.to_string(),
+        name: name.to_string(),
+        description: None,
+    }
+}
+
+fn get_pcm_format(sample_rate: i32) -> Option<&'static OutputFormat> {
+    ALL_OUTPUT_FORMATS
+        .iter()
+        .find(|f| *f.get_format() == MediaFormat::Pcm && f.get_sample_rate() == sample_rate)
+        .copied()
+}
+
+/// Reads up to the start of the samples, returning the sample rate and any samples that were read along with it
+async fn read_wav_header(stdout: &mut ChildStdout) -> Result<(i32, Vec<u8>), String> {
+    let mut header = Vec::new();
+    loop {
+        if let Some((sample_rate, data_start)) = parse_wav_header(&header)? {
+            return Ok((sample_rate, header.split_off(data_start)));
+        }
+        if header.len() >= MAX_WAV_HEADER_LEN {
+            return Err("its output has no audio in it".to_string());
+        }
+        let mut buf = [0; 1024];
+        let len = stdout
+            .read(&mut buf)
+            .await
+            .map_err(|e| format!("couldn't read its audio: {}", e))?;
+        if len == 0 {
+            return Err("it didn't produce any audio".to_string());
+        }
+        header.extend_from_slice(&buf[..len]);
+    }
+}
+
+/// The sample rate and where the samples start, or None if more of the file is needed to tell.
+/// Only mono 16-bit PCM is accepted, since that's what the raw PCM output formats are.
+fn parse_wav_header(bytes: &[u8]) -> Result<Option<(i32, usize)>, String> {
+    if bytes.len() < 12 {
+        return Ok(None);
+    }
+    if &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err("its output isn't WAV audio".to_string());
+    }
+    let read_u16 = |at: usize| u16::from_le_bytes([bytes[at], bytes[at + 1]]);
+    let read_u32 =
+        |at: usize| u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]);
+
+    let mut sample_rate = None;
+    let mut chunk_start = 12;
+    while chunk_start + 8 <= bytes.len() {
+        let content_start = chunk_start + 8;
+        match &bytes[chunk_start..chunk_start + 4] {
+            b"data" => {
+                return match sample_rate {
+                    Some(rate) => Ok(Some((rate, content_start))),
+                    None => Err("its WAV output doesn't say what format it's in".to_string()),
+                };
+            }
+            b"fmt " => {
+                if content_start + 16 > bytes.len() {
+                    return Ok(None);
+                }
+                let format_tag = read_u16(content_start);
+                let channels = read_u16(content_start + 2);
+                let bits_per_sample = read_u16(content_start + 14);
+                if format_tag != 1 || channels != 1 || bits_per_sample != 16 {
+                    return Err(format!(
+                        "its output is {} channel {}-bit audio, only mono 16-bit PCM is supported",
+                        channels, bits_per_sample
+                    ));
+                }
+                sample_rate = Some(read_u32(content_start + 4) as i32);
+            }
+            _ => {}
+        }
+        // Chunks are padded to an even length
+        let len = read_u32(chunk_start + 4) as usize;
+        chunk_start = content_start.saturating_add(len + len % 2);
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8; 4], content: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend_from_slice(&(content.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(content);
+        if content.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn fmt(channels: u16, sample_rate: u32, bits_per_sample: u16) -> Vec<u8> {
+        let block_align = channels * bits_per_sample / 8;
+        let mut content = Vec::new();
+        content.extend_from_slice(&1u16.to_le_bytes());
+        content.extend_from_slice(&channels.to_le_bytes());
+        content.extend_from_slice(&sample_rate.to_le_bytes());
+        content.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+        content.extend_from_slice(&block_align.to_le_bytes());
+        content.extend_from_slice(&bits_per_sample.to_le_bytes());
+        chunk(b"fmt ", &content)
+    }
+
+    fn wav(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut wav = b"RIFF\xff\xff\xff\xffWAVE".to_vec();
+        for c in chunks {
+            wav.extend_from_slice(c);
+        }
+        wav
+    }
+
+    #[test]
+    fn finds_the_samples() {
+        let bytes = wav(&[fmt(1, 22050, 16), chunk(b"data", &[1, 2, 3, 4])]);
+        assert_eq!(parse_wav_header(&bytes), Ok(Some((22050, 44))));
+    }
+
+    #[test]
+    fn skips_other_chunks_and_their_padding() {
+        let bytes = wav(&[
+            chunk(b"LIST", b"odd"),
+            fmt(1, 16000, 16),
+            chunk(b"data", &[0, 0]),
+        ]);
+        assert_eq!(parse_wav_header(&bytes), Ok(Some((16000, 56))));
+    }
+
+    #[test]
+    fn waits_for_more_of_the_header() {
+        let bytes = wav(&[fmt(1, 22050, 16), chunk(b"data", &[])]);
+        for len in [0, 11, 12, 20, 35] {
+            assert_eq!(parse_wav_header(&bytes[..len]), Ok(None), "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn rejects_what_it_cant_play() {
+        assert!(parse_wav_header(b"ID3\x04\x00\x00\x00\x00\x00\x00\x00\x00").is_err());
+        let stereo = wav(&[fmt(2, 22050, 16), chunk(b"data", &[])]);
+        assert_eq!(
+            parse_wav_header(&stereo),
+            Err(
+                "its output is 2 channel 16-bit audio, only mono 16-bit PCM is supported"
+                    .to_string()
+            )
+        );
+        let no_format = wav(&[chunk(b"data", &[])]);
+        assert!(parse_wav_header(&no_format).is_err());
+    }
+}
@@ -0,0 +1,326 @@
+use futures::StreamExt;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+
+use crate::elevenlabs::media::{MediaFormat, PCM_24000HZ};
+use crate::tts::{
+    ProviderUsage, ProviderVoice, SpeechRequest, SpeechStream, TtsError, TtsProvider,
+};
+
+pub const OPENAI_PROVIDER_ID: &str = "openai";
+
+const API_BASE_ENV: &str = "OPENAI_TTS_API_BASE";
+const API_KEY_ENV: &str = "OPENAI_TTS_API_KEY";
+const MODEL_ENV: &str = "OPENAI_TTS_MODEL";
+const VOICES_ENV: &str = "OPENAI_TTS_VOICES";
+const NAME_ENV: &str = "OPENAI_TTS_NAME";
+
+const DEFAULT_API_BASE: &str = "https://api.openai.com/";
+const DEFAULT_MODEL: &str = "gpt-4o-mini-tts";
+// OpenAI's own voices, which most self-hosted servers also answer to
+const DEFAULT_VOICES: &[&str] = &[
+    "alloy", "ash", "coral", "echo", "fable", "nova", "onyx", "sage", "shimmer",
+];
+// The speeds the API accepts
+const MIN_SPEED: f32 = 0.25;
+const MAX_SPEED: f32 = 4.0;
+
+#[derive(Debug, Serialize)]
+struct CreateSpeechRequest<'a> {
+    model: &'a str,
+    voice: &'a str,
+    input: &'a str,
+    response_format: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    speed: Option<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: ErrorDetail,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorDetail {
+    message: String,
+}
+
+/// Speaks through anything with an OpenAI style `/v1/audio/speech` endpoint, be it OpenAI itself or a
+/// self-hosted server
+pub struct OpenAiProvider {
+    name: String,
+    api_key: Option<String>,
+    api_base: String,
+    model: String,
+    client: reqwest::Client,
+}
+
+impl OpenAiProvider {
+    /// Set up from `OPENAI_TTS_API_BASE` and/or `OPENAI_TTS_API_KEY`, with `OPENAI_TTS_MODEL` and
+    /// `OPENAI_TTS_NAME` to go with them. None if neither the base URL nor the key is set.
+    pub fn from_env() -> Option<Self> {
+        let api_key = std::env::var(API_KEY_ENV).ok();
+        let api_base = std::env::var(API_BASE_ENV).ok();
+        if api_key.is_none() && api_base.is_none() {
+            return None;
+        }
+        let name = std::env::var(NAME_ENV).unwrap_or_else(|_| match api_base {
+            Some(_) => "OpenAI-compatible server".to_string(),
+            None => "OpenAI".to_string(),
+        });
+        let api_base = api_base.unwrap_or_else(|| DEFAULT_API_BASE.to_string());
+        let model = std::env::var(MODEL_ENV).unwrap_or_else(|_| DEFAULT_MODEL.to_string());
+        Some(Self::new(name, api_base, api_key, model))
+    }
+
+    pub fn new(name: String, mut api_base: String, api_key: Option<String>, model: String) -> Self {
+        // Endpoints are joined onto the base without a separator, so make sure it ends in one
+        if !api_base.ends_with('/') {
+            api_base.push('/');
+        }
+        info!(
+            name = name.as_str(), api_base = api_base.as_str(), model = model.as_str();
+            "Using OpenAI-compatible speech provider"
+        );
+        Self {
+            name,
+            api_key,
+            api_base,
+            model,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// The voices to offer in commands, from `OPENAI_TTS_VOICES` as comma separated "Name=voice" or just "voice".
+    /// Without it, OpenAI's own voices.
+    pub fn get_configured_voices(&self) -> Vec<ProviderVoice> {
+        match std::env::var(VOICES_ENV) {
+            Ok(env_value) => env_value
+                .split(',')
+                .map(str::trim)
+                .filter(|entry| !entry.is_empty())
+                .map(|entry| {
+                    let (name, voice_id) = entry.split_once('=').unwrap_or((entry, entry));
+                    make_voice(voice_id.trim(), name.trim())
+                })
+                .collect(),
+            Err(_) => DEFAULT_VOICES
+                .iter()
+                .map(|voice_id| make_voice(voice_id, &capitalize(voice_id)))
+                .collect(),
+        }
+    }
+
+    fn error(&self, status: Option<reqwest::StatusCode>, message: impl Into<String>) -> TtsError {
+        TtsError::Api {
+            provider: self.name.clone(),
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl TtsProvider for OpenAiProvider {
+    fn id(&self) -> &str {
+        OPENAI_PROVIDER_ID
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn stream(&self, request: SpeechRequest) -> Result<SpeechStream<'_>, TtsError> {
+        // MP3 and Opus come back as asked, everything raw as 24kHz PCM, the only raw format there is
+        let (response_format, format) = match request.format.get_format() {
+            MediaFormat::MP3 => ("mp3", request.format),
+            MediaFormat::Opus => ("opus", request.format),
+            MediaFormat::Pcm | MediaFormat::ULaw | MediaFormat::ALaw => ("pcm", PCM_24000HZ),
+        };
+        let speed = request
+            .voice_settings
+            .as_ref()
+            .and_then(|s| s.speed)
+            .map(|s| s.clamp(MIN_SPEED, MAX_SPEED));
+        let body = CreateSpeechRequest {
+            model: &self.model,
+            voice: &request.voice_id,
+            input: &request.text,
+            response_format,
+            speed,
+        };
+        info!(
+            voice_id = request.voice_id.as_str(), text = request.text.as_str();
+            "Generating voice with {} model {}", self.name, self.model
+        );
+
+        let mut req = self
+            .client
+            .post(format!("{}v1/audio/speech", self.api_base))
+            .json(&body);
+        if let Some(api_key) = &self.api_key {
+            req = req.bearer_auth(api_key);
+        }
+        let resp = req
+            .send()
+            .await
+            .map_err(|e| self.error(None, e.to_string()))?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            let text = resp.text().await.unwrap_or_default();
+            error!(status = status.as_u16(), body = text.as_str(); "Speech request failed");
+            let message = serde_json::from_str::<ErrorResponse>(&text)
+                .map(|e| e.error.message)
+                .unwrap_or_else(|_| {
+                    status
+                        .canonical_reason()
+                        .unwrap_or("Unknown error")
+                        .to_string()
+                });
+            return Err(self.error(Some(status), message));
+        }
+
+        let provider = self.name.clone();
+        Ok(SpeechStream {
+            request_id: None,
+            format,
+            audio: Box::pin(resp.bytes_stream().map(move |chunk| {
+                chunk.map_err(|e| TtsError::Api {
+                    provider: provider.clone(),
+                    status: None,
+                    message: e.to_string(),
+                })
+            })),
+        })
+    }
+
+    /// There's no endpoint for listing voices, so these are just the configured ones
+    async fn list_voices(&self) -> Result<Vec<ProviderVoice>, TtsError> {
+        Ok(self.get_configured_voices())
+    }
+
+    async fn get_usage(&self) -> Result<Option<ProviderUsage>, TtsError> {
+        Ok(None)
+    }
+}
+
+fn make_voice(voice_id: &str, name: &str) -> ProviderVoice {
+    ProviderVoice {
+        provider_id: OPENAI_PROVIDER_ID.to_string(),
+        voice_id: voice_id.to_string(),
+        name: name.to_string(),
+        description: None,
+    }
+}
+
+/// "alloy" as "Alloy"
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elevenlabs::media::{
+        MP3_44100HZ_128KBPS, OPUS_48000HZ_64KBPS, OutputFormat, ULAW_8000HZ,
+    };
+    use crate::elevenlabs::types::{SpeechOptions, VoiceSettings};
+    use crate::testing::start_mock_server;
+
+    // Long enough that the mock's audio length follows the speed
+    const TEXT: &str = "Contestants, the cashout is now open in the central building";
+
+    async fn provider() -> OpenAiProvider {
+        OpenAiProvider::new(
+            "Mock".to_string(),
+            start_mock_server().await,
+            None,
+            "tts-1".to_string(),
+        )
+    }
+
+    fn request(format: &'static OutputFormat, speed: Option<f32>) -> SpeechRequest {
+        SpeechRequest {
+            voice_id: "alloy".to_string(),
+            text: TEXT.to_string(),
+            voice_settings: speed.map(|speed| VoiceSettings {
+                speed: Some(speed),
+                ..Default::default()
+            }),
+            model_id: None,
+            format,
+            options: SpeechOptions::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn asks_for_mp3() {
+        let speech = provider()
+            .await
+            .generate(request(MP3_44100HZ_128KBPS, None))
+            .await
+            .unwrap();
+        assert_eq!(speech.format.to_string(), "mp3_44100_128");
+        assert_eq!(&speech.audio[..2], &[0xFF, 0xFB]);
+    }
+
+    #[tokio::test]
+    async fn gets_raw_formats_as_24khz_pcm() {
+        let speech = provider()
+            .await
+            .generate(request(ULAW_8000HZ, None))
+            .await
+            .unwrap();
+        assert_eq!(speech.format.to_string(), "pcm_24000");
+        assert!(!speech.audio.is_empty());
+    }
+
+    #[tokio::test]
+    async fn passes_speed_on_within_the_api_limits() {
+        let provider = provider().await;
+        let length = |speed| {
+            let provider = &provider;
+            async move {
+                provider
+                    .generate(request(ULAW_8000HZ, speed))
+                    .await
+                    .unwrap()
+                    .audio
+                    .len()
+            }
+        };
+        let normal = length(None).await;
+        assert!(normal.abs_diff(length(Some(2.0)).await * 2) <= 4);
+        // The mock rejects speeds outside 0.25 to 4, like the real API
+        assert!(normal.abs_diff(length(Some(10.0)).await * 4) <= 8);
+    }
+
+    #[tokio::test]
+    async fn reports_api_errors() {
+        let Err(e) = provider()
+            .await
+            .generate(request(OPUS_48000HZ_64KBPS, None))
+            .await
+        else {
+            panic!("the mock can't produce opus");
+        };
+        match e {
+            TtsError::Api {
+                provider,
+                status,
+                message,
+            } => {
+                assert_eq!(provider, "Mock");
+                assert_eq!(status, Some(reqwest::StatusCode::BAD_REQUEST));
+                assert_eq!(message, "The mock can't produce opus, use mp3 or pcm");
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+}
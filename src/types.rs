@@ -1,10 +1,19 @@
-use crate::elevenlabs::ElevenLabs;
+use crate::elevenlabs::{ElevenLabs, models::ModelRegistry};
+use crate::pronunciation_registry::PronunciationRegistry;
+use crate::soundboard::{RecentClips, Soundboard};
+use crate::speech_cache::SpeechCache;
+use crate::speech_stitching::SpeechStitcher;
+use crate::tts::{ProviderRegistry, fallback::FallbackChain};
+use crate::voice_registry::VoiceRegistry;
 
 pub type Error = Box<dyn std::error::Error + Send + Sync>;
 pub type Context<'a> = poise::Context<'a, Data, Error>;
+pub type ApplicationContext<'a> = poise::ApplicationContext<'a, Data, Error>;
 
 pub const DISCORD_TOKEN_ENV: &str = "DISCORD_TOKEN";
 pub const ELEVENLABS_TOKEN_ENV: &str = "ELEVENLABS_TOKEN";
+pub const ELEVENLABS_BACKUP_TOKEN_ENV: &str = "ELEVENLABS_BACKUP_TOKEN";
+pub const ELEVENLABS_API_BASE_ENV: &str = "ELEVENLABS_API_BASE";
 
 pub struct HttpKey;
 
@@ -13,5 +22,16 @@ impl ::serenity::prelude::TypeMapKey for HttpKey {
 }
 
 pub struct Data {
-    pub client: ElevenLabs,
+    pub client: std::sync::Arc<ElevenLabs>,
+    /// Everything that can speak, ElevenLabs included
+    pub providers: ProviderRegistry,
+    /// Where to turn when a voice's provider is out of quota or down
+    pub fallback: FallbackChain,
+    pub models: ModelRegistry,
+    pub voices: tokio::sync::RwLock<VoiceRegistry>,
+    pub pronunciations: tokio::sync::RwLock<PronunciationRegistry>,
+    pub stitcher: SpeechStitcher,
+    pub cache: SpeechCache,
+    pub soundboard: Soundboard,
+    pub recent_clips: RecentClips,
 } // User data, which is stored and accessible in all command invocations
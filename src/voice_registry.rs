@@ -0,0 +1,178 @@
+use log::{error, info, kv::ToValue};
+use poise::ChoiceParameter;
+
+use crate::elevenlabs::{
+    ElevenLabs,
+    types::{
+        ALL_KNOWN_VOICES, KnownVoice, SpeechSpeed, VoiceCategory, VoiceListFilter, VoiceSettings,
+    },
+};
+use crate::tts::{ProviderVoice, elevenlabs::ELEVENLABS_PROVIDER_ID};
+
+/// A voice cloned into the ElevenLabs account, e.g. with `/clone_voice`
+#[derive(Debug, Clone)]
+pub struct CustomVoice {
+    pub voice_id: String,
+    pub name: String,
+}
+
+/// Any voice the bot can speak with
+#[derive(Debug, Clone)]
+pub enum BotVoice {
+    Known(KnownVoice),
+    Custom(CustomVoice),
+    /// A voice from a provider other than ElevenLabs, e.g. the offline engine
+    Provider(ProviderVoice),
+}
+
+impl BotVoice {
+    pub fn get_id(&self) -> String {
+        match self {
+            BotVoice::Known(voice) => voice.get_id(),
+            BotVoice::Custom(voice) => voice.voice_id.clone(),
+            BotVoice::Provider(voice) => voice.voice_id.clone(),
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        match self {
+            BotVoice::Known(voice) => voice.name(),
+            BotVoice::Custom(voice) => &voice.name,
+            BotVoice::Provider(voice) => &voice.name,
+        }
+    }
+
+    pub fn is_custom(&self) -> bool {
+        matches!(self, BotVoice::Custom(_))
+    }
+
+    /// Which provider speaks with this voice
+    pub fn get_provider_id(&self) -> &str {
+        match self {
+            BotVoice::Known(_) | BotVoice::Custom(_) => ELEVENLABS_PROVIDER_ID,
+            BotVoice::Provider(voice) => &voice.provider_id,
+        }
+    }
+
+    pub fn is_elevenlabs(&self) -> bool {
+        self.get_provider_id() == ELEVENLABS_PROVIDER_ID
+    }
+
+    pub fn get_default_voice_settings(&self) -> VoiceSettings {
+        match self {
+            BotVoice::Known(voice) => voice.get_default_voice_settings(),
+            BotVoice::Custom(_) | BotVoice::Provider(_) => VoiceSettings {
+                speed: Some(self.get_speed(None)),
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn get_speed(&self, speed: Option<SpeechSpeed>) -> f32 {
+        match self {
+            BotVoice::Known(voice) => voice.get_speed(speed),
+            // We don't know anything about how cloned voices sound, so stick to the API's range
+            BotVoice::Custom(_) | BotVoice::Provider(_) => {
+                match speed.unwrap_or(SpeechSpeed::Normal) {
+                    SpeechSpeed::Slow => 0.8,
+                    SpeechSpeed::Normal => 1.0,
+                    SpeechSpeed::Fast => 1.2,
+                }
+            }
+        }
+    }
+}
+
+impl ToValue for BotVoice {
+    fn to_value(&self) -> log::kv::Value<'_> {
+        self.get_name().to_value()
+    }
+}
+
+/// The built-in voices plus any cloned ones, which can change while the bot is running, and those of other
+/// providers
+#[derive(Debug, Default)]
+pub struct VoiceRegistry {
+    custom: Vec<CustomVoice>,
+    provider_voices: Vec<ProviderVoice>,
+}
+
+impl VoiceRegistry {
+    pub fn new(custom: Vec<CustomVoice>) -> Self {
+        Self {
+            custom,
+            provider_voices: Vec::new(),
+        }
+    }
+
+    pub fn with_provider_voices(mut self, voices: Vec<ProviderVoice>) -> Self {
+        info!(count = voices.len(); "Added voices from other providers");
+        self.provider_voices.extend(voices);
+        self
+    }
+
+    /// Finds the cloned voices in the account. If that fails, only the built-in voices are available.
+    pub async fn load(client: &ElevenLabs) -> Self {
+        let filter = VoiceListFilter {
+            category: Some(VoiceCategory::Cloned),
+            ..Default::default()
+        };
+        match client.get_voice_list(&filter).await {
+            Ok(voices) => {
+                let custom = voices
+                    .into_iter()
+                    .filter(|v| !ALL_KNOWN_VOICES.iter().any(|k| k.get_id() == v.voice_id))
+                    .map(|v| CustomVoice {
+                        voice_id: v.voice_id,
+                        name: v.name,
+                    })
+                    .collect::<Vec<_>>();
+                info!(count = custom.len(); "Loaded cloned voices");
+                Self::new(custom)
+            }
+            Err(e) => {
+                error!(error = e.to_string().as_str(); "Failed to load cloned voices, only built-in voices are available");
+                Self::default()
+            }
+        }
+    }
+
+    pub fn all(&self) -> impl Iterator<Item = BotVoice> {
+        ALL_KNOWN_VOICES
+            .iter()
+            .map(|v| BotVoice::Known(*v))
+            .chain(self.custom.iter().cloned().map(BotVoice::Custom))
+            .chain(self.provider_voices.iter().cloned().map(BotVoice::Provider))
+    }
+
+    /// Looks a voice up by its ID or (case insensitive) name
+    pub fn get(&self, name_or_id: &str) -> Option<BotVoice> {
+        let name_or_id = name_or_id.trim();
+        self.all()
+            .find(|v| v.get_id() == name_or_id || v.get_name().eq_ignore_ascii_case(name_or_id))
+    }
+
+    /// Like `get`, but with an error that's meant to be shown to the user
+    pub fn resolve(&self, name_or_id: &str) -> Result<BotVoice, String> {
+        self.get(name_or_id).ok_or_else(|| {
+            format!(
+                "Unknown voice \"{}\". Valid voices are: {}",
+                name_or_id,
+                self.all()
+                    .map(|v| v.get_name().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })
+    }
+
+    pub fn add(&mut self, voice: CustomVoice) {
+        self.custom.retain(|v| v.voice_id != voice.voice_id);
+        self.custom.push(voice);
+    }
+
+    pub fn remove(&mut self, voice_id: &str) -> Option<CustomVoice> {
+        let index = self.custom.iter().position(|v| v.voice_id == voice_id)?;
+        Some(self.custom.remove(index))
+    }
+}